@@ -0,0 +1,567 @@
+/// Tolerance estimation from a quick calibration search.
+///
+/// Every n-th spectrum of the selected mzML files is copied into a temporary file and
+/// searched with wide tolerances. The mass errors of confident target PSMs (and of their
+/// annotated fragments) are then summarized with median and MAD, which are robust
+/// against the random matches still left at wide tolerances.
+use crate::plot::{Histogram, Marker};
+use crate::results;
+use crate::stats;
+use crate::{Config, ToleranceConfig};
+use eframe::egui;
+use sage_cli::{input::Input, runner::Runner};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const NEUTRON: f64 = 1.003_354_8;
+/// Search window of the calibration search.
+const WIDE_PPM: f32 = 50.0;
+const WIDE_DA: f32 = 0.6;
+const Q_VALUE: f64 = 0.01;
+const MIN_PSMS: usize = 30;
+/// Proposed tolerances span this many (robust) standard deviations around the median.
+const WIDTH_SIGMAS: f64 = 4.0;
+
+/// Median and robust standard deviation (scaled MAD) of a set of errors.
+#[derive(Debug, Clone, Copy)]
+pub struct Fit {
+    pub median: f64,
+    pub sigma: f64,
+    pub n: usize,
+}
+
+impl Fit {
+    fn new(values: &[f64]) -> Option<Self> {
+        if values.len() < MIN_PSMS {
+            return None;
+        }
+        let center = stats::median(values)?;
+        let deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+        let mad = stats::median(&deviations)?;
+        Some(Self {
+            median: center,
+            sigma: 1.4826 * mad,
+            n: values.len(),
+        })
+    }
+
+    /// `median ± WIDTH_SIGMAS * sigma`, rounded outwards to `step`.
+    fn bounds(&self, step: f64, min_half_width: f64, limit: f64) -> (f32, f32) {
+        let half = (WIDTH_SIGMAS * self.sigma).max(min_half_width);
+        let lo = ((self.median - half) / step).floor() * step;
+        let hi = ((self.median + half) / step).ceil() * step;
+        (lo.max(-limit) as f32, hi.min(limit) as f32)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub spectra: usize,
+    pub psms: usize,
+    pub precursor: Fit,
+    pub fragment: Fit,
+    precursor_hist: Histogram,
+    fragment_hist: Histogram,
+    pub precursor_tol: ToleranceConfig,
+    pub fragment_tol: ToleranceConfig,
+}
+
+/// What the calibration thread sends back: the estimate and, if the temporary
+/// files could not be removed, why.
+type Outcome = (Result<Estimate, String>, Option<String>);
+
+pub struct Calibration {
+    pub stride: usize,
+    pub max_spectra: usize,
+    pub max_files: usize,
+    receiver: Option<Receiver<Outcome>>,
+    started: Option<Instant>,
+    result: Option<Result<Estimate, String>>,
+    cleanup: Option<String>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            stride: 10,
+            max_spectra: 5000,
+            max_files: 3,
+            receiver: None,
+            started: None,
+            result: None,
+            cleanup: None,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    pub fn start(&mut self, config: &Config) {
+        let config = config.clone();
+        let (stride, max_spectra, max_files) = (self.stride, self.max_spectra, self.max_files);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(estimate(config, stride, max_spectra, max_files));
+        });
+        self.receiver = Some(receiver);
+        self.started = Some(Instant::now());
+        self.result = None;
+        self.cleanup = None;
+    }
+
+    /// Pick up the result of a finished calibration search.
+    pub fn poll(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.receiver else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok((result, cleanup)) => {
+                self.result = Some(result);
+                self.cleanup = cleanup;
+                self.receiver = None;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.result = Some(Err("Calibration search stopped unexpectedly".to_string()));
+                self.receiver = None;
+            }
+            Err(mpsc::TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(250)),
+        }
+    }
+
+    /// Returns the proposed (precursor, fragment) tolerances once the user accepts them.
+    /// No calibration can be started while `search_running`.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        config: &Config,
+        search_running: bool,
+    ) -> Option<(ToleranceConfig, ToleranceConfig)> {
+        let mut accepted = None;
+        let mut discard = false;
+        ui.label("Runs a quick wide-tolerance search on a subsample of the selected mzML files and proposes tolerances from the observed mass errors.");
+        ui.add_enabled_ui(!self.is_running(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Use every");
+                ui.add(egui::DragValue::new(&mut self.stride).range(1..=1000));
+                ui.label("th spectrum, at most");
+                ui.add(egui::DragValue::new(&mut self.max_spectra).range(500..=100_000));
+                ui.label("spectra from at most");
+                ui.add(egui::DragValue::new(&mut self.max_files).range(1..=20));
+                ui.label("files");
+            });
+        });
+
+        ui.horizontal(|ui| {
+            if self.is_running() {
+                ui.spinner();
+                let elapsed = self.started.map(|s| s.elapsed()).unwrap_or_default();
+                ui.label(format!(
+                    "Calibration search running ({})",
+                    crate::format_duration(elapsed)
+                ));
+            } else if ui
+                .add_enabled(!search_running, egui::Button::new("Estimate Tolerances"))
+                .on_disabled_hover_text("Wait for the running search to finish")
+                .clicked()
+            {
+                self.start(config);
+            }
+        });
+        if let Some(cleanup) = &self.cleanup {
+            ui.colored_label(egui::Color32::from_rgb(230, 160, 0), cleanup);
+        }
+
+        match &self.result {
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, format!("Estimation failed: {}", e));
+            }
+            Some(Ok(estimate)) => {
+                let source = match estimate.spectra {
+                    0 => "one complete run".to_string(),
+                    n => format!("{} spectra", n),
+                };
+                ui.label(format!(
+                    "{} confident PSMs (1% FDR) from {}",
+                    estimate.psms, source
+                ));
+                show_fit(
+                    ui,
+                    "Precursor",
+                    &estimate.precursor,
+                    &estimate.precursor_hist,
+                    &estimate.precursor_tol,
+                );
+                show_fit(
+                    ui,
+                    "Fragment",
+                    &estimate.fragment,
+                    &estimate.fragment_hist,
+                    &estimate.fragment_tol,
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Accept Proposed Tolerances").clicked() {
+                        accepted = Some((estimate.precursor_tol, estimate.fragment_tol));
+                    }
+                    if ui.button("Discard").clicked() {
+                        discard = true;
+                    }
+                });
+            }
+            None => {}
+        }
+        if accepted.is_some() || discard {
+            self.result = None;
+        }
+        accepted
+    }
+}
+
+fn show_fit(
+    ui: &mut egui::Ui,
+    name: &str,
+    fit: &Fit,
+    histogram: &Histogram,
+    proposed: &ToleranceConfig,
+) {
+    let (lo, hi, unit) = match *proposed {
+        ToleranceConfig::Ppm(lo, hi) => (lo, hi, "ppm"),
+        ToleranceConfig::Da(lo, hi) => (lo, hi, "Da"),
+    };
+    ui.label(format!(
+        "{}: median {:.4} {}, sigma {:.4} {} over {} matches, proposed {} to {} {}",
+        name, fit.median, unit, fit.sigma, unit, fit.n, lo, hi, unit
+    ));
+    let bound = egui::Color32::from_rgb(220, 60, 60);
+    histogram.show(
+        ui,
+        &format!("{} error ({})", name, unit),
+        &[
+            Marker {
+                x: fit.median,
+                color: egui::Color32::from_rgb(230, 160, 0),
+                label: "median".to_string(),
+            },
+            Marker {
+                x: lo as f64,
+                color: bound,
+                label: String::new(),
+            },
+            Marker {
+                x: hi as f64,
+                color: bound,
+                label: String::new(),
+            },
+        ],
+    );
+}
+
+fn is_spectrum_start(trimmed: &str) -> bool {
+    trimmed.starts_with("<spectrum ") || trimmed.starts_with("<spectrum>")
+}
+
+/// `line` with the value of its `count="..."` attribute replaced.
+fn with_count(line: &str, count: usize) -> String {
+    let Some(start) = line.find("count=\"").map(|i| i + "count=\"".len()) else {
+        return line.to_string();
+    };
+    match line[start..].find('"') {
+        Some(end) => format!("{}{}{}", &line[..start], count, &line[start + end..]),
+        None => line.to_string(),
+    }
+}
+
+/// Copy every `stride`-th spectrum (up to `max_spectra`) of an mzML file.
+///
+/// Works line by line, which is fine for the indented files converters write. The
+/// index is dropped, since its offsets would be wrong; sage does not need it. The
+/// spectra are counted first, so the `spectrumList` count matches what is kept.
+fn subsample(src: &Path, dst: &Path, stride: usize, max_spectra: usize) -> Result<usize, String> {
+    let open = || {
+        File::open(src)
+            .map(BufReader::new)
+            .map_err(|e| e.to_string())
+    };
+    let mut total = 0usize;
+    for line in open()?.lines() {
+        if is_spectrum_start(line.map_err(|e| e.to_string())?.trim_start()) {
+            total += 1;
+        }
+    }
+    let stride = stride.max(1);
+    let expected = total.div_ceil(stride).min(max_spectra);
+
+    let mut writer = BufWriter::new(File::create(dst).map_err(|e| e.to_string())?);
+    // Position within the current stride, the first spectrum of each is kept
+    let (mut phase, mut kept) = (0usize, 0);
+    let (mut in_spectrum, mut keep, mut in_index) = (false, false, false);
+    for line in open()?.lines() {
+        let mut line = line.map_err(|e| e.to_string())?;
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("<indexListOffset") || trimmed.starts_with("<fileChecksum") {
+            continue;
+        }
+        if in_index || trimmed.starts_with("<indexList") {
+            in_index = !trimmed.contains("</indexList>");
+            continue;
+        }
+
+        if trimmed.starts_with("<spectrumList") {
+            line = with_count(&line, expected);
+        } else if is_spectrum_start(trimmed) {
+            in_spectrum = true;
+            keep = phase == 0 && kept < max_spectra;
+            phase = (phase + 1) % stride;
+            if keep {
+                kept += 1;
+            }
+        }
+        let trimmed = line.trim_start();
+        if !in_spectrum || keep {
+            writeln!(writer, "{}", line).map_err(|e| e.to_string())?;
+        }
+        if in_spectrum && trimmed.contains("</spectrum>") {
+            in_spectrum = false;
+        }
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+
+    if kept == 0 {
+        return Err(format!(
+            "No spectra found in {} (is it a single-line mzML?)",
+            src.display()
+        ));
+    }
+    Ok(kept)
+}
+
+fn estimate(config: Config, stride: usize, max_spectra: usize, max_files: usize) -> Outcome {
+    let temp = std::env::temp_dir().join(format!(
+        "sagegui-calibration-{}-{}",
+        std::process::id(),
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    ));
+    if let Err(e) = fs::create_dir_all(&temp) {
+        return (Err(e.to_string()), None);
+    }
+    let result = calibrate(config, &temp, stride, max_spectra, max_files);
+    let cleanup = fs::remove_dir_all(&temp).err().map(|e| {
+        format!(
+            "Could not remove the calibration files in {}: {}",
+            temp.display(),
+            e
+        )
+    });
+    (result, cleanup)
+}
+
+fn calibrate(
+    mut config: Config,
+    temp: &Path,
+    stride: usize,
+    max_spectra: usize,
+    max_files: usize,
+) -> Result<Estimate, String> {
+    // Gzipped files cannot be subsampled line by line, they are used whole
+    let (plain, other): (Vec<PathBuf>, Vec<PathBuf>) =
+        config.mzml_paths.iter().cloned().partition(|p| {
+            p.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("mzml"))
+        });
+
+    let mut spectra = 0;
+    let mut mzml_paths = Vec::new();
+    for (i, path) in plain.iter().take(max_files).enumerate() {
+        let dst = temp.join(format!("subsample_{}.mzML", i));
+        spectra += subsample(path, &dst, stride, max_spectra)?;
+        mzml_paths.push(dst);
+    }
+    let mut dotd_paths = Vec::new();
+    if mzml_paths.is_empty() {
+        // Nothing to subsample, fall back to searching a single run completely
+        match other.first().or(config.dotd_paths.first()) {
+            Some(path) if path.extension().is_some_and(|ext| ext == "d") => {
+                dotd_paths.push(path.clone())
+            }
+            Some(path) => mzml_paths.push(path.clone()),
+            None => return Err("Estimation needs local mzML or .d files".to_string()),
+        }
+    }
+
+    let fragment_da = matches!(config.fragment_tol, ToleranceConfig::Da(..));
+    config.precursor_tol = ToleranceConfig::Ppm(-WIDE_PPM, WIDE_PPM);
+    config.fragment_tol = if fragment_da {
+        ToleranceConfig::Da(-WIDE_DA, WIDE_DA)
+    } else {
+        ToleranceConfig::Ppm(-WIDE_PPM, WIDE_PPM)
+    };
+    config.mzml_paths = mzml_paths;
+    config.dotd_paths = dotd_paths;
+    config.cloud_paths.clear();
+    config.wide_window = false;
+    config.chimera = false;
+    config.quant_enabled = false;
+    config.report_psms = 1;
+    config.annotate_matches = true;
+    config.write_pin = false;
+    config.write_report = false;
+    config.output_directory = temp.display().to_string();
+    config.output_template.clear();
+
+    let parallel = (num_cpus::get() / 2).max(1);
+    let input: Input = config.into();
+    let search = input.build().map_err(|e| e.to_string())?;
+    let runner = Runner::new(search, parallel).map_err(|e| e.to_string())?;
+    runner.run(parallel, false).map_err(|e| e.to_string())?;
+
+    let confident: Vec<results::Psm> = results::read_psms(temp)?
+        .into_iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && psm.spectrum_q <= Q_VALUE)
+        .collect();
+    if confident.len() < MIN_PSMS {
+        return Err(format!(
+            "Only {} confident PSMs, use more spectra (smaller stride) or check the FASTA and modifications",
+            confident.len()
+        ));
+    }
+
+    // Signed error after removing the isotope offset the PSM was matched at
+    let precursor_errors: Vec<f64> = confident
+        .iter()
+        .map(|psm| {
+            let delta = psm.expmass - psm.calcmass;
+            let isotope = (delta / NEUTRON).round();
+            (delta - isotope * NEUTRON) / psm.calcmass * 1e6
+        })
+        .collect();
+
+    let ids: HashSet<u64> = confident.iter().map(|psm| psm.psm_id).collect();
+    let fragment_errors: Vec<f64> = results::read_fragments(temp)?
+        .into_iter()
+        .filter(|f| ids.contains(&f.psm_id) && f.mz_calculated > 0.0)
+        .map(|f| {
+            let delta = f.mz_experimental - f.mz_calculated;
+            if fragment_da {
+                delta
+            } else {
+                delta / f.mz_calculated * 1e6
+            }
+        })
+        .collect();
+
+    let precursor = Fit::new(&precursor_errors).ok_or("Too few precursor mass errors")?;
+    let fragment = Fit::new(&fragment_errors).ok_or("Too few annotated fragments")?;
+
+    let wide_ppm = WIDE_PPM as f64;
+    let (lo, hi) = precursor.bounds(0.5, 2.0, wide_ppm);
+    let precursor_tol = ToleranceConfig::Ppm(lo, hi);
+    let (fragment_tol, fragment_window) = if fragment_da {
+        let (lo, hi) = fragment.bounds(0.005, 0.01, WIDE_DA as f64);
+        (ToleranceConfig::Da(lo, hi), WIDE_DA as f64)
+    } else {
+        let (lo, hi) = fragment.bounds(0.5, 2.0, wide_ppm);
+        (ToleranceConfig::Ppm(lo, hi), wide_ppm)
+    };
+
+    Ok(Estimate {
+        spectra,
+        psms: confident.len(),
+        precursor,
+        fragment,
+        precursor_hist: Histogram::new(&precursor_errors, -wide_ppm, wide_ppm, 100),
+        fragment_hist: Histogram::new(&fragment_errors, -fragment_window, fragment_window, 100),
+        precursor_tol,
+        fragment_tol,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_median_and_mad() {
+        assert!(Fit::new(&[1.0; MIN_PSMS - 1]).is_none());
+
+        // Symmetric around 2 ppm with an outlier that must not move the fit
+        let mut errors: Vec<f64> = (0..40).map(|i| 2.0 + (i % 5) as f64 - 2.0).collect();
+        errors.push(45.0);
+        let fit = Fit::new(&errors).unwrap();
+        assert_eq!(fit.n, 41);
+        assert_eq!(fit.median, 2.0);
+        assert!((fit.sigma - 1.4826).abs() < 1e-9);
+
+        // 2 ± 4 * 1.4826, rounded outwards to 0.5
+        assert_eq!(fit.bounds(0.5, 2.0, 50.0), (-4.0, 8.0));
+        assert_eq!(fit.bounds(0.5, 2.0, 5.0), (-4.0, 5.0));
+        let narrow = Fit {
+            median: 0.0,
+            sigma: 0.1,
+            n: 100,
+        };
+        assert_eq!(narrow.bounds(0.5, 2.0, 50.0), (-2.0, 2.0));
+    }
+
+    fn mzml(spectra: usize) -> String {
+        let mut text = String::from("<indexedmzML>\n  <mzML>\n    <run id=\"r\">\n");
+        text.push_str(&format!(
+            "      <spectrumList count=\"{}\" defaultDataProcessingRef=\"dp\">\n",
+            spectra
+        ));
+        for i in 0..spectra {
+            text.push_str(&format!(
+                "        <spectrum index=\"{}\" id=\"scan={}\">\n          <binaryDataArrayList count=\"2\"/>\n        </spectrum>\n",
+                i,
+                i + 1
+            ));
+        }
+        text.push_str("      </spectrumList>\n    </run>\n  </mzML>\n");
+        text.push_str(
+            "  <indexList count=\"1\">\n    <offset idRef=\"scan=1\">42</offset>\n  </indexList>\n",
+        );
+        text.push_str("  <indexListOffset>1234</indexListOffset>\n</indexedmzML>\n");
+        text
+    }
+
+    #[test]
+    fn subsamples_and_rewrites_the_count() {
+        let dir = std::env::temp_dir().join(format!("sagegui_calibration_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (src, dst) = (dir.join("run.mzML"), dir.join("sub.mzML"));
+        fs::write(&src, mzml(25)).unwrap();
+
+        assert_eq!(subsample(&src, &dst, 10, 100), Ok(3));
+        let text = fs::read_to_string(&dst).unwrap();
+        assert!(text.contains("<spectrumList count=\"3\" defaultDataProcessingRef=\"dp\">"));
+        let ids: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.split(" id=\"scan=").nth(1))
+            .collect();
+        assert_eq!(ids, ["1\">", "11\">", "21\">"]);
+        // Only the kept spectra, and no index with stale offsets
+        assert!(text.contains("<binaryDataArrayList count=\"2\"/>"));
+        assert!(!text.contains("indexList"));
+        assert!(text.trim_end().ends_with("</indexedmzML>"));
+
+        assert_eq!(subsample(&src, &dst, 1, 4), Ok(4));
+        let text = fs::read_to_string(&dst).unwrap();
+        assert!(text.contains("<spectrumList count=\"4\""));
+        assert_eq!(text.matches("</spectrum>").count(), 4);
+
+        fs::write(
+            &src,
+            "<mzML><spectrumList count=\"1\"><spectrum/></spectrumList></mzML>",
+        )
+        .unwrap();
+        assert!(subsample(&src, &dst, 1, 10).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,423 @@
+/// S3 support for inputs and outputs.
+///
+/// sage reads and writes `s3://` URIs through sage-cloudpath, which takes its
+/// credentials from the standard `AWS_*` environment variables. The settings here
+/// start from those variables and only ever reach the object store the GUI builds to
+/// list buckets and check objects. The environment is never written, other threads
+/// read it and secrets should not linger there.
+use eframe::egui;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreExt};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub const S3_SCHEME: &str = "s3://";
+
+pub fn is_s3_uri(path: &str) -> bool {
+    path.starts_with(S3_SCHEME)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Uri {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Uri {
+    pub fn parse(uri: &str) -> Result<Self, String> {
+        let rest = uri
+            .strip_prefix(S3_SCHEME)
+            .ok_or_else(|| format!("'{}' is not an s3:// URI", uri))?;
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(format!("'{}' does not name a bucket", uri));
+        }
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    fn to_uri(&self) -> String {
+        format!("{}{}/{}", S3_SCHEME, self.bucket, self.key)
+    }
+
+    /// The parent "folder" of this key, with a trailing slash (or empty for the root).
+    fn parent_prefix(&self) -> String {
+        let trimmed = self.key.trim_end_matches('/');
+        match trimmed.rfind('/') {
+            Some(idx) => trimmed[..=idx].to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CloudSettings {
+    /// Empty means AWS itself; set it to e.g. `http://localhost:9000` for MinIO.
+    pub endpoint: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub allow_http: bool,
+}
+
+impl CloudSettings {
+    /// Start from whatever the environment already has, so credentials set up for
+    /// the AWS CLI keep working without touching the GUI.
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let endpoint = match var("AWS_ENDPOINT_URL") {
+            e if e.is_empty() => var("AWS_ENDPOINT"),
+            e => e,
+        };
+        let region = match var("AWS_REGION") {
+            r if r.is_empty() => var("AWS_DEFAULT_REGION"),
+            r => r,
+        };
+        Self {
+            allow_http: endpoint.starts_with("http://") || var("AWS_ALLOW_HTTP") == "true",
+            endpoint,
+            region,
+            access_key_id: var("AWS_ACCESS_KEY_ID"),
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY"),
+        }
+    }
+
+    /// Whether the fields still match what sage will read from the environment.
+    fn matches_env(&self) -> bool {
+        let env = Self::from_env();
+        self.endpoint == env.endpoint
+            && self.region == env.region
+            && self.access_key_id == env.access_key_id
+            && self.secret_access_key == env.secret_access_key
+    }
+
+    pub fn update_section(&mut self, ui: &mut egui::Ui) {
+        ui.label("Used for s3:// paths. Leave the endpoint empty for AWS, or point it to a MinIO-compatible server.");
+        egui::Grid::new("cloud_settings_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Endpoint:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.endpoint)
+                        .hint_text("http://localhost:9000"),
+                );
+                ui.end_row();
+
+                ui.label("Region:");
+                ui.add(egui::TextEdit::singleline(&mut self.region).hint_text("us-east-1"));
+                ui.end_row();
+
+                ui.label("Access Key ID:");
+                ui.text_edit_singleline(&mut self.access_key_id);
+                ui.end_row();
+
+                ui.label("Secret Access Key:");
+                ui.add(egui::TextEdit::singleline(&mut self.secret_access_key).password(true));
+                ui.end_row();
+            });
+        ui.checkbox(
+            &mut self.allow_http,
+            "Allow plain HTTP (local test servers)",
+        );
+        if !self.matches_env() {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "These settings are only used for browsing and checking paths. sage itself reads S3 with the AWS_* environment variables sagegui was started with.",
+            );
+        }
+    }
+
+    /// Built from the fields, never from the environment, so it is safe to call from
+    /// background threads. Empty fields keep the builder's defaults.
+    fn store(&self, bucket: &str) -> Result<impl ObjectStore, String> {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(bucket)
+            .with_allow_http(self.allow_http);
+        if !self.endpoint.is_empty() {
+            builder = builder.with_endpoint(&self.endpoint);
+        }
+        if !self.region.is_empty() {
+            builder = builder.with_region(&self.region);
+        }
+        if !self.access_key_id.is_empty() {
+            builder = builder.with_access_key_id(&self.access_key_id);
+        }
+        if !self.secret_access_key.is_empty() {
+            builder = builder.with_secret_access_key(&self.secret_access_key);
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+fn block_on<F: std::future::Future>(fut: F) -> Result<F::Output, String> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    Ok(rt.block_on(fut))
+}
+
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub location: S3Uri,
+    pub prefixes: Vec<String>,
+    pub objects: Vec<(String, u64)>,
+}
+
+pub fn list(settings: &CloudSettings, location: &S3Uri) -> Result<Listing, String> {
+    let store = settings.store(&location.bucket)?;
+    let prefix = location.key.trim_end_matches('/');
+    let prefix = if prefix.is_empty() {
+        None
+    } else {
+        Some(ObjectPath::from(prefix))
+    };
+    let result =
+        block_on(store.list_with_delimiter(prefix.as_ref()))?.map_err(|e| e.to_string())?;
+
+    let mut prefixes: Vec<String> = result
+        .common_prefixes
+        .iter()
+        .map(|p| format!("{}/", p))
+        .collect();
+    let mut objects: Vec<(String, u64)> = result
+        .objects
+        .iter()
+        .map(|o| (o.location.to_string(), o.size))
+        .collect();
+    prefixes.sort();
+    objects.sort();
+
+    Ok(Listing {
+        location: location.clone(),
+        prefixes,
+        objects,
+    })
+}
+
+/// Check that an input object exists. Bruker `.d` "folders" are prefixes, so for those
+/// (or anything that is not a plain object) any key underneath counts.
+pub fn check_exists(settings: &CloudSettings, uri: &str) -> Result<(), String> {
+    let parsed = S3Uri::parse(uri)?;
+    let store = settings.store(&parsed.bucket)?;
+    let key = parsed.key.trim_end_matches('/');
+    let path = ObjectPath::from(key);
+
+    if !parsed.key.ends_with('/') && block_on(store.head(&path))?.is_ok() {
+        return Ok(());
+    }
+    let listing =
+        block_on(store.list_with_delimiter(Some(&path)))?.map_err(|e| format!("{}: {}", uri, e))?;
+    if listing.objects.is_empty() && listing.common_prefixes.is_empty() {
+        return Err(format!("{} does not exist", uri));
+    }
+    Ok(())
+}
+
+/// Output "directories" only need a reachable bucket.
+pub fn check_bucket(settings: &CloudSettings, uri: &str) -> Result<(), String> {
+    let parsed = S3Uri::parse(uri)?;
+    let store = settings.store(&parsed.bucket)?;
+    block_on(store.list_with_delimiter(None))?
+        .map(|_| ())
+        .map_err(|e| format!("Cannot access bucket '{}': {}", parsed.bucket, e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowseTarget {
+    Fasta,
+    Spectra,
+    Output,
+}
+
+/// What the user picked in the browser window.
+pub enum BrowsePick {
+    Fasta(String),
+    Spectra(Vec<String>),
+    Output(String),
+}
+
+#[derive(Default)]
+pub struct S3Browser {
+    target: Option<BrowseTarget>,
+    location: String,
+    listing: Option<Listing>,
+    selected: Vec<String>,
+    pending: Option<Receiver<Result<Listing, String>>>,
+    error: Option<String>,
+}
+
+impl S3Browser {
+    pub fn open(&mut self, target: BrowseTarget, start: &str) {
+        self.target = Some(target);
+        self.selected.clear();
+        if is_s3_uri(start) {
+            self.location = start.to_string();
+        } else if self.location.is_empty() {
+            self.location = S3_SCHEME.to_string();
+        }
+    }
+
+    fn request_listing(&mut self, settings: &CloudSettings) {
+        let location = match S3Uri::parse(&self.location) {
+            Ok(loc) => loc,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        let settings = settings.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(list(&settings, &location));
+        });
+        self.pending = Some(receiver);
+        self.error = None;
+    }
+
+    fn poll(&mut self) {
+        if let Some(receiver) = &self.pending {
+            match receiver.try_recv() {
+                Ok(Ok(listing)) => {
+                    self.listing = Some(listing);
+                    self.pending = None;
+                }
+                Ok(Err(e)) => {
+                    self.error = Some(e);
+                    self.pending = None;
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => self.pending = None,
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, settings: &CloudSettings) -> Option<BrowsePick> {
+        let target = self.target?;
+        self.poll();
+
+        let mut open = true;
+        let mut pick = None;
+        let mut navigate_to = None;
+
+        egui::Window::new("Browse S3")
+            .open(&mut open)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Location:");
+                    ui.text_edit_singleline(&mut self.location);
+                    if ui.button("List").clicked() {
+                        navigate_to = Some(self.location.clone());
+                    }
+                });
+                if self.pending.is_some() {
+                    ui.spinner();
+                    ctx.request_repaint();
+                }
+                if let Some(err) = &self.error {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+
+                let Some(listing) = &self.listing else {
+                    return;
+                };
+                let bucket = &listing.location.bucket;
+                let uri = |key: &str| {
+                    S3Uri {
+                        bucket: bucket.clone(),
+                        key: key.to_string(),
+                    }
+                    .to_uri()
+                };
+
+                if ui.button("⬆ Up").clicked() {
+                    navigate_to = Some(uri(&listing.location.parent_prefix()));
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for prefix in &listing.prefixes {
+                            ui.horizontal(|ui| {
+                                // Bruker .d acquisitions are folders, so they are picked, not opened.
+                                let is_dotd = prefix.trim_end_matches('/').ends_with(".d");
+                                if target == BrowseTarget::Spectra && is_dotd {
+                                    let full = uri(prefix.trim_end_matches('/'));
+                                    let mut checked = self.selected.contains(&full);
+                                    if ui.checkbox(&mut checked, prefix).changed() {
+                                        toggle(&mut self.selected, full, checked);
+                                    }
+                                } else if ui
+                                    .selectable_label(false, format!("📁 {}", prefix))
+                                    .clicked()
+                                {
+                                    navigate_to = Some(uri(prefix));
+                                }
+                            });
+                        }
+                        for (key, size) in &listing.objects {
+                            let full = uri(key);
+                            let label = format!("{} ({:.1} MB)", key, *size as f64 / 1e6);
+                            match target {
+                                BrowseTarget::Fasta => {
+                                    if ui.selectable_label(false, label).clicked() {
+                                        pick = Some(BrowsePick::Fasta(full));
+                                    }
+                                }
+                                BrowseTarget::Spectra => {
+                                    let mut checked = self.selected.contains(&full);
+                                    if ui.checkbox(&mut checked, label).changed() {
+                                        toggle(&mut self.selected, full, checked);
+                                    }
+                                }
+                                BrowseTarget::Output => {
+                                    ui.weak(label);
+                                }
+                            }
+                        }
+                    });
+
+                ui.separator();
+                match target {
+                    BrowseTarget::Spectra => {
+                        let n = self.selected.len();
+                        if ui
+                            .add_enabled(n > 0, egui::Button::new(format!("Add {} selected", n)))
+                            .clicked()
+                        {
+                            pick = Some(BrowsePick::Spectra(self.selected.clone()));
+                        }
+                    }
+                    BrowseTarget::Output => {
+                        if ui.button("Use this location as output").clicked() {
+                            pick = Some(BrowsePick::Output(uri(&listing.location.key)));
+                        }
+                    }
+                    BrowseTarget::Fasta => {}
+                }
+            });
+
+        if let Some(location) = navigate_to {
+            self.location = location;
+            self.request_listing(settings);
+        }
+        if !open || pick.is_some() {
+            self.target = None;
+        }
+        pick
+    }
+}
+
+fn toggle(selected: &mut Vec<String>, item: String, checked: bool) {
+    if checked {
+        if !selected.contains(&item) {
+            selected.push(item);
+        }
+    } else {
+        selected.retain(|s| s != &item);
+    }
+}
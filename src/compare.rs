@@ -0,0 +1,407 @@
+/// Side by side comparison of two result directories, e.g. before and after a
+/// parameter change or a sage upgrade.
+use crate::plot::{self, Point};
+use crate::results::{self, Psm};
+use eframe::egui;
+use rfd::FileDialog;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+const Q_VALUE: f64 = 0.01;
+/// Scatter plots get slow and unreadable beyond this, the rest is thinned out.
+const MAX_POINTS: usize = 20_000;
+
+/// Search settings of a run, as resolved by sage (defaults filled in) in the
+/// `results.json` it writes next to the results.
+pub fn settings(dir: &Path) -> Option<Value> {
+    let json = fs::read_to_string(dir.join("results.json")).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Dotted path to every leaf. Arrays are compared as a whole.
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = match prefix.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}.{}", prefix, key),
+                };
+                flatten(&path, value, out);
+            }
+        }
+        other => {
+            out.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// (path, value in A, value in B) for every setting that differs.
+fn diff(a: &Value, b: &Value) -> Vec<(String, String, String)> {
+    let (mut left, mut right) = (BTreeMap::new(), BTreeMap::new());
+    flatten("", a, &mut left);
+    flatten("", b, &mut right);
+    let keys: BTreeSet<&String> = left.keys().chain(right.keys()).collect();
+    let missing = "(not set)".to_string();
+    keys.into_iter()
+        .filter(|key| left.get(*key) != right.get(*key))
+        .map(|key| {
+            (
+                key.clone(),
+                left.get(key).unwrap_or(&missing).clone(),
+                right.get(key).unwrap_or(&missing).clone(),
+            )
+        })
+        .collect()
+}
+
+struct Overlap {
+    only_a: Vec<String>,
+    both: usize,
+    only_b: Vec<String>,
+}
+
+impl Overlap {
+    fn new(a: BTreeSet<String>, b: BTreeSet<String>) -> Self {
+        Self {
+            only_a: a.difference(&b).cloned().collect(),
+            both: a.intersection(&b).count(),
+            only_b: b.difference(&a).cloned().collect(),
+        }
+    }
+}
+
+fn confident(psms: &[Psm], q: impl Fn(&Psm) -> f64, id: impl Fn(&Psm) -> &str) -> BTreeSet<String> {
+    psms.iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && q(psm) <= Q_VALUE)
+        .map(|psm| id(psm).to_string())
+        .collect()
+}
+
+/// The same spectrum, as scored in both runs.
+struct SharedSpectrum {
+    hyperscore: (f64, f64),
+    discriminant: (f64, f64),
+    same_peptide: bool,
+}
+
+struct Comparison {
+    settings: Result<Vec<(String, String, String)>, String>,
+    psms: (usize, usize),
+    peptides: Overlap,
+    proteins: Overlap,
+    shared: Vec<SharedSpectrum>,
+}
+
+fn compare(a: &Path, b: &Path) -> Result<Comparison, String> {
+    let psms_a = results::read_psms(a)?;
+    let psms_b = results::read_psms(b)?;
+
+    let settings = match (settings(a), settings(b)) {
+        (Some(a), Some(b)) => Ok(diff(&a, &b)),
+        _ => Err("Search settings are only available for runs with a results.json".to_string()),
+    };
+
+    let by_spectrum: HashMap<(&str, &str), &Psm> = psms_a
+        .iter()
+        .filter(|psm| psm.rank == 1)
+        .map(|psm| ((psm.filename.as_str(), psm.scannr.as_str()), psm))
+        .collect();
+    let shared = psms_b
+        .iter()
+        .filter(|psm| psm.rank == 1)
+        .filter_map(|b| {
+            let a = by_spectrum.get(&(b.filename.as_str(), b.scannr.as_str()))?;
+            Some(SharedSpectrum {
+                hyperscore: (a.hyperscore, b.hyperscore),
+                discriminant: (a.discriminant, b.discriminant),
+                same_peptide: a.peptide == b.peptide,
+            })
+        })
+        .collect();
+
+    Ok(Comparison {
+        settings,
+        psms: (
+            results::Summary::new(&psms_a, Q_VALUE).psms,
+            results::Summary::new(&psms_b, Q_VALUE).psms,
+        ),
+        peptides: Overlap::new(
+            confident(&psms_a, |p| p.peptide_q, |p| &p.peptide),
+            confident(&psms_b, |p| p.peptide_q, |p| &p.peptide),
+        ),
+        proteins: Overlap::new(
+            confident(&psms_a, |p| p.protein_q, |p| &p.proteins),
+            confident(&psms_b, |p| p.protein_q, |p| &p.proteins),
+        ),
+        shared,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Peptides,
+    Proteins,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Score {
+    Hyperscore,
+    Discriminant,
+}
+
+pub struct Compare {
+    pub dir_a: String,
+    pub dir_b: String,
+    comparison: Option<Result<Comparison, String>>,
+    level: Level,
+    score: Score,
+    message: String,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Self {
+            dir_a: String::new(),
+            dir_b: String::new(),
+            comparison: None,
+            level: Level::Peptides,
+            score: Score::Hyperscore,
+            message: String::new(),
+        }
+    }
+}
+
+fn export(path: &Path, level: &str, overlap: &Overlap) -> Result<(), String> {
+    let mut out = format!("change\t{}\n", level);
+    for (change, ids) in [("lost", &overlap.only_a), ("gained", &overlap.only_b)] {
+        for id in ids {
+            out.push_str(&format!("{}\t{}\n", change, id));
+        }
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+fn dir_row(ui: &mut egui::Ui, label: &str, dir: &mut String) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.text_edit_singleline(dir);
+        if ui.button("Browse").clicked() {
+            if let Some(path) = FileDialog::new().pick_folder() {
+                *dir = path.display().to_string();
+            }
+        }
+    });
+}
+
+fn id_list(ui: &mut egui::Ui, id: &str, title: &str, ids: &[String]) {
+    ui.vertical(|ui| {
+        ui.strong(format!("{} ({})", title, ids.len()));
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .id_salt(id)
+            .max_height(200.0)
+            .show_rows(ui, row_height, ids.len(), |ui, rows| {
+                for id in &ids[rows] {
+                    ui.label(id);
+                }
+            });
+    });
+}
+
+impl Compare {
+    pub fn update_section(&mut self, ui: &mut egui::Ui) {
+        dir_row(ui, "Run A:", &mut self.dir_a);
+        dir_row(ui, "Run B:", &mut self.dir_b);
+        if ui.button("Compare").clicked() {
+            self.comparison = Some(compare(Path::new(&self.dir_a), Path::new(&self.dir_b)));
+            self.message.clear();
+        }
+
+        let comparison = match &self.comparison {
+            Some(Ok(comparison)) => comparison,
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+                return;
+            }
+            None => return,
+        };
+
+        ui.collapsing("Settings Diff", |ui| match &comparison.settings {
+            Ok(diff) if diff.is_empty() => {
+                ui.label("Both runs used identical search settings.");
+            }
+            Ok(diff) => {
+                egui::ScrollArea::vertical()
+                    .id_salt("compare_settings")
+                    .max_height(250.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("compare_settings_grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.strong("Setting");
+                                ui.strong("Run A");
+                                ui.strong("Run B");
+                                ui.end_row();
+                                for (key, a, b) in diff {
+                                    ui.label(key);
+                                    ui.label(a);
+                                    ui.label(b);
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            }
+            Err(e) => {
+                ui.weak(e);
+            }
+        });
+
+        ui.label(format!(
+            "PSMs at 1% FDR: {} (A) vs {} (B)",
+            comparison.psms.0, comparison.psms.1
+        ));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.level, Level::Peptides, "Peptides");
+            ui.radio_value(&mut self.level, Level::Proteins, "Protein groups");
+        });
+        let (overlap, level) = match self.level {
+            Level::Peptides => (&comparison.peptides, "peptide"),
+            Level::Proteins => (&comparison.proteins, "protein_group"),
+        };
+        plot::venn(
+            ui,
+            ("Run A", "Run B"),
+            overlap.only_a.len(),
+            overlap.both,
+            overlap.only_b.len(),
+        );
+        ui.horizontal(|ui| {
+            id_list(ui, "compare_lost", "Lost (only in A)", &overlap.only_a);
+            id_list(ui, "compare_gained", "Gained (only in B)", &overlap.only_b);
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Export Gained/Lost").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv"])
+                    .set_file_name(format!("{}_changes.tsv", level))
+                    .save_file()
+                {
+                    self.message = match export(&path, level, overlap) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            ui.label(&self.message);
+        });
+
+        ui.separator();
+        let same = comparison.shared.iter().filter(|s| s.same_peptide).count();
+        ui.label(format!(
+            "{} spectra with a top PSM in both runs, {} with the same peptide",
+            comparison.shared.len(),
+            same
+        ));
+        if comparison.shared.is_empty() {
+            ui.weak("No shared spectra, nothing to plot.");
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.score, Score::Hyperscore, "Hyperscore");
+            ui.radio_value(&mut self.score, Score::Discriminant, "Discriminant score");
+        });
+        let step = comparison.shared.len().div_ceil(MAX_POINTS).max(1);
+        let points: Vec<Point> = comparison
+            .shared
+            .iter()
+            .step_by(step)
+            .map(|shared| {
+                let (x, y) = match self.score {
+                    Score::Hyperscore => shared.hyperscore,
+                    Score::Discriminant => shared.discriminant,
+                };
+                let color = match shared.same_peptide {
+                    true => egui::Color32::from_rgb(90, 140, 200),
+                    false => egui::Color32::from_rgb(220, 60, 60),
+                };
+                Point { x, y, color }
+            })
+            .collect();
+        let (lo, hi) = points
+            .iter()
+            .flat_map(|p| [p.x, p.y])
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            });
+        plot::scatter(ui, &points, "Run A", "Run B", &[((lo, lo), (hi, hi))]);
+        ui.weak("Blue: same peptide in both runs, red: the runs disagree.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn flattens_to_dotted_paths() {
+        let mut out = BTreeMap::new();
+        flatten(
+            "",
+            &json!({
+                "database": {"enzyme": {"min_len": 5}, "decoy_tag": "rev_"},
+                "precursor_tol": {"ppm": [-10, 10]},
+                "deisotope": false,
+                "mzml_paths": ["a.mzML"],
+            }),
+            &mut out,
+        );
+        let flat: Vec<(&str, &str)> = out.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(
+            flat,
+            [
+                ("database.decoy_tag", "\"rev_\""),
+                ("database.enzyme.min_len", "5"),
+                ("deisotope", "false"),
+                ("mzml_paths", "[\"a.mzML\"]"),
+                ("precursor_tol.ppm", "[-10,10]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_changed_added_and_removed_settings() {
+        let a = json!({
+            "version": "0.14.7",
+            "database": {"enzyme": {"min_len": 5, "max_len": 50}},
+            "precursor_tol": {"ppm": [-10, 10]},
+            "chimera": false,
+        });
+        let b = json!({
+            "version": "0.15.0",
+            "database": {"enzyme": {"min_len": 7, "max_len": 50}},
+            "precursor_tol": {"da": [-500, 500]},
+            "chimera": false,
+            "wide_window": true,
+        });
+        let changes = diff(&a, &b);
+        let changes: Vec<(&str, &str, &str)> = changes
+            .iter()
+            .map(|(k, a, b)| (k.as_str(), a.as_str(), b.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("database.enzyme.min_len", "5", "7"),
+                ("precursor_tol.da", "(not set)", "[-500,500]"),
+                ("precursor_tol.ppm", "[-10,10]", "(not set)"),
+                ("version", "\"0.14.7\"", "\"0.15.0\""),
+                ("wide_window", "(not set)", "true"),
+            ]
+        );
+        assert!(diff(&a, &a).is_empty());
+    }
+}
@@ -0,0 +1,400 @@
+/// Experimental design: what each input file is, for labelling and grouping the
+/// per-file columns of LFQ results and exports.
+///
+/// Rows are keyed by the file stem, which is how files show up in sage's outputs no
+/// matter if they were picked locally or from S3. Inputs that share a stem (the same
+/// name in different folders or buckets) cannot be told apart and are reported.
+use crate::output::stem;
+use crate::results::{field, Tsv};
+use eframe::egui;
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const HEADER: [&str; 6] = [
+    "file",
+    "sample",
+    "condition",
+    "bio_replicate",
+    "tech_replicate",
+    "fraction",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct DesignRow {
+    pub file: String,
+    pub sample: String,
+    pub condition: String,
+    pub bio_replicate: u32,
+    pub tech_replicate: u32,
+    pub fraction: u32,
+}
+
+impl DesignRow {
+    fn new(file: String) -> Self {
+        Self {
+            file,
+            bio_replicate: 1,
+            tech_replicate: 1,
+            fraction: 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Stems shared by more than one of `files`, with the files sharing them.
+pub fn name_collisions(files: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut by_stem: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for file in files {
+        let paths = by_stem.entry(stem(file)).or_default();
+        if !paths.contains(file) {
+            paths.push(file.clone());
+        }
+    }
+    by_stem
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .collect()
+}
+
+/// Tabs and line breaks would shift the columns of the exported TSV.
+fn tsv_text(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Design {
+    pub rows: Vec<DesignRow>,
+}
+
+impl Design {
+    /// The row of a file, given by path, URI or bare name as found in sage's outputs.
+    pub fn get(&self, file: &str) -> Option<&DesignRow> {
+        let file = stem(file);
+        self.rows.iter().find(|row| row.file == file)
+    }
+
+    /// Column name for a file: the sample if one is set, else the file stem.
+    pub fn column_name(&self, file: &str) -> String {
+        match self.get(file) {
+            Some(row) if !row.sample.trim().is_empty() => row.sample.trim().to_string(),
+            _ => stem(file),
+        }
+    }
+
+    pub fn condition(&self, file: &str) -> Option<&str> {
+        self.get(file)
+            .map(|row| row.condition.trim())
+            .filter(|condition| !condition.is_empty())
+    }
+
+    /// Distinct conditions, in the order they first appear.
+    pub fn conditions(&self) -> Vec<String> {
+        let mut conditions: Vec<String> = Vec::new();
+        for row in &self.rows {
+            let condition = row.condition.trim();
+            if !condition.is_empty() && !conditions.iter().any(|c| c == condition) {
+                conditions.push(condition.to_string());
+            }
+        }
+        conditions
+    }
+
+    /// Indices of `files` grouped by condition, then replicates and fraction. Files
+    /// without a design row come last, in their original order.
+    pub fn order(&self, files: &[String]) -> Vec<usize> {
+        let conditions = self.conditions();
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        order.sort_by_key(|&i| match self.get(&files[i]) {
+            Some(row) => (
+                conditions
+                    .iter()
+                    .position(|c| c == row.condition.trim())
+                    .unwrap_or(conditions.len()),
+                row.bio_replicate,
+                row.tech_replicate,
+                row.fraction,
+            ),
+            None => (usize::MAX, 0, 0, 0),
+        });
+        order
+    }
+
+    /// Add a row for every input file that does not have one yet.
+    pub fn sync<'a>(&mut self, files: impl Iterator<Item = &'a str>) {
+        for file in files {
+            if self.get(file).is_none() {
+                self.rows.push(DesignRow::new(stem(file)));
+            }
+        }
+    }
+
+    pub fn import_tsv(path: &Path) -> Result<Self, String> {
+        let tsv = Tsv::open(path)?;
+        let file = tsv.column("file")?;
+        let sample = tsv.optional_column("sample");
+        let condition = tsv.optional_column("condition");
+        let bio = tsv.optional_column("bio_replicate");
+        let tech = tsv.optional_column("tech_replicate");
+        let fraction = tsv.optional_column("fraction");
+        let text = |row: &[String], column: Option<usize>| {
+            column
+                .and_then(|i| row.get(i))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        let number =
+            |row: &[String], column: Option<usize>| column.and_then(|i| field(row, i)).unwrap_or(1);
+        let rows = tsv
+            .filter_map(|row| {
+                Some(DesignRow {
+                    file: stem(row.get(file)?.trim()),
+                    sample: text(&row, sample),
+                    condition: text(&row, condition),
+                    bio_replicate: number(&row, bio),
+                    tech_replicate: number(&row, tech),
+                    fraction: number(&row, fraction),
+                })
+            })
+            .collect();
+        Ok(Self { rows })
+    }
+
+    pub fn export_tsv(&self, path: &Path) -> Result<(), String> {
+        let mut out = HEADER.join("\t");
+        out.push('\n');
+        for row in &self.rows {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                tsv_text(&row.file),
+                tsv_text(&row.sample),
+                tsv_text(&row.condition),
+                row.bio_replicate,
+                row.tech_replicate,
+                row.fraction
+            ));
+        }
+        fs::write(path, out).map_err(|e| e.to_string())
+    }
+
+    /// Editor for the rows; `files` are the currently selected inputs.
+    pub fn update_section(&mut self, ui: &mut egui::Ui, files: &[String], message: &mut String) {
+        self.sync(files.iter().map(String::as_str));
+        let selected: Vec<String> = files.iter().map(|file| stem(file)).collect();
+
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .id_salt("design_rows")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("design_grid").striped(true).show(ui, |ui| {
+                    for header in [
+                        "File",
+                        "Sample",
+                        "Condition",
+                        "Bio. Rep.",
+                        "Tech. Rep.",
+                        "Fraction",
+                        "",
+                    ] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for (i, row) in self.rows.iter_mut().enumerate() {
+                        let in_use = selected.contains(&row.file);
+                        match in_use {
+                            true => ui.label(&row.file),
+                            false => ui
+                                .weak(&row.file)
+                                .on_hover_text("Not among the selected input files"),
+                        };
+                        ui.add(egui::TextEdit::singleline(&mut row.sample).desired_width(120.0));
+                        ui.add(egui::TextEdit::singleline(&mut row.condition).desired_width(100.0));
+                        ui.add(egui::DragValue::new(&mut row.bio_replicate).range(1..=999));
+                        ui.add(egui::DragValue::new(&mut row.tech_replicate).range(1..=999));
+                        ui.add(egui::DragValue::new(&mut row.fraction).range(1..=999));
+                        if !in_use && ui.small_button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        if let Some(i) = remove {
+            self.rows.remove(i);
+        }
+        for (name, paths) in name_collisions(files) {
+            ui.colored_label(
+                egui::Color32::from_rgb(230, 160, 0),
+                format!(
+                    "⚠ '{}' is the name of {} inputs, they share one row: {}",
+                    name,
+                    paths.len(),
+                    paths.join(", ")
+                ),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Import TSV").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv", "txt"])
+                    .pick_file()
+                {
+                    *message = match Design::import_tsv(&path) {
+                        Ok(design) => {
+                            let n = design.rows.len();
+                            *self = design;
+                            format!("Imported {} rows", n)
+                        }
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            if ui.button("Export TSV").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv"])
+                    .set_file_name("design.tsv")
+                    .save_file()
+                {
+                    *message = match self.export_tsv(&path) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            ui.label(message.as_str());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(file: &str, sample: &str, condition: &str, bio: u32, fraction: u32) -> DesignRow {
+        DesignRow {
+            file: file.to_string(),
+            sample: sample.to_string(),
+            condition: condition.to_string(),
+            bio_replicate: bio,
+            tech_replicate: 1,
+            fraction,
+        }
+    }
+
+    fn design() -> Design {
+        Design {
+            rows: vec![
+                row("ctrl_2", "C2", "control", 2, 1),
+                row("treated_1", "T1", " treated ", 1, 1),
+                row("ctrl_1", "", "control", 1, 1),
+                row("blank", "", "", 1, 1),
+            ],
+        }
+    }
+
+    #[test]
+    fn finds_rows_by_path_uri_or_name() {
+        let design = design();
+        for file in [
+            "/data/ctrl_2.mzML",
+            "C:\\data\\ctrl_2.mzML.gz",
+            "s3://bucket/runs/ctrl_2.mzML",
+            "ctrl_2",
+        ] {
+            assert_eq!(design.get(file).map(|r| r.sample.as_str()), Some("C2"));
+        }
+        assert!(design.get("other.mzML").is_none());
+        assert_eq!(design.column_name("/data/ctrl_2.mzML"), "C2");
+        assert_eq!(design.column_name("/data/ctrl_1.mzML"), "ctrl_1");
+        assert_eq!(design.condition("treated_1.d"), Some("treated"));
+        assert_eq!(design.condition("blank"), None);
+        assert_eq!(design.conditions(), ["control", "treated"]);
+    }
+
+    #[test]
+    fn orders_by_condition_and_replicate() {
+        let files: Vec<String> = [
+            "unknown.mzML",
+            "treated_1.mzML",
+            "blank.mzML",
+            "ctrl_2.mzML",
+            "ctrl_1.mzML",
+        ]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+        // Conditions in order of appearance, rows without one after them, files
+        // without a row last
+        assert_eq!(design().order(&files), [4, 3, 1, 2, 0]);
+    }
+
+    #[test]
+    fn sync_adds_missing_rows_once() {
+        let mut design = design();
+        let files = ["/a/ctrl_1.mzML", "/a/new.mzML", "s3://b/new.mzML"];
+        design.sync(files.into_iter());
+        design.sync(files.into_iter());
+        assert_eq!(design.rows.len(), 5);
+        assert_eq!(design.rows[4], DesignRow::new("new".to_string()));
+    }
+
+    #[test]
+    fn detects_name_collisions() {
+        let files: Vec<String> = [
+            "/a/run01.mzML",
+            "s3://bucket/run01.mzML.gz",
+            "/a/run01.mzML",
+            "/a/run02.mzML",
+        ]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+        assert_eq!(
+            name_collisions(&files),
+            [(
+                "run01".to_string(),
+                vec![
+                    "/a/run01.mzML".to_string(),
+                    "s3://bucket/run01.mzML.gz".to_string()
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_tsv() {
+        let dir = std::env::temp_dir().join(format!("sagegui_design_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("design.tsv");
+
+        let mut design = design();
+        design.rows[1].fraction = 3;
+        design.export_tsv(&path).unwrap();
+        // Values are trimmed on import
+        design.rows[1].condition = "treated".to_string();
+        assert_eq!(Design::import_tsv(&path).unwrap(), design);
+
+        // Tabs and line breaks cannot end up in a cell
+        design.rows[0].sample = "C\t2".to_string();
+        design.rows[0].condition = "con\ntrol".to_string();
+        design.export_tsv(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 5);
+        assert!(text.lines().all(|line| line.split('\t').count() == 6));
+        let imported = Design::import_tsv(&path).unwrap();
+        assert_eq!(imported.rows[0].sample, "C 2");
+        assert_eq!(imported.rows[0].condition, "con trol");
+
+        // Only the file column is required, numbers default to 1
+        fs::write(&path, "condition\tfile\n  a\t/x/run.mzML\n").unwrap();
+        let imported = Design::import_tsv(&path).unwrap();
+        assert_eq!(imported.rows, [row("run", "", "a", 1, 1)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
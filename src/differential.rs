@@ -0,0 +1,734 @@
+/// Two-group differential abundance on a processed quant matrix.
+///
+/// Columns are assigned to conditions through the experimental design (LFQ) or the
+/// channel annotation (TMT), matched by sample name. Every row is tested with Welch's
+/// t-test or a moderated t-test, where each row's variance is shrunk towards a prior
+/// estimated from all rows (limma's empirical Bayes). p-values are adjusted with
+/// Benjamini-Hochberg.
+use crate::plot::{self, Point};
+use crate::quant::{self, QuantMatrix};
+use crate::stats;
+use eframe::egui;
+use rfd::FileDialog;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Rows listed in the results table.
+const MAX_ROWS: usize = 500;
+const SIGNIFICANT: egui::Color32 = egui::Color32::from_rgb(220, 60, 60);
+const NOT_SIGNIFICANT: egui::Color32 = egui::Color32::from_rgb(150, 150, 150);
+const SELECTED: egui::Color32 = egui::Color32::from_rgb(230, 160, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Test {
+    Welch,
+    Moderated,
+}
+
+#[derive(Debug, Clone)]
+struct TestRow {
+    id: String,
+    mean_a: f64,
+    mean_b: f64,
+    log2_fc: f64,
+    p: f64,
+    q: f64,
+}
+
+/// Per row summary of both groups, on the log2 scale.
+struct Groups {
+    mean_a: f64,
+    mean_b: f64,
+    var_a: f64,
+    var_b: f64,
+    n_a: f64,
+    n_b: f64,
+}
+
+impl Groups {
+    fn new(a: &[f64], b: &[f64]) -> Option<Self> {
+        Some(Self {
+            mean_a: stats::mean(a)?,
+            mean_b: stats::mean(b)?,
+            var_a: stats::variance(a)?,
+            var_b: stats::variance(b)?,
+            n_a: a.len() as f64,
+            n_b: b.len() as f64,
+        })
+    }
+
+    fn difference(&self) -> f64 {
+        self.mean_b - self.mean_a
+    }
+
+    /// Welch's t statistic and Welch-Satterthwaite degrees of freedom.
+    fn welch(&self) -> Option<(f64, f64)> {
+        let (sa, sb) = (self.var_a / self.n_a, self.var_b / self.n_b);
+        let se = (sa + sb).sqrt();
+        if se <= 0.0 {
+            return None;
+        }
+        let df =
+            (sa + sb).powi(2) / (sa.powi(2) / (self.n_a - 1.0) + sb.powi(2) / (self.n_b - 1.0));
+        Some((self.difference() / se, df))
+    }
+
+    fn pooled(&self) -> (f64, f64) {
+        let df = self.n_a + self.n_b - 2.0;
+        let variance = ((self.n_a - 1.0) * self.var_a + (self.n_b - 1.0) * self.var_b) / df;
+        (variance, df)
+    }
+}
+
+/// Prior degrees of freedom and variance from the pooled variances of all rows, by
+/// moments of their log (limma's `fitFDist` without covariates).
+fn variance_prior(pooled: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let usable: Vec<(f64, f64)> = pooled.iter().copied().filter(|(v, _)| *v > 0.0).collect();
+    let e: Vec<f64> = usable
+        .iter()
+        .map(|(v, df)| v.ln() - stats::digamma(df / 2.0) + (df / 2.0).ln())
+        .collect();
+    let e_mean = stats::mean(&e)?;
+    let e_var = stats::variance(&e)?;
+    let expected = stats::mean(
+        &usable
+            .iter()
+            .map(|(_, df)| stats::trigamma(df / 2.0))
+            .collect::<Vec<_>>(),
+    )?;
+    let excess = e_var - expected;
+    if excess <= 0.0 {
+        // No more spread than sampling noise: all rows share one variance
+        let variances: Vec<f64> = usable.iter().map(|(v, _)| *v).collect();
+        return Some((f64::INFINITY, stats::mean(&variances)?));
+    }
+    let d0 = 2.0 * stats::inverse_trigamma(excess);
+    Some((
+        d0,
+        (e_mean + stats::digamma(d0 / 2.0) - (d0 / 2.0).ln()).exp(),
+    ))
+}
+
+/// Test every row with at least two values in each group. `group` is `Some(false)`
+/// for condition A columns, `Some(true)` for B.
+fn test(
+    matrix: &QuantMatrix,
+    group: &[Option<bool>],
+    log: bool,
+    method: Test,
+) -> Result<Vec<TestRow>, String> {
+    let rows: Vec<(String, Groups)> = matrix
+        .rows
+        .iter()
+        .filter_map(|row| {
+            let (mut a, mut b) = (Vec::new(), Vec::new());
+            for (value, group) in row.values.iter().zip(group) {
+                let value = match (value, log) {
+                    (Some(v), true) => *v,
+                    (Some(v), false) if *v > 0.0 => v.log2(),
+                    _ => continue,
+                };
+                match group {
+                    Some(false) => a.push(value),
+                    Some(true) => b.push(value),
+                    None => {}
+                }
+            }
+            Some((row.label(), Groups::new(&a, &b)?))
+        })
+        .collect();
+
+    let prior = match method {
+        Test::Welch => None,
+        Test::Moderated => {
+            let pooled: Vec<(f64, f64)> = rows.iter().map(|(_, g)| g.pooled()).collect();
+            Some(
+                variance_prior(&pooled)
+                    .ok_or("Not enough rows to estimate a variance prior, use Welch's t-test")?,
+            )
+        }
+    };
+    let tested: Vec<(String, &Groups, f64)> = rows
+        .iter()
+        .filter_map(|(id, groups)| {
+            // A moderated test always has its prior by now
+            let (t, df) = match prior {
+                None => groups.welch()?,
+                Some((d0, s0)) => {
+                    let (variance, df) = groups.pooled();
+                    let shrunk = match d0.is_finite() {
+                        true => (d0 * s0 + df * variance) / (d0 + df),
+                        false => s0,
+                    };
+                    let se = (shrunk * (1.0 / groups.n_a + 1.0 / groups.n_b)).sqrt();
+                    if se <= 0.0 {
+                        return None;
+                    }
+                    (groups.difference() / se, (df + d0).min(1e6))
+                }
+            };
+            Some((id.clone(), groups, stats::t_test_p(t, df)))
+        })
+        .collect();
+
+    let p_values: Vec<f64> = tested.iter().map(|(_, _, p)| *p).collect();
+    let q_values = stats::benjamini_hochberg(&p_values);
+    let mut results: Vec<TestRow> = tested
+        .into_iter()
+        .zip(q_values)
+        .map(|((id, groups, p), q)| TestRow {
+            id,
+            mean_a: groups.mean_a,
+            mean_b: groups.mean_b,
+            log2_fc: groups.difference(),
+            p,
+            q,
+        })
+        .collect();
+    results.sort_by(|a, b| a.p.total_cmp(&b.p));
+    Ok(results)
+}
+
+fn export_table(path: &Path, rows: &[TestRow]) -> Result<(), String> {
+    let mut out = "id\tmean_log2_a\tmean_log2_b\tlog2_fc\tp_value\tq_value\n".to_string();
+    for row in rows {
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            row.id, row.mean_a, row.mean_b, row.log2_fc, row.p, row.q
+        ));
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+/// The volcano plot as a standalone SVG.
+fn export_svg(
+    path: &Path,
+    points: &[Point],
+    title: &str,
+    guides: &[plot::Line],
+) -> Result<(), String> {
+    let (width, height, margin) = (640.0, 480.0, 50.0);
+    let range = |values: Vec<f64>| {
+        let lo = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        match lo < hi {
+            true => (lo - 0.05 * (hi - lo), hi + 0.05 * (hi - lo)),
+            false => (lo - 1.0, lo + 1.0),
+        }
+    };
+    let finite: Vec<&Point> = points
+        .iter()
+        .filter(|p| p.x.is_finite() && p.y.is_finite())
+        .collect();
+    let (x_lo, x_hi) = range(finite.iter().map(|p| p.x).collect());
+    let (y_lo, y_hi) = range(finite.iter().map(|p| p.y).collect());
+    let sx = |x: f64| margin + (x - x_lo) / (x_hi - x_lo) * (width - 2.0 * margin);
+    let sy = |y: f64| height - margin - (y - y_lo) / (y_hi - y_lo) * (height - 2.0 * margin);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" font-family=\"sans-serif\" font-size=\"12\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n\
+         <text x=\"{cx}\" y=\"20\" text-anchor=\"middle\" font-size=\"14\">{title}</text>\n\
+         <rect x=\"{m}\" y=\"{m}\" width=\"{iw}\" height=\"{ih}\" fill=\"none\" stroke=\"#888\"/>\n",
+        w = width,
+        h = height,
+        cx = width / 2.0,
+        m = margin,
+        iw = width - 2.0 * margin,
+        ih = height - 2.0 * margin,
+        title = title
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;"),
+    );
+    for ((x0, y0), (x1, y1)) in guides {
+        svg.push_str(&format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#aaa\" stroke-dasharray=\"4 3\"/>\n",
+            sx(x0.clamp(x_lo, x_hi)),
+            sy(y0.clamp(y_lo, y_hi)),
+            sx(x1.clamp(x_lo, x_hi)),
+            sy(y1.clamp(y_lo, y_hi))
+        ));
+    }
+    for point in finite {
+        let [r, g, b, _] = point.color.to_array();
+        svg.push_str(&format!(
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"#{:02x}{:02x}{:02x}\"/>\n",
+            sx(point.x),
+            sy(point.y),
+            r,
+            g,
+            b
+        ));
+    }
+    svg.push_str(&format!(
+        "<text x=\"{m}\" y=\"{b}\">{x_lo:.2}</text>\n\
+         <text x=\"{r}\" y=\"{b}\" text-anchor=\"end\">{x_hi:.2}</text>\n\
+         <text x=\"{cx}\" y=\"{b}\" text-anchor=\"middle\">log2 fold change</text>\n\
+         <text x=\"{l}\" y=\"{bottom}\" text-anchor=\"end\">{y_lo:.1}</text>\n\
+         <text x=\"{l}\" y=\"{top}\" text-anchor=\"end\">{y_hi:.1}</text>\n\
+         <text x=\"14\" y=\"{cy}\" transform=\"rotate(-90 14 {cy})\" text-anchor=\"middle\">-log10 p</text>\n\
+         </svg>\n",
+        m = margin,
+        b = height - margin + 18.0,
+        r = width - margin,
+        cx = width / 2.0,
+        l = margin - 4.0,
+        bottom = height - margin,
+        top = margin + 10.0,
+        cy = height / 2.0,
+        x_lo = x_lo,
+        x_hi = x_hi,
+        y_lo = y_lo,
+        y_hi = y_hi,
+    ));
+    fs::write(path, svg).map_err(|e| e.to_string())
+}
+
+/// Processed matrices in the results folder, newest naming first.
+fn processed_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.to_string_lossy().ends_with(".processed.tsv"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+pub struct Differential {
+    pub results_dir: String,
+    path: String,
+    matrix: Option<Result<QuantMatrix, String>>,
+    log: bool,
+    condition_a: String,
+    condition_b: String,
+    method: Test,
+    q_threshold: f64,
+    fc_threshold: f64,
+    results: Vec<TestRow>,
+    selected: Option<usize>,
+    message: String,
+}
+
+impl Default for Differential {
+    fn default() -> Self {
+        Self {
+            results_dir: String::new(),
+            path: String::new(),
+            matrix: None,
+            log: true,
+            condition_a: String::new(),
+            condition_b: String::new(),
+            method: Test::Moderated,
+            q_threshold: 0.05,
+            fc_threshold: 1.0,
+            results: Vec::new(),
+            selected: None,
+            message: String::new(),
+        }
+    }
+}
+
+fn condition_combo(ui: &mut egui::Ui, id: &str, value: &mut String, conditions: &[String]) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(value.as_str())
+        .show_ui(ui, |ui| {
+            for condition in conditions {
+                ui.selectable_value(value, condition.clone(), condition);
+            }
+        });
+}
+
+impl Differential {
+    fn load(&mut self) {
+        let matrix = quant::read_wide(Path::new(&self.path));
+        if let Ok(matrix) = &matrix {
+            // Intensities are in the millions, log2 values rarely above 40
+            self.log = matrix
+                .rows
+                .iter()
+                .flat_map(|row| row.values.iter().flatten())
+                .all(|v| *v < 100.0);
+        }
+        self.matrix = Some(matrix);
+        self.results.clear();
+        self.selected = None;
+        self.message.clear();
+    }
+
+    /// `conditions` maps sample (column) names to their condition.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        default_dir: &str,
+        conditions: &HashMap<String, String>,
+    ) {
+        if self.results_dir.is_empty() {
+            self.results_dir = default_dir.to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Results Folder:");
+            ui.text_edit_singleline(&mut self.results_dir);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Matrix:");
+            let files = processed_files(Path::new(&self.results_dir));
+            let name = |path: &str| {
+                Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            };
+            egui::ComboBox::from_id_salt("differential_matrix")
+                .selected_text(name(&self.path))
+                .show_ui(ui, |ui| {
+                    for file in files {
+                        let file = file.display().to_string();
+                        ui.selectable_value(&mut self.path, file.clone(), name(&file));
+                    }
+                });
+            if ui.button("Browse").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("TSV", &["tsv"]).pick_file() {
+                    self.path = path.display().to_string();
+                }
+            }
+            if ui
+                .add_enabled(!self.path.is_empty(), egui::Button::new("Load"))
+                .clicked()
+            {
+                self.load();
+            }
+        });
+        ui.weak("Write a processed matrix from the LFQ or TMT tab first (Normalization and Imputation).");
+
+        let matrix = match &self.matrix {
+            Some(Ok(matrix)) => matrix,
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+                return;
+            }
+            None => return,
+        };
+
+        let mut available: Vec<String> = matrix
+            .samples
+            .iter()
+            .filter_map(|sample| conditions.get(sample).cloned())
+            .collect();
+        available.sort();
+        available.dedup();
+        if available.len() < 2 {
+            ui.colored_label(
+                egui::Color32::from_rgb(230, 160, 0),
+                "Fewer than two conditions match the columns. Assign conditions in the experimental design or the TMT channel annotation.",
+            );
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Condition A:");
+            condition_combo(ui, "condition_a", &mut self.condition_a, &available);
+            ui.label("vs. B:");
+            condition_combo(ui, "condition_b", &mut self.condition_b, &available);
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.method, Test::Moderated, "Moderated t-test");
+            ui.radio_value(&mut self.method, Test::Welch, "Welch t-test");
+            ui.checkbox(&mut self.log, "Values are log2");
+        });
+        ui.horizontal(|ui| {
+            ui.label("q-value ≤");
+            ui.add(
+                egui::DragValue::new(&mut self.q_threshold)
+                    .speed(0.005)
+                    .range(0.001..=0.5),
+            );
+            ui.label("|log2 FC| ≥");
+            ui.add(
+                egui::DragValue::new(&mut self.fc_threshold)
+                    .speed(0.05)
+                    .range(0.0..=10.0),
+            );
+        });
+
+        let group: Vec<Option<bool>> = matrix
+            .samples
+            .iter()
+            .map(|sample| match conditions.get(sample) {
+                Some(c) if *c == self.condition_a => Some(false),
+                Some(c) if *c == self.condition_b => Some(true),
+                _ => None,
+            })
+            .collect();
+        let ready = !self.condition_a.is_empty()
+            && !self.condition_b.is_empty()
+            && self.condition_a != self.condition_b;
+        if ui
+            .add_enabled(ready, egui::Button::new("Run Test"))
+            .clicked()
+        {
+            self.selected = None;
+            self.message = match test(matrix, &group, self.log, self.method) {
+                Ok(results) => {
+                    self.results = results;
+                    format!("{} rows tested", self.results.len())
+                }
+                Err(e) => {
+                    self.results.clear();
+                    format!("Error: {}", e)
+                }
+            };
+        }
+        if self.results.is_empty() {
+            ui.label(&self.message);
+            return;
+        }
+
+        let significant =
+            |row: &TestRow| row.q <= self.q_threshold && row.log2_fc.abs() >= self.fc_threshold;
+        let n_significant = self.results.iter().filter(|row| significant(row)).count();
+        ui.label(format!(
+            "{}, {} significant ({} up, {} down in B)",
+            self.message,
+            n_significant,
+            self.results
+                .iter()
+                .filter(|r| significant(r) && r.log2_fc > 0.0)
+                .count(),
+            self.results
+                .iter()
+                .filter(|r| significant(r) && r.log2_fc < 0.0)
+                .count(),
+        ));
+
+        // Selected point drawn last so it stays on top
+        let mut order: Vec<usize> = (0..self.results.len())
+            .filter(|i| Some(*i) != self.selected)
+            .collect();
+        order.extend(self.selected);
+        let points: Vec<Point> = order
+            .iter()
+            .map(|&i| {
+                let row = &self.results[i];
+                let color = match (Some(i) == self.selected, significant(row)) {
+                    (true, _) => SELECTED,
+                    (false, true) => SIGNIFICANT,
+                    (false, false) => NOT_SIGNIFICANT,
+                };
+                Point {
+                    x: row.log2_fc,
+                    y: -row.p.max(f64::MIN_POSITIVE).log10(),
+                    color,
+                }
+            })
+            .collect();
+        // p-value at the q-value cutoff, for the horizontal guide
+        let cutoff = self
+            .results
+            .iter()
+            .filter(|row| row.q <= self.q_threshold)
+            .map(|row| row.p)
+            .fold(None, |max: Option<f64>, p| {
+                Some(max.map_or(p, |m| m.max(p)))
+            });
+        let y_max = points
+            .iter()
+            .map(|p| p.y)
+            .filter(|y| y.is_finite())
+            .fold(1.0, f64::max);
+        let x_max = points
+            .iter()
+            .map(|p| p.x.abs())
+            .filter(|x| x.is_finite())
+            .fold(1.0, f64::max);
+        let mut guides = vec![
+            ((-self.fc_threshold, 0.0), (-self.fc_threshold, y_max)),
+            ((self.fc_threshold, 0.0), (self.fc_threshold, y_max)),
+        ];
+        if let Some(p) = cutoff {
+            guides.push(((-x_max, -p.log10()), (x_max, -p.log10())));
+        }
+        let title = format!("{} vs {}", self.condition_b, self.condition_a);
+        if let Some(i) = plot::scatter(ui, &points, "log2 fold change (B / A)", "-log10 p", &guides)
+        {
+            self.selected = Some(order[i]);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Export Table").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv"])
+                    .set_file_name("differential.tsv")
+                    .save_file()
+                {
+                    self.message = match export_table(&path, &self.results) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            if ui.button("Export Volcano (SVG)").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("SVG", &["svg"])
+                    .set_file_name("volcano.svg")
+                    .save_file()
+                {
+                    self.message = match export_svg(&path, &points, &title, &guides) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+        });
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        let shown = self.results.len().min(MAX_ROWS);
+        egui::ScrollArea::vertical()
+            .id_salt("differential_rows")
+            .max_height(200.0)
+            .show_rows(ui, row_height, shown, |ui, range| {
+                for i in range {
+                    let row = &self.results[i];
+                    let text = format!(
+                        "{}  log2 FC {:+.2}  p {:.2e}  q {:.2e}",
+                        row.id, row.log2_fc, row.p, row.q
+                    );
+                    if ui
+                        .selectable_label(self.selected == Some(i), text)
+                        .clicked()
+                    {
+                        self.selected = Some(i);
+                    }
+                }
+            });
+
+        if let Some(row) = self.selected.and_then(|i| self.results.get(i)) {
+            ui.strong(&row.id);
+            if let Some(values) = matrix.rows.iter().find(|r| r.label() == row.id) {
+                let groups: Vec<(String, Vec<f64>)> =
+                    [(&self.condition_a, false), (&self.condition_b, true)]
+                        .into_iter()
+                        .map(|(condition, b)| {
+                            let values = values
+                                .values
+                                .iter()
+                                .zip(&group)
+                                .filter(|(_, g)| **g == Some(b))
+                                .filter_map(|(v, _)| match self.log {
+                                    true => *v,
+                                    false => v.filter(|v| *v > 0.0).map(f64::log2),
+                                })
+                                .collect();
+                            (condition.clone(), values)
+                        })
+                        .collect();
+                plot::boxplots(ui, &groups, "log2 value");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn variance_prior_matches_fit_f_dist() {
+        // limma::fitFDist(c(0.12, 0.35, 0.08, 0.9, 0.22, 0.05, 1.4, 0.3),
+        //                 df1 = c(4, 4, 4, 4, 6, 6, 6, 6))
+        let pooled = [
+            (0.12, 4.0),
+            (0.35, 4.0),
+            (0.08, 4.0),
+            (0.9, 4.0),
+            (0.22, 6.0),
+            (0.05, 6.0),
+            (1.4, 6.0),
+            (0.3, 6.0),
+        ];
+        let (d0, s0) = variance_prior(&pooled).unwrap();
+        assert_close(d0, 3.407_830_161, 1e-6);
+        assert_close(s0, 0.224_136_218_8, 1e-8);
+    }
+
+    #[test]
+    fn variance_prior_without_excess_spread() {
+        let (d0, s0) = variance_prior(&[(0.5, 4.0), (0.5, 4.0), (0.5, 4.0)]).unwrap();
+        assert!(d0.is_infinite());
+        assert_close(s0, 0.5, 1e-12);
+    }
+
+    #[test]
+    fn variance_prior_needs_two_rows() {
+        assert!(variance_prior(&[(0.5, 4.0)]).is_none());
+        assert!(variance_prior(&[(0.5, 4.0), (0.0, 4.0)]).is_none());
+    }
+
+    fn matrix(rows: &[[f64; 6]]) -> QuantMatrix {
+        let samples = (1..=6).map(|i| format!("s{}", i)).collect();
+        let rows = rows
+            .iter()
+            .enumerate()
+            .map(|(i, values)| (format!("row{}", i), values.map(Some).to_vec()))
+            .collect();
+        QuantMatrix::from_rows(samples, rows)
+    }
+
+    const GROUPS: [Option<bool>; 6] = [
+        Some(false),
+        Some(false),
+        Some(false),
+        Some(true),
+        Some(true),
+        Some(true),
+    ];
+
+    #[test]
+    fn welch_test() {
+        let rows = test(
+            &matrix(&[[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]]),
+            &GROUPS,
+            true,
+            Test::Welch,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_close(rows[0].log2_fc, 3.0, 1e-12);
+        // t = 3.674, df = 4
+        assert_close(rows[0].p, 0.021_311_641_13, 1e-9);
+        assert_close(rows[0].q, rows[0].p, 1e-12);
+    }
+
+    #[test]
+    fn moderated_test_needs_a_prior() {
+        let one_row = matrix(&[[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]]);
+        assert!(test(&one_row, &GROUPS, true, Test::Moderated).is_err());
+
+        let rows = matrix(&[
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0],
+            [1.0, 1.5, 2.5, 1.0, 2.0, 3.5],
+            [3.0, 3.1, 2.8, 3.3, 2.9, 3.0],
+        ]);
+        let moderated = test(&rows, &GROUPS, true, Test::Moderated).unwrap();
+        let welch = test(&rows, &GROUPS, true, Test::Welch).unwrap();
+        assert_eq!(moderated.len(), 3);
+        assert!(moderated
+            .iter()
+            .zip(&welch)
+            .any(|(m, w)| (m.p - w.p).abs() > 1e-6));
+    }
+}
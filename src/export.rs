@@ -0,0 +1,96 @@
+/// Exports of a finished search for downstream tools.
+use crate::design::Design;
+use crate::identifications;
+use crate::library::LibraryBuilder;
+use crate::msstats::MsstatsExport;
+use crate::mztab;
+use crate::ChannelAnnotation;
+use eframe::egui;
+use rfd::FileDialog;
+use std::path::Path;
+
+#[derive(Default)]
+pub struct Exports {
+    pub results_dir: String,
+    msstats: MsstatsExport,
+    mztab_message: String,
+    mztab_problems: Vec<String>,
+    identifications_message: String,
+    library: LibraryBuilder,
+}
+
+impl Exports {
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        default_dir: &str,
+        design: &Design,
+        channels: &[ChannelAnnotation],
+    ) {
+        if self.results_dir.is_empty() {
+            self.results_dir = default_dir.to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Results Folder:");
+            ui.text_edit_singleline(&mut self.results_dir);
+            if ui.button("Browse").clicked() {
+                if let Some(path) = FileDialog::new().pick_folder() {
+                    self.results_dir = path.display().to_string();
+                }
+            }
+        });
+        let dir = Path::new(&self.results_dir);
+
+        ui.collapsing("MSstats", |ui| {
+            self.msstats.update_section(ui, dir, design, channels);
+        });
+        ui.collapsing("mzTab", |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Write results.mzTab").clicked() {
+                    (self.mztab_message, self.mztab_problems) = match mztab::write(dir, design) {
+                        Ok((rows, problems)) => (
+                            format!("{} PSM rows written to {}", rows, mztab::MZTAB_FILE_NAME),
+                            problems,
+                        ),
+                        Err(e) => (format!("Error: {}", e), Vec::new()),
+                    };
+                }
+                ui.weak(
+                    "identifications at 1% FDR, with label free quantities if lfq.tsv is there",
+                );
+            });
+            ui.label(&self.mztab_message);
+            for problem in &self.mztab_problems {
+                ui.colored_label(egui::Color32::RED, format!("⛔ {}", problem));
+            }
+        });
+        ui.collapsing("mzIdentML and pepXML", |ui| {
+            ui.horizontal(|ui| {
+                let mut written = None;
+                if ui.button("Write results.mzid").clicked() {
+                    written = Some((
+                        identifications::write_mzid(dir),
+                        identifications::MZID_FILE_NAME,
+                    ));
+                }
+                if ui.button("Write results.pep.xml").clicked() {
+                    written = Some((
+                        identifications::write_pepxml(dir),
+                        identifications::PEPXML_FILE_NAME,
+                    ));
+                }
+                if let Some((result, file_name)) = written {
+                    self.identifications_message = match result {
+                        Ok(n) => format!("{} spectra written to {}", n, file_name),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+                ui.weak("all PSMs with decoys flagged, for FDR control downstream");
+            });
+            ui.label(&self.identifications_message);
+        });
+        ui.collapsing("Spectral Library", |ui| {
+            self.library.update_section(ui, dir);
+        });
+    }
+}
@@ -0,0 +1,847 @@
+/// mzIdentML 1.2 and pepXML export of every PSM sage reported, targets and decoys, for
+/// tools that build on identifications (spectral library builders, the TPP).
+///
+/// Search parameters come from the run's settings, so the files describe the search
+/// as sage actually ran it. The score of the chosen `ScoreType` is written next to
+/// sage's discriminant score and q-values.
+use crate::compare;
+use crate::output::stem;
+use crate::psi::{
+    self, location, mod_site, parse_peptide, residue_mass, searched_mods, unimod, HYDROGEN,
+    HYDROXYL, PROTON,
+};
+use crate::results::{self, Psm};
+use chrono::Local;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const MZID_FILE_NAME: &str = "results.mzid";
+pub const PEPXML_FILE_NAME: &str = "results.pep.xml";
+/// PSMs at or below this spectrum q-value pass the threshold.
+const Q_VALUE: f64 = 0.01;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The parts of sage's `Input` both formats describe.
+struct Search {
+    version: String,
+    score: &'static str,
+    fasta: String,
+    decoy_tag: String,
+    cleave_at: String,
+    restrict: Option<String>,
+    c_terminal: bool,
+    semi_enzymatic: bool,
+    missed_cleavages: u64,
+    /// Lower and upper bound, and unit (`ppm` or `da`).
+    precursor_tol: Option<(f64, f64, String)>,
+    fragment_tol: Option<(f64, f64, String)>,
+    fixed: Vec<(String, f64)>,
+    variable: Vec<(String, f64)>,
+    /// Input files in the order they were searched.
+    files: Vec<String>,
+}
+
+fn tolerance(value: Option<&Value>) -> Option<(f64, f64, String)> {
+    let (unit, bounds) = value?.as_object()?.iter().next()?;
+    let bounds = bounds.as_array()?;
+    Some((
+        bounds.first()?.as_f64()?,
+        bounds.get(1)?.as_f64()?,
+        unit.to_lowercase(),
+    ))
+}
+
+impl Search {
+    fn new(settings: &Value) -> Self {
+        let text = |pointer: &str| {
+            settings
+                .pointer(pointer)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        let enzyme = |key: &str| settings.pointer(&format!("/database/enzyme/{}", key));
+        Self {
+            version: text("/version").unwrap_or_else(|| "unknown".to_string()),
+            score: match text("/score_type").as_deref() {
+                Some("OpenMSHyperScore") => "openms_hyperscore",
+                _ => "sage_hyperscore",
+            },
+            fasta: text("/database/fasta").unwrap_or_default(),
+            decoy_tag: text("/database/decoy_tag").unwrap_or_else(|| "rev_".to_string()),
+            cleave_at: enzyme("cleave_at")
+                .and_then(Value::as_str)
+                .unwrap_or("KR")
+                .to_string(),
+            restrict: enzyme("restrict")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            c_terminal: enzyme("c_terminal")
+                .and_then(Value::as_bool)
+                .unwrap_or(true),
+            semi_enzymatic: enzyme("semi_enzymatic")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            missed_cleavages: enzyme("missed_cleavages")
+                .and_then(Value::as_u64)
+                .unwrap_or(0),
+            precursor_tol: tolerance(settings.get("precursor_tol")),
+            fragment_tol: tolerance(settings.get("fragment_tol")),
+            fixed: searched_mods(settings, "static_mods"),
+            variable: searched_mods(settings, "variable_mods"),
+            files: settings
+                .get("mzml_paths")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Name and PSI-MS accession for the usual trypsin settings.
+    fn enzyme_name(&self) -> Option<(&'static str, &'static str)> {
+        match (self.cleave_at.as_str(), self.restrict.as_deref()) {
+            ("KR", Some("P")) => Some(("Trypsin", "MS:1001251")),
+            ("KR", None) => Some(("Trypsin/P", "MS:1001313")),
+            _ => None,
+        }
+    }
+
+    fn site_regexp(&self) -> String {
+        let restrict = self
+            .restrict
+            .as_ref()
+            .map(|r| format!("(?![{}])", r))
+            .unwrap_or_default();
+        match self.c_terminal {
+            true => format!("(?<=[{}]){}", self.cleave_at, restrict),
+            false => format!("(?=[{}])", self.cleave_at),
+        }
+    }
+
+    /// Where a PSM's file was searched from, falling back to the name sage reports.
+    fn location(&self, filename: &str) -> String {
+        let name = stem(filename);
+        match self.files.iter().find(|file| stem(file) == name) {
+            Some(file) => location(file),
+            None => location(filename),
+        }
+    }
+}
+
+/// The PSMs of every spectrum by rank, in file and scan order.
+fn spectra(psms: Vec<Psm>) -> Vec<Vec<Psm>> {
+    let mut spectra: BTreeMap<(String, String), Vec<Psm>> = BTreeMap::new();
+    for psm in psms {
+        spectra
+            .entry((psm.filename.clone(), psm.scannr.clone()))
+            .or_default()
+            .push(psm);
+    }
+    spectra
+        .into_values()
+        .map(|mut hits| {
+            hits.sort_by_key(|psm| psm.rank);
+            hits
+        })
+        .collect()
+}
+
+/// Scan number in a native ID such as `controllerType=0 controllerNumber=1 scan=42`.
+fn scan_number(scannr: &str) -> Option<u64> {
+    match scannr
+        .split_whitespace()
+        .find_map(|id| id.strip_prefix("scan="))
+    {
+        Some(scan) => scan.parse().ok(),
+        None => scannr.parse().ok(),
+    }
+}
+
+fn mass_to_charge(mass: f64, charge: u8) -> f64 {
+    let z = charge.max(1) as f64;
+    (mass + z * PROTON) / z
+}
+
+fn accessions(proteins: &str) -> impl Iterator<Item = &str> {
+    proteins.split(';').filter(|p| !p.is_empty())
+}
+
+fn settings(dir: &Path) -> Result<Value, String> {
+    compare::settings(dir)
+        .ok_or_else(|| "No search settings found, they come from results.json".to_string())
+}
+
+fn cv(accession: &str, name: &str) -> String {
+    format!(
+        "<cvParam cvRef=\"PSI-MS\" accession=\"{}\" name=\"{}\"/>",
+        accession, name
+    )
+}
+
+fn cv_value(accession: &str, name: &str, value: impl std::fmt::Display) -> String {
+    format!(
+        "<cvParam cvRef=\"PSI-MS\" accession=\"{}\" name=\"{}\" value=\"{}\"/>",
+        accession, name, value
+    )
+}
+
+fn user_value(name: &str, value: impl std::fmt::Display) -> String {
+    format!(
+        "<userParam name=\"{}\" value=\"{}\" type=\"xsd:double\"/>",
+        name, value
+    )
+}
+
+/// Unimod term of a mass shift, or the PSI-MS term for modifications not in the table.
+fn mod_cv(mass: f64) -> String {
+    match unimod(mass) {
+        Some((accession, name)) => format!(
+            "<cvParam cvRef=\"UNIMOD\" accession=\"UNIMOD:{}\" name=\"{}\"/>",
+            accession,
+            escape(name)
+        ),
+        None => cv("MS:1001460", "unknown modification"),
+    }
+}
+
+fn tolerance_cvs(tolerance: &(f64, f64, String)) -> String {
+    let (low, high, unit) = tolerance;
+    let (unit_accession, unit_name) = match unit.as_str() {
+        "ppm" => ("UO:0000169", "parts per million"),
+        _ => ("UO:0000221", "dalton"),
+    };
+    [
+        ("MS:1001412", "search tolerance plus value", high.abs()),
+        ("MS:1001413", "search tolerance minus value", low.abs()),
+    ]
+    .iter()
+    .map(|(accession, name, value)| {
+        format!(
+            "<cvParam cvRef=\"PSI-MS\" accession=\"{}\" name=\"{}\" value=\"{}\" unitCvRef=\"UO\" unitAccession=\"{}\" unitName=\"{}\"/>",
+            accession, name, value, unit_accession, unit_name
+        )
+    })
+    .collect::<Vec<_>>()
+    .join("\n        ")
+}
+
+/// `SearchModification` elements of the protocol's `ModificationParams`.
+fn search_modifications(search: &Search) -> String {
+    let mut out = String::new();
+    for (fixed, mods) in [(true, &search.fixed), (false, &search.variable)] {
+        for (key, mass) in mods {
+            let (site, position) = mod_site(key);
+            let residues = match site.as_str() {
+                "N-term" | "C-term" => ".".to_string(),
+                residue => residue.to_string(),
+            };
+            let rule = match position {
+                "Any N-term" => Some(("MS:1001189", "modification specificity peptide N-term")),
+                "Any C-term" => Some(("MS:1001190", "modification specificity peptide C-term")),
+                "Protein N-term" => Some(("MS:1002057", "modification specificity protein N-term")),
+                "Protein C-term" => Some(("MS:1002058", "modification specificity protein C-term")),
+                _ => None,
+            };
+            out.push_str(&format!(
+                "        <SearchModification fixedMod=\"{}\" massDelta=\"{}\" residues=\"{}\">\n",
+                fixed, mass, residues
+            ));
+            // The schema wants the rules before the modification's cvParam
+            if let Some((accession, name)) = rule {
+                out.push_str(&format!(
+                    "          <SpecificityRules>{}</SpecificityRules>\n",
+                    cv(accession, name)
+                ));
+            }
+            out.push_str(&format!(
+                "          {}\n        </SearchModification>\n",
+                mod_cv(*mass)
+            ));
+        }
+    }
+    out
+}
+
+/// Write `results.mzid`; returns the number of spectra.
+pub fn write_mzid(dir: &Path) -> Result<usize, String> {
+    let settings = settings(dir)?;
+    let search = Search::new(&settings);
+    let spectra = spectra(results::read_psms(dir)?);
+
+    // Sequence collection, everything referenced by index
+    let mut proteins: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut peptides: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut evidence: BTreeMap<(&str, &str), (usize, bool)> = BTreeMap::new();
+    let mut files: Vec<String> = Vec::new();
+    for psm in spectra.iter().flatten() {
+        let n = peptides.len();
+        peptides.entry(&psm.peptide).or_insert(n);
+        for accession in accessions(&psm.proteins) {
+            let n = proteins.len();
+            proteins.entry(accession).or_insert(n);
+            let n = evidence.len();
+            evidence
+                .entry((&psm.peptide, accession))
+                .or_insert((n, !psm.is_target()));
+        }
+        if !files.contains(&psm.filename) {
+            files.push(psm.filename.clone());
+        }
+    }
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<MzIdentML id=\"sage_results\" version=\"1.2.0\" creationDate=\"{}\" xmlns=\"http://psidev.info/psi/pi/mzIdentML/1.2\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://psidev.info/psi/pi/mzIdentML/1.2 https://raw.githubusercontent.com/HUPO-PSI/mzIdentML/master/schema/mzIdentML1.2.0.xsd\">
+  <cvList>
+    <cv id=\"PSI-MS\" fullName=\"PSI-MS\" uri=\"https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo\"/>
+    <cv id=\"UNIMOD\" fullName=\"UNIMOD\" uri=\"http://www.unimod.org/obo/unimod.obo\"/>
+    <cv id=\"UO\" fullName=\"UNIT-ONTOLOGY\" uri=\"https://raw.githubusercontent.com/bio-ontology-research-group/unit-ontology/master/unit.obo\"/>
+  </cvList>
+  <AnalysisSoftwareList>
+    <AnalysisSoftware id=\"sage\" name=\"Sage\" version=\"{}\">
+      <SoftwareName><userParam name=\"Sage\"/></SoftwareName>
+    </AnalysisSoftware>
+    <AnalysisSoftware id=\"sagegui\" name=\"sagegui\" version=\"{}\">
+      <SoftwareName><userParam name=\"sagegui\"/></SoftwareName>
+    </AnalysisSoftware>
+  </AnalysisSoftwareList>
+  <SequenceCollection>
+",
+        Local::now().format("%Y-%m-%dT%H:%M:%S"),
+        escape(&search.version),
+        env!("CARGO_PKG_VERSION")
+    ));
+    for (accession, i) in &proteins {
+        out.push_str(&format!(
+            "    <DBSequence id=\"DBSeq_{}\" accession=\"{}\" searchDatabase_ref=\"SDB_1\"/>\n",
+            i,
+            escape(accession)
+        ));
+    }
+    for (peptide, i) in &peptides {
+        let (sequence, mods) = parse_peptide(peptide);
+        out.push_str(&format!(
+            "    <Peptide id=\"PEP_{}\">\n      <PeptideSequence>{}</PeptideSequence>\n",
+            i, sequence
+        ));
+        for (position, mass) in mods {
+            let residue = match position {
+                0 => None,
+                p => sequence.chars().nth(p - 1),
+            };
+            let residues = residue
+                .map(|r| format!(" residues=\"{}\"", r))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "      <Modification location=\"{}\" monoisotopicMassDelta=\"{}\"{}>{}</Modification>\n",
+                position,
+                mass,
+                residues,
+                mod_cv(mass)
+            ));
+        }
+        out.push_str("    </Peptide>\n");
+    }
+    for ((peptide, accession), (i, decoy)) in &evidence {
+        out.push_str(&format!(
+            "    <PeptideEvidence id=\"PE_{}\" peptide_ref=\"PEP_{}\" dBSequence_ref=\"DBSeq_{}\" isDecoy=\"{}\"/>\n",
+            i, peptides[peptide], proteins[accession], decoy
+        ));
+    }
+    out.push_str("  </SequenceCollection>\n  <AnalysisCollection>\n");
+    out.push_str("    <SpectrumIdentification id=\"SI_1\" spectrumIdentificationProtocol_ref=\"SIP_1\" spectrumIdentificationList_ref=\"SIL_1\">\n");
+    for i in 0..files.len() {
+        out.push_str(&format!(
+            "      <InputSpectra spectraData_ref=\"SD_{}\"/>\n",
+            i + 1
+        ));
+    }
+    out.push_str("      <SearchDatabaseRef searchDatabase_ref=\"SDB_1\"/>\n    </SpectrumIdentification>\n  </AnalysisCollection>\n");
+
+    // Search protocol
+    out.push_str(&format!(
+        "  <AnalysisProtocolCollection>
+    <SpectrumIdentificationProtocol id=\"SIP_1\" analysisSoftware_ref=\"sage\">
+      <SearchType>{}</SearchType>
+      <AdditionalSearchParams>
+        {}
+        {}
+        <userParam name=\"score_type\" value=\"{}\"/>
+        <userParam name=\"decoy_tag\" value=\"{}\"/>
+      </AdditionalSearchParams>
+      <ModificationParams>
+",
+        cv("MS:1001083", "ms-ms search"),
+        cv("MS:1001211", "parent mass type mono"),
+        cv("MS:1001256", "fragment mass type mono"),
+        search.score,
+        escape(&search.decoy_tag)
+    ));
+    out.push_str(&search_modifications(&search));
+    let enzyme_name = match search.enzyme_name() {
+        Some((name, accession)) => cv(accession, name),
+        None => format!(
+            "<userParam name=\"cleave at {}\"/>",
+            escape(&search.cleave_at)
+        ),
+    };
+    out.push_str(&format!(
+        "      </ModificationParams>
+      <Enzymes>
+        <Enzyme id=\"ENZ_1\" missedCleavages=\"{}\" semiSpecific=\"{}\">
+          <SiteRegexp><![CDATA[{}]]></SiteRegexp>
+          <EnzymeName>{}</EnzymeName>
+        </Enzyme>
+      </Enzymes>
+",
+        search.missed_cleavages,
+        search.semi_enzymatic,
+        search.site_regexp(),
+        enzyme_name
+    ));
+    for (element, tolerance) in [
+        ("FragmentTolerance", &search.fragment_tol),
+        ("ParentTolerance", &search.precursor_tol),
+    ] {
+        if let Some(tolerance) = tolerance {
+            out.push_str(&format!(
+                "      <{}>\n        {}\n      </{}>\n",
+                element,
+                tolerance_cvs(tolerance),
+                element
+            ));
+        }
+    }
+    out.push_str(&format!(
+        "      <Threshold>{}</Threshold>\n    </SpectrumIdentificationProtocol>\n  </AnalysisProtocolCollection>\n",
+        cv_value("MS:1001448", "pep:FDR threshold", Q_VALUE)
+    ));
+
+    // Inputs
+    out.push_str(&format!(
+        "  <DataCollection>
+    <Inputs>
+      <SearchDatabase id=\"SDB_1\" location=\"{}\">
+        <FileFormat>{}</FileFormat>
+        <DatabaseName><userParam name=\"{}\"/></DatabaseName>
+      </SearchDatabase>
+",
+        escape(&location(&search.fasta)),
+        cv("MS:1001348", "FASTA format"),
+        escape(&stem(&search.fasta))
+    ));
+    for (i, file) in files.iter().enumerate() {
+        let example = spectra
+            .iter()
+            .flatten()
+            .find(|psm| psm.filename == *file)
+            .map(|psm| psm.scannr.as_str())
+            .unwrap_or_default();
+        let (accession, name) = psi::id_format(example);
+        let format = match file.to_lowercase().ends_with(".mzml") {
+            true => cv("MS:1000584", "mzML format"),
+            false => cv("MS:1000560", "mass spectrometer file format"),
+        };
+        out.push_str(&format!(
+            "      <SpectraData id=\"SD_{}\" location=\"{}\" name=\"{}\">
+        <FileFormat>{}</FileFormat>
+        <SpectrumIDFormat>{}</SpectrumIDFormat>
+      </SpectraData>
+",
+            i + 1,
+            escape(&search.location(file)),
+            escape(&stem(file)),
+            format,
+            cv(accession, name)
+        ));
+    }
+    out.push_str(
+        "    </Inputs>\n    <AnalysisData>\n      <SpectrumIdentificationList id=\"SIL_1\">\n",
+    );
+
+    for (i, hits) in spectra.iter().enumerate() {
+        let first = &hits[0];
+        let data = files.iter().position(|f| *f == first.filename).unwrap_or(0) + 1;
+        let spectrum_id = match first.scannr.contains('=') {
+            true => first.scannr.clone(),
+            false => format!("scan={}", first.scannr),
+        };
+        out.push_str(&format!(
+            "        <SpectrumIdentificationResult id=\"SIR_{}\" spectrumID=\"{}\" spectraData_ref=\"SD_{}\">\n",
+            i,
+            escape(&spectrum_id),
+            data
+        ));
+        // Chimeric and wide window searches can give several hits the same rank, so
+        // IDs use the position of the hit instead
+        for (j, psm) in hits.iter().enumerate() {
+            let charge = psm.charge.unwrap_or(0);
+            out.push_str(&format!(
+                "          <SpectrumIdentificationItem id=\"SII_{}_{}\" rank=\"{}\" chargeState=\"{}\" experimentalMassToCharge=\"{}\" calculatedMassToCharge=\"{}\" peptide_ref=\"PEP_{}\" passThreshold=\"{}\">\n",
+                i,
+                j + 1,
+                psm.rank,
+                charge,
+                mass_to_charge(psm.expmass, charge),
+                mass_to_charge(psm.calcmass, charge),
+                peptides[psm.peptide.as_str()],
+                psm.spectrum_q <= Q_VALUE
+            ));
+            for accession in accessions(&psm.proteins) {
+                out.push_str(&format!(
+                    "            <PeptideEvidenceRef peptideEvidence_ref=\"PE_{}\"/>\n",
+                    evidence[&(psm.peptide.as_str(), accession)].0
+                ));
+            }
+            for param in [
+                cv_value("MS:1002354", "PSM-level q-value", psm.spectrum_q),
+                cv_value(
+                    "MS:1001868",
+                    "distinct peptide-level q-value",
+                    psm.peptide_q,
+                ),
+                user_value(search.score, psm.hyperscore),
+                user_value("sage_discriminant_score", psm.discriminant),
+                user_value("protein_q", psm.protein_q),
+            ] {
+                out.push_str(&format!("            {}\n", param));
+            }
+            out.push_str("          </SpectrumIdentificationItem>\n");
+        }
+        if let Some(rt) = first.rt {
+            out.push_str(&format!(
+                "          <cvParam cvRef=\"PSI-MS\" accession=\"MS:1000016\" name=\"scan start time\" value=\"{}\" unitCvRef=\"UO\" unitAccession=\"UO:0000010\" unitName=\"second\"/>\n",
+                rt * 60.0
+            ));
+        }
+        out.push_str("        </SpectrumIdentificationResult>\n");
+    }
+    out.push_str("      </SpectrumIdentificationList>\n    </AnalysisData>\n  </DataCollection>\n</MzIdentML>\n");
+
+    fs::write(dir.join(MZID_FILE_NAME), out).map_err(|e| e.to_string())?;
+    Ok(spectra.len())
+}
+
+/// `modification_info` of a pepXML search hit; masses include the residue (or the
+/// terminal group).
+fn modification_info(peptide: &str) -> String {
+    let (sequence, mods) = parse_peptide(peptide);
+    if mods.is_empty() {
+        return String::new();
+    }
+    let length = sequence.len();
+    let mut attributes = String::new();
+    let mut residues = String::new();
+    let mut by_position: HashMap<usize, f64> = HashMap::new();
+    for (position, mass) in mods {
+        *by_position.entry(position).or_default() += mass;
+    }
+    let mut positions: Vec<(usize, f64)> = by_position.into_iter().collect();
+    positions.sort_by_key(|(position, _)| *position);
+    for (position, mass) in positions {
+        if position == 0 {
+            attributes.push_str(&format!(" mod_nterm_mass=\"{:.6}\"", HYDROGEN + mass));
+        } else if position > length {
+            attributes.push_str(&format!(" mod_cterm_mass=\"{:.6}\"", HYDROXYL + mass));
+        } else {
+            let residue = sequence.chars().nth(position - 1).and_then(residue_mass);
+            residues.push_str(&format!(
+                "\n          <mod_aminoacid_mass position=\"{}\" mass=\"{:.6}\"/>",
+                position,
+                residue.unwrap_or(0.0) + mass
+            ));
+        }
+    }
+    format!(
+        "\n        <modification_info modified_peptide=\"{}\"{}>{}\n        </modification_info>",
+        escape(peptide),
+        attributes,
+        residues
+    )
+}
+
+/// `aminoacid_modification` and `terminal_modification` elements of the search summary,
+/// all of the first kind before the second as the schema's sequence requires.
+fn pepxml_mods(search: &Search) -> String {
+    let (mut aminoacids, mut terminals) = (String::new(), String::new());
+    for (variable, mods) in [("N", &search.fixed), ("Y", &search.variable)] {
+        for (key, mass) in mods {
+            let (site, position) = mod_site(key);
+            let protein = match position {
+                "Protein N-term" | "Protein C-term" => "Y",
+                _ => "N",
+            };
+            match (site.as_str(), position) {
+                ("N-term", _) => terminals.push_str(&format!(
+                    "    <terminal_modification terminus=\"n\" massdiff=\"{}\" mass=\"{:.6}\" variable=\"{}\" protein_terminus=\"{}\"/>\n",
+                    mass, HYDROGEN + mass, variable, protein
+                )),
+                ("C-term", _) => terminals.push_str(&format!(
+                    "    <terminal_modification terminus=\"c\" massdiff=\"{}\" mass=\"{:.6}\" variable=\"{}\" protein_terminus=\"{}\"/>\n",
+                    mass, HYDROXYL + mass, variable, protein
+                )),
+                (residue, position) => {
+                    let terminus = match position {
+                        "Any N-term" | "Protein N-term" => " peptide_terminus=\"n\"",
+                        "Any C-term" | "Protein C-term" => " peptide_terminus=\"c\"",
+                        _ => "",
+                    };
+                    for aminoacid in residue.chars() {
+                        let base = residue_mass(aminoacid).unwrap_or(0.0);
+                        aminoacids.push_str(&format!(
+                            "    <aminoacid_modification aminoacid=\"{}\" massdiff=\"{}\" mass=\"{:.6}\" variable=\"{}\"{}/>\n",
+                            aminoacid, mass, base + mass, variable, terminus
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    aminoacids + &terminals
+}
+
+/// Write `results.pep.xml`, one `msms_run_summary` per input file; returns the number
+/// of spectra. Decoy hits are flagged with a `decoy` parameter and their tagged
+/// protein names.
+pub fn write_pepxml(dir: &Path) -> Result<usize, String> {
+    let settings = settings(dir)?;
+    let search = Search::new(&settings);
+    let spectra = spectra(results::read_psms(dir)?);
+    let enzyme = search
+        .enzyme_name()
+        .map(|(name, _)| name.to_lowercase())
+        .unwrap_or_else(|| "custom".to_string());
+    let sense = match search.c_terminal {
+        true => "C",
+        false => "N",
+    };
+    let no_cut = search
+        .restrict
+        .as_ref()
+        .map(|r| format!(" no_cut=\"{}\"", r))
+        .unwrap_or_default();
+    let termini = match search.semi_enzymatic {
+        true => 1,
+        false => 2,
+    };
+    let mods = pepxml_mods(&search);
+    let mut parameters = vec![
+        ("score_type".to_string(), search.score.to_string()),
+        ("decoy_tag".to_string(), search.decoy_tag.clone()),
+    ];
+    for (name, tolerance) in [
+        ("precursor_tolerance", &search.precursor_tol),
+        ("fragment_tolerance", &search.fragment_tol),
+    ] {
+        if let Some((low, high, unit)) = tolerance {
+            parameters.push((name.to_string(), format!("{} to {} {}", low, high, unit)));
+        }
+    }
+
+    let path = dir.join(PEPXML_FILE_NAME);
+    let mut out = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<msms_pipeline_analysis date=\"{}\" xmlns=\"http://regis-web.systemsbiology.net/pepXML\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://regis-web.systemsbiology.net/pepXML http://sashimi.sourceforge.net/schema_revision/pepXML/pepXML_v122.xsd\" summary_xml=\"{}\">
+",
+        Local::now().format("%Y-%m-%dT%H:%M:%S"),
+        escape(&path.display().to_string())
+    );
+
+    let mut index = 0;
+    let mut run: Option<&str> = None;
+    for hits in &spectra {
+        let first = &hits[0];
+        if run != Some(first.filename.as_str()) {
+            if run.is_some() {
+                out.push_str("</msms_run_summary>\n");
+            }
+            run = Some(&first.filename);
+            let location = search.location(&first.filename);
+            let path = location.strip_prefix("file://").unwrap_or(&location);
+            let base_name = path
+                .strip_suffix(".mzML")
+                .or_else(|| path.strip_suffix(".mzml"))
+                .or_else(|| path.strip_suffix(".d"))
+                .unwrap_or(path);
+            let extension = &path[base_name.len()..];
+            out.push_str(&format!(
+                "<msms_run_summary base_name=\"{base}\" raw_data_type=\"raw\" raw_data=\"{extension}\">
+  <sample_enzyme name=\"{enzyme}\">
+    <specificity cut=\"{cut}\"{no_cut} sense=\"{sense}\"/>
+  </sample_enzyme>
+  <search_summary base_name=\"{base}\" search_engine=\"Sage\" search_engine_version=\"{version}\" precursor_mass_type=\"monoisotopic\" fragment_mass_type=\"monoisotopic\" search_id=\"1\">
+    <search_database local_path=\"{fasta}\" type=\"AA\"/>
+    <enzymatic_search_constraint enzyme=\"{enzyme}\" max_num_internal_cleavages=\"{missed}\" min_number_termini=\"{termini}\"/>
+{mods}",
+                base = escape(base_name),
+                extension = escape(extension),
+                enzyme = escape(&enzyme),
+                cut = escape(&search.cleave_at),
+                version = escape(&search.version),
+                fasta = escape(&search.fasta),
+                missed = search.missed_cleavages,
+            ));
+            for (name, value) in &parameters {
+                out.push_str(&format!(
+                    "    <parameter name=\"{}\" value=\"{}\"/>\n",
+                    name,
+                    escape(value)
+                ));
+            }
+            out.push_str("  </search_summary>\n");
+        }
+
+        index += 1;
+        let charge = first.charge.unwrap_or(0);
+        let scan = scan_number(&first.scannr).unwrap_or(index);
+        let rt = first
+            .rt
+            .map(|rt| format!(" retention_time_sec=\"{}\"", rt * 60.0))
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "  <spectrum_query spectrum=\"{stem}.{scan}.{scan}.{charge}\" spectrumNativeID=\"{native}\" start_scan=\"{scan}\" end_scan=\"{scan}\" precursor_neutral_mass=\"{mass}\" assumed_charge=\"{charge}\" index=\"{index}\"{rt}>\n    <search_result>\n",
+            stem = escape(&stem(&first.filename)),
+            native = escape(&first.scannr),
+            mass = first.expmass,
+        ));
+        for psm in hits {
+            let (sequence, _) = parse_peptide(&psm.peptide);
+            let proteins: Vec<&str> = accessions(&psm.proteins).collect();
+            let alternatives: String = proteins
+                .iter()
+                .skip(1)
+                .map(|p| format!("\n        <alternative_protein protein=\"{}\"/>", escape(p)))
+                .collect();
+            out.push_str(&format!(
+                "      <search_hit hit_rank=\"{}\" peptide=\"{}\" protein=\"{}\" num_tot_proteins=\"{}\" calc_neutral_pep_mass=\"{}\" massdiff=\"{:.6}\" is_rejected=\"0\">{}{}\n",
+                psm.rank,
+                sequence,
+                escape(proteins.first().copied().unwrap_or_default()),
+                proteins.len(),
+                psm.calcmass,
+                psm.expmass - psm.calcmass,
+                alternatives,
+                modification_info(&psm.peptide)
+            ));
+            for (name, value) in [
+                (search.score, psm.hyperscore),
+                ("sage_discriminant_score", psm.discriminant),
+                ("spectrum_q", psm.spectrum_q),
+                ("peptide_q", psm.peptide_q),
+                ("protein_q", psm.protein_q),
+            ] {
+                out.push_str(&format!(
+                    "        <search_score name=\"{}\" value=\"{}\"/>\n",
+                    name, value
+                ));
+            }
+            out.push_str(&format!(
+                "        <parameter name=\"decoy\" value=\"{}\"/>\n      </search_hit>\n",
+                u8::from(!psm.is_target())
+            ));
+        }
+        out.push_str("    </search_result>\n  </spectrum_query>\n");
+    }
+    if run.is_some() {
+        out.push_str("</msms_run_summary>\n");
+    }
+    out.push_str("</msms_pipeline_analysis>\n");
+
+    fs::write(&path, out).map_err(|e| e.to_string())?;
+    Ok(spectra.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A TMTpro search: fixed on K and the peptide N-terminus, variable oxidation.
+    fn tmt_search() -> Search {
+        Search::new(&json!({
+            "database": {
+                "static_mods": {"K": 304.207146, "^": 304.207146},
+                "variable_mods": {"M": [15.994915]},
+            }
+        }))
+    }
+
+    #[test]
+    fn specificity_rules_come_before_the_modification() {
+        let xml = search_modifications(&tmt_search());
+        let terminal = xml
+            .split("</SearchModification>")
+            .find(|m| m.contains("residues=\".\""))
+            .unwrap();
+        let rules = terminal.find("<SpecificityRules>").unwrap();
+        let modification = terminal.find("accession=\"UNIMOD:2016\"").unwrap();
+        assert!(rules < modification, "{}", terminal);
+    }
+
+    #[test]
+    fn pepxml_aminoacid_modifications_come_first() {
+        let xml = pepxml_mods(&tmt_search());
+        let kinds: Vec<&str> = xml
+            .lines()
+            .map(|line| line.trim_start().split(' ').next().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "<aminoacid_modification",
+                "<aminoacid_modification",
+                "<terminal_modification",
+            ]
+        );
+        assert!(xml
+            .contains("aminoacid=\"M\" massdiff=\"15.994915\" mass=\"147.035400\" variable=\"Y\""));
+    }
+
+    #[test]
+    fn hits_sharing_a_rank_get_their_own_ids() {
+        let dir = std::env::temp_dir().join(format!("sagegui_mzid_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let settings = json!({
+            "database": {"static_mods": {"C": 57.021464}, "variable_mods": {}},
+            "mzml_paths": ["/data/run.mzML"],
+        });
+        fs::write(dir.join("results.json"), settings.to_string()).unwrap();
+        let mut tsv = "psm_id\tpeptide\tproteins\tfilename\tscannr\tcharge\trank\tlabel\texpmass\tcalcmass\thyperscore\tspectrum_q\tpeptide_q\tprotein_q\n".to_string();
+        for (id, peptide) in [(1, "PEPTIDEK"), (2, "PEPC[+57.0215]IDER")] {
+            tsv.push_str(&format!(
+                "{}\t{}\tsp|P1|A\trun.mzML\tscan=7\t2\t1\t1\t1000.5\t1000.5\t30\t0.001\t0.001\t0.001\n",
+                id, peptide
+            ));
+        }
+        fs::write(dir.join(results::RESULTS_FILE_NAME), tsv).unwrap();
+
+        assert_eq!(write_mzid(&dir), Ok(1));
+        let xml = fs::read_to_string(dir.join(MZID_FILE_NAME)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(xml.contains("id=\"SII_0_1\" rank=\"1\""));
+        assert!(xml.contains("id=\"SII_0_2\" rank=\"1\""));
+        let mut ids: Vec<&str> = xml
+            .split(" id=\"")
+            .skip(1)
+            .filter_map(|rest| rest.split('"').next())
+            .collect();
+        ids.sort();
+        let duplicates: Vec<&&str> = ids
+            .windows(2)
+            .filter(|w| w[0] == w[1])
+            .map(|w| &w[0])
+            .collect();
+        assert!(duplicates.is_empty(), "duplicate IDs {:?}", duplicates);
+    }
+}
@@ -0,0 +1,342 @@
+/// Isotopic impurity correction of TMT reporter ions.
+///
+/// Kit lot sheets give, for every channel, the percentage of its reporter that shows
+/// up 2 and 1 Da lighter and 1 and 2 Da heavier (13C shifts). That defines how true
+/// reporter signal mixes into the observed channels; the true signal is recovered per
+/// spectrum by non-negative least squares so no channel ends up below zero.
+use crate::quant::TMT_FILE_NAME;
+use crate::results::{field, Tsv};
+use eframe::egui;
+use rfd::FileDialog;
+use std::fs;
+use std::path::Path;
+
+pub const CORRECTED_FILE_NAME: &str = "tmt.corrected.tsv";
+/// Isotope shifts of the lot sheet columns, in Da.
+const SHIFTS: [i32; 4] = [-2, -1, 1, 2];
+const SHIFT_LABELS: [&str; 4] = ["-2", "-1", "+1", "+2"];
+
+/// Impurity percentages per channel, in the order of `SHIFTS`.
+#[derive(Debug, Clone, Default)]
+pub struct ImpurityTable {
+    pub channels: Vec<String>,
+    pub percent: Vec<[f64; 4]>,
+}
+
+impl ImpurityTable {
+    /// A table of zeros for these channels, keeping entries for channels it already had.
+    fn resize(&mut self, channels: &[String]) {
+        if self.channels == channels {
+            return;
+        }
+        let percent = channels
+            .iter()
+            .map(|channel| {
+                self.channels
+                    .iter()
+                    .position(|c| c == channel)
+                    .map(|i| self.percent[i])
+                    .unwrap_or_default()
+            })
+            .collect();
+        self.channels = channels.to_vec();
+        self.percent = percent;
+    }
+
+    /// `channel,-2,-1,+1,+2`, with or without a header line; channels are matched by
+    /// label.
+    pub fn read_csv(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut updated = 0;
+        for (n, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split([',', '\t', ';']).map(str::trim).collect();
+            if fields.len() < 5 || fields[0].is_empty() {
+                continue;
+            }
+            let channel = fields[0].trim_start_matches("tmt_");
+            let Some(i) = self.channels.iter().position(|c| c == channel) else {
+                // A first line that names no channel is the header
+                if n == 0 {
+                    continue;
+                }
+                return Err(format!("Line {}: unknown channel '{}'", n + 1, channel));
+            };
+            for (k, value) in fields[1..5].iter().enumerate() {
+                self.percent[i][k] = value
+                    .parse()
+                    .map_err(|_| format!("Line {}: '{}' is not a number", n + 1, value))?;
+            }
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<(), String> {
+        let mut out = format!("channel,{}\n", SHIFT_LABELS.join(","));
+        for (channel, percent) in self.channels.iter().zip(&self.percent) {
+            let values: Vec<String> = percent.iter().map(|p| p.to_string()).collect();
+            out.push_str(&format!("{},{}\n", channel, values.join(",")));
+        }
+        fs::write(path, out).map_err(|e| e.to_string())
+    }
+
+    /// Column `i` is where the signal of channel `i` ends up; columns sum to one.
+    ///
+    /// TMT6 channels are 1 Da apart, the N/C pairs of the higher plexes put the next
+    /// 13C isotope two channels over.
+    fn mixing(&self) -> Vec<Vec<f64>> {
+        let n = self.channels.len();
+        let step = match self
+            .channels
+            .iter()
+            .any(|c| c.ends_with('N') || c.ends_with('C'))
+        {
+            true => 2,
+            false => 1,
+        };
+        let mut matrix = vec![vec![0.0; n]; n];
+        for (i, percent) in self.percent.iter().enumerate() {
+            let impurity: f64 = percent.iter().sum::<f64>() / 100.0;
+            matrix[i][i] = 1.0 - impurity;
+            for (shift, p) in SHIFTS.iter().zip(percent) {
+                let j = i as i32 + shift * step;
+                if (0..n as i32).contains(&j) {
+                    matrix[j as usize][i] += p / 100.0;
+                }
+            }
+        }
+        matrix
+    }
+}
+
+/// Non-negative least squares by cyclic coordinate descent on the normal equations.
+/// Fine for the at most 18 channels of a spectrum.
+fn nnls(a: &[Vec<f64>], y: &[f64]) -> Vec<f64> {
+    let n = y.len();
+    // Gram matrix and A^T y
+    let gram: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| (0..n).map(|k| a[k][i] * a[k][j]).sum())
+                .collect()
+        })
+        .collect();
+    let aty: Vec<f64> = (0..n)
+        .map(|i| (0..n).map(|k| a[k][i] * y[k]).sum())
+        .collect();
+
+    let mut x = y.to_vec();
+    let scale = y.iter().fold(0.0f64, |m, v| m.max(v.abs())).max(1.0);
+    for _ in 0..500 {
+        let mut change = 0.0f64;
+        for i in 0..n {
+            if gram[i][i] <= 0.0 {
+                continue;
+            }
+            let gradient: f64 = (0..n).map(|j| gram[i][j] * x[j]).sum::<f64>() - aty[i];
+            let updated = (x[i] - gradient / gram[i][i]).max(0.0);
+            change = change.max((updated - x[i]).abs());
+            x[i] = updated;
+        }
+        if change < 1e-9 * scale {
+            break;
+        }
+    }
+    x
+}
+
+/// Write `tmt.corrected.tsv` next to `tmt.tsv`, same layout with corrected reporters.
+/// Reporters that were missing stay missing.
+pub fn correct(dir: &Path, table: &ImpurityTable) -> Result<usize, String> {
+    let tsv = Tsv::open(&dir.join(TMT_FILE_NAME))?;
+    let header = tsv.header();
+    let columns: Vec<usize> = table
+        .channels
+        .iter()
+        .map(|channel| tsv.column(&format!("tmt_{}", channel)))
+        .collect::<Result<_, _>>()?;
+    let mixing = table.mixing();
+
+    let mut out = header.join("\t");
+    out.push('\n');
+    let mut rows = 0;
+    for mut row in tsv {
+        let observed: Vec<Option<f64>> = columns.iter().map(|&i| field::<f64>(&row, i)).collect();
+        let y: Vec<f64> = observed.iter().map(|v| v.unwrap_or(0.0)).collect();
+        let corrected = nnls(&mixing, &y);
+        for ((&i, value), observed) in columns.iter().zip(corrected).zip(&observed) {
+            if observed.is_some() {
+                if let Some(cell) = row.get_mut(i) {
+                    *cell = format!("{:.2}", value);
+                }
+            }
+        }
+        out.push_str(&row.join("\t"));
+        out.push('\n');
+        rows += 1;
+    }
+    fs::write(dir.join(CORRECTED_FILE_NAME), out).map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+#[derive(Default)]
+pub struct ImpurityCorrection {
+    table: ImpurityTable,
+    message: String,
+}
+
+impl ImpurityCorrection {
+    /// `channels` are the reporter channels of the loaded `tmt.tsv`.
+    pub fn update_section(&mut self, ui: &mut egui::Ui, dir: &Path, channels: &[String]) {
+        self.table.resize(channels);
+        ui.label("Percentages from the lot-specific product data sheet of the TMT kit.");
+        egui::Grid::new("tmt_impurities")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Channel");
+                for label in SHIFT_LABELS {
+                    ui.strong(format!("{} Da (%)", label));
+                }
+                ui.end_row();
+                for (channel, percent) in self.table.channels.iter().zip(&mut self.table.percent) {
+                    ui.label(channel);
+                    for p in percent.iter_mut() {
+                        ui.add(egui::DragValue::new(p).speed(0.05).range(0.0..=50.0));
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button("Load CSV").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("CSV", &["csv", "tsv", "txt"])
+                    .pick_file()
+                {
+                    self.message = match self.table.read_csv(&path) {
+                        Ok(n) => format!("Loaded impurities for {} channels", n),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            if ui.button("Save CSV").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("tmt_impurities.csv")
+                    .save_file()
+                {
+                    self.message = match self.table.write_csv(&path) {
+                        Ok(_) => format!("Saved to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            if ui.button("Write Corrected Reporters").clicked() {
+                self.message = match correct(dir, &self.table) {
+                    Ok(n) => format!("Corrected {} spectra into {}", n, CORRECTED_FILE_NAME),
+                    Err(e) => format!("Error: {}", e),
+                };
+            }
+        });
+        ui.label(&self.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(channels: &[&str]) -> ImpurityTable {
+        let mut table = ImpurityTable::default();
+        table.resize(&channels.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+        table
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn tmt6_channels_are_one_step_apart() {
+        let mut table = table(&["126", "127", "128", "129", "130", "131"]);
+        table.percent[2] = [1.0, 2.0, 3.0, 4.0];
+        let mixing = table.mixing();
+        let column: Vec<f64> = mixing.iter().map(|row| row[2]).collect();
+        assert_close(&column, &[0.01, 0.02, 0.9, 0.03, 0.04, 0.0]);
+    }
+
+    #[test]
+    fn n_and_c_channels_are_two_steps_apart() {
+        let mut table = table(&[
+            "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131",
+        ]);
+        table.percent[4] = [1.0, 2.0, 3.0, 4.0];
+        let mixing = table.mixing();
+        let column: Vec<f64> = mixing.iter().map(|row| row[4]).collect();
+        assert_close(
+            &column,
+            &[0.01, 0.0, 0.02, 0.0, 0.9, 0.0, 0.03, 0.0, 0.04, 0.0],
+        );
+    }
+
+    #[test]
+    fn impurities_past_the_edge_channels_are_lost() {
+        let mut table = table(&["126", "127", "128"]);
+        table.percent[0] = [1.0, 2.0, 3.0, 4.0];
+        table.percent[2] = [1.0, 2.0, 3.0, 4.0];
+        let mixing = table.mixing();
+        let first: Vec<f64> = mixing.iter().map(|row| row[0]).collect();
+        let last: Vec<f64> = mixing.iter().map(|row| row[2]).collect();
+        assert_close(&first, &[0.9, 0.03, 0.04]);
+        assert_close(&last, &[0.01, 0.02, 0.9]);
+    }
+
+    #[test]
+    fn nnls_recovers_a_non_negative_signal() {
+        let mut table = table(&["126", "127N", "127C", "128N", "128C", "129N"]);
+        for percent in &mut table.percent {
+            *percent = [0.5, 1.5, 4.0, 0.8];
+        }
+        let mixing = table.mixing();
+        let signal = [1000.0, 0.0, 250.0, 4000.0, 0.0, 80.0];
+        let observed: Vec<f64> = (0..signal.len())
+            .map(|i| (0..signal.len()).map(|j| mixing[i][j] * signal[j]).sum())
+            .collect();
+        let recovered = nnls(&mixing, &observed);
+        for (r, s) in recovered.iter().zip(signal) {
+            assert!((r - s).abs() < 1e-3, "{:?}", recovered);
+        }
+    }
+
+    #[test]
+    fn nnls_clamps_at_zero() {
+        // Unconstrained least squares would give [-0.5, 1]
+        let a = vec![vec![1.0, 0.5], vec![0.0, 1.0]];
+        assert_close(&nnls(&a, &[0.0, 1.0]), &[0.0, 0.8]);
+    }
+
+    #[test]
+    fn lot_sheet_with_and_without_header() {
+        let path =
+            std::env::temp_dir().join(format!("sagegui_impurities_{}.csv", std::process::id()));
+        let mut table = table(&["126", "127"]);
+
+        fs::write(&path, "126,0,0,5,0.1\ntmt_127,0.2,1,4,0\n").unwrap();
+        assert_eq!(table.read_csv(&path), Ok(2));
+        assert_eq!(
+            table.percent,
+            vec![[0.0, 0.0, 5.0, 0.1], [0.2, 1.0, 4.0, 0.0]]
+        );
+
+        fs::write(&path, "channel,-2,-1,+1,+2\n127,0,0,3,0\n").unwrap();
+        assert_eq!(table.read_csv(&path), Ok(1));
+        assert_eq!(table.percent[1], [0.0, 0.0, 3.0, 0.0]);
+
+        fs::write(&path, "126,0,0,5,0.1\n125,0,0,5,0.1\n").unwrap();
+        assert!(table.read_csv(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
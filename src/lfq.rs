@@ -0,0 +1,368 @@
+/// The LFQ tab: a first look at `lfq.tsv` without leaving the GUI.
+use crate::design::Design;
+use crate::plot::{self, Histogram, Marker};
+use crate::processing::{Processing, Source};
+use crate::quant::{self, QuantMatrix};
+use crate::rollup::Rollup;
+use crate::stats;
+use eframe::egui;
+use rfd::FileDialog;
+use std::path::Path;
+
+/// The missing value heatmap shows at most this many bands of peptides.
+const MAX_BANDS: usize = 200;
+/// Peptides listed for the search box.
+const MAX_MATCHES: usize = 500;
+
+/// Everything derived from the matrix that does not depend on UI state.
+struct Overview {
+    log2: Vec<(String, Vec<f64>)>,
+    missing: Vec<f64>,
+    // Fraction missing per band of peptides (sorted by missingness) and sample
+    missing_bands: Vec<Vec<f64>>,
+    correlation: Vec<Vec<Option<f64>>>,
+}
+
+impl Overview {
+    fn new(matrix: &QuantMatrix) -> Self {
+        let samples = 0..matrix.samples.len();
+
+        let mut order: Vec<&quant::QuantRow> = matrix.rows.iter().collect();
+        order.sort_by_key(|row| row.values.iter().filter(|v| v.is_none()).count());
+        let band = order.len().div_ceil(MAX_BANDS).max(1);
+        let missing_bands = order
+            .chunks(band)
+            .map(|rows| {
+                samples
+                    .clone()
+                    .map(|s| {
+                        let missing = rows.iter().filter(|row| row.values[s].is_none()).count();
+                        missing as f64 / rows.len() as f64
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            log2: samples
+                .clone()
+                .map(|s| (matrix.samples[s].clone(), matrix.log2_column(s)))
+                .collect(),
+            missing: samples
+                .clone()
+                .map(|s| matrix.missing_fraction(s))
+                .collect(),
+            missing_bands,
+            correlation: samples
+                .clone()
+                .map(|a| samples.clone().map(|b| matrix.correlation(a, b)).collect())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LfqExplorer {
+    pub results_dir: String,
+    matrix: Option<Result<(QuantMatrix, Overview), String>>,
+    replicates: Vec<bool>,
+    // CVs for the replicate selection they were computed for
+    cvs: Option<(Vec<bool>, Vec<f64>)>,
+    query: String,
+    selected: Option<usize>,
+    message: String,
+    rollup: Rollup,
+    processing: Processing,
+}
+
+fn load(dir: &Path) -> Result<(QuantMatrix, Overview), String> {
+    let matrix = quant::read_lfq(dir)?;
+    let overview = Overview::new(&matrix);
+    Ok((matrix, overview))
+}
+
+/// CV of every peptide quantified in at least two of the selected samples.
+fn cvs(matrix: &QuantMatrix, selected: &[bool]) -> Vec<f64> {
+    matrix
+        .rows
+        .iter()
+        .filter_map(|row| {
+            let values: Vec<f64> = row
+                .values
+                .iter()
+                .zip(selected)
+                .filter(|(_, selected)| **selected)
+                .filter_map(|(value, _)| *value)
+                .collect();
+            stats::cv(&values)
+        })
+        .collect()
+}
+
+/// Peptide by sample matrix, columns named by sample and grouped by condition.
+fn export(path: &Path, matrix: &QuantMatrix, design: &Design) -> Result<(), String> {
+    let order = design.order(&matrix.samples);
+    let columns: Vec<String> = order
+        .iter()
+        .map(|&i| design.column_name(&matrix.samples[i]))
+        .collect();
+    let rows: Vec<(String, Vec<Option<f64>>)> = matrix
+        .rows
+        .iter()
+        .map(|row| (row.label(), order.iter().map(|&i| row.values[i]).collect()))
+        .collect();
+    quant::write_wide(path, "peptide", &columns, &rows)
+}
+
+impl LfqExplorer {
+    /// Files are labelled with their sample in `design` where one is set.
+    pub fn update_section(&mut self, ui: &mut egui::Ui, default_dir: &str, design: &Design) {
+        if self.results_dir.is_empty() {
+            self.results_dir = default_dir.to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Results Folder:");
+            ui.text_edit_singleline(&mut self.results_dir);
+            if ui.button("Browse").clicked() {
+                if let Some(path) = FileDialog::new().pick_folder() {
+                    self.results_dir = path.display().to_string();
+                }
+            }
+            if ui.button("Load lfq.tsv").clicked() {
+                self.matrix = Some(load(Path::new(&self.results_dir)));
+                self.selected = None;
+                self.cvs = None;
+                self.message.clear();
+                self.rollup.clear();
+                self.processing.clear();
+                self.replicates = match &self.matrix {
+                    Some(Ok((matrix, _))) => vec![true; matrix.samples.len()],
+                    _ => Vec::new(),
+                };
+            }
+        });
+
+        // The design can change while results are loaded
+        if let Some(Ok((matrix, overview))) = &mut self.matrix {
+            for ((name, _), file) in overview.log2.iter_mut().zip(&matrix.samples) {
+                *name = design.column_name(file);
+            }
+        }
+        let (matrix, overview) = match &self.matrix {
+            Some(Ok(loaded)) => (&loaded.0, &loaded.1),
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+                return;
+            }
+            None => {
+                ui.label("Load the lfq.tsv sage writes when LFQ is enabled.");
+                return;
+            }
+        };
+        ui.label(format!(
+            "{} peptides across {} files",
+            matrix.rows.len(),
+            matrix.samples.len()
+        ));
+        let names: Vec<String> = overview.log2.iter().map(|(name, _)| name.clone()).collect();
+
+        ui.collapsing("Intensity Distributions", |ui| {
+            plot::boxplots(ui, &overview.log2, "log2 intensity");
+        });
+
+        ui.collapsing("Missing Values", |ui| {
+            for (name, missing) in names.iter().zip(&overview.missing) {
+                ui.label(format!("{}: {:.1}% missing", name, 100.0 * missing));
+            }
+            let bands = &overview.missing_bands;
+            plot::heatmap(ui, bands.len(), names.len(), None, &names, |r, c| {
+                let fraction = bands[r][c];
+                (
+                    plot::sequential(fraction),
+                    format!("{}: {:.0}% missing", names[c], 100.0 * fraction),
+                )
+            });
+            ui.weak("Rows are peptides sorted by the number of files they are missing in (dark = missing).");
+        });
+
+        ui.collapsing("Correlation", |ui| {
+            let correlation = &overview.correlation;
+            plot::heatmap(
+                ui,
+                names.len(),
+                names.len(),
+                Some(&names),
+                &names,
+                |r, c| match correlation[r][c] {
+                    // Correlations of intensities are nearly always positive
+                    Some(rho) => (
+                        plot::diverging(0.5 + rho / 2.0),
+                        format!("{} vs {}: r = {:.3}", names[r], names[c], rho),
+                    ),
+                    None => (
+                        egui::Color32::GRAY,
+                        "Not enough shared peptides".to_string(),
+                    ),
+                },
+            );
+            ui.weak(
+                "Pearson correlation of log2 intensities over peptides quantified in both files.",
+            );
+        });
+
+        ui.collapsing("Replicate CVs", |ui| {
+            ui.label("Files that are replicates of each other:");
+            let conditions = design.conditions();
+            if !conditions.is_empty() {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("Select condition:");
+                    for condition in &conditions {
+                        if ui.small_button(condition).clicked() {
+                            for (selected, file) in self.replicates.iter_mut().zip(&matrix.samples)
+                            {
+                                *selected = design.condition(file) == Some(condition.as_str());
+                            }
+                        }
+                    }
+                });
+            }
+            ui.horizontal_wrapped(|ui| {
+                for (name, selected) in names.iter().zip(self.replicates.iter_mut()) {
+                    ui.checkbox(selected, name);
+                }
+            });
+            if self.cvs.as_ref().map(|(key, _)| key) != Some(&self.replicates) {
+                self.cvs = Some((self.replicates.clone(), cvs(matrix, &self.replicates)));
+            }
+            if let Some((_, cvs)) = &self.cvs {
+                let percent: Vec<f64> = cvs.iter().map(|cv| 100.0 * cv).collect();
+                match stats::median(&percent) {
+                    Some(median) => {
+                        ui.label(format!(
+                            "{} peptides in at least two replicates, median CV {:.1}%",
+                            percent.len(),
+                            median
+                        ));
+                        Histogram::new(&percent, 0.0, 150.0, 75).show(
+                            ui,
+                            "CV (%)",
+                            &[Marker {
+                                x: median,
+                                color: egui::Color32::from_rgb(230, 160, 0),
+                                label: "median".to_string(),
+                            }],
+                        );
+                    }
+                    None => {
+                        ui.weak("Select at least two files with shared peptides.");
+                    }
+                }
+            }
+        });
+
+        ui.collapsing("Proteins", |ui| {
+            let dir = Path::new(&self.results_dir);
+            self.rollup.update_section(ui, matrix, dir, design, &names);
+        });
+
+        ui.collapsing("Normalization and Imputation", |ui| {
+            let dir = Path::new(&self.results_dir);
+            let rollup = &self.rollup;
+            let samples: Vec<String> = matrix
+                .samples
+                .iter()
+                .map(|s| design.column_name(s))
+                .collect();
+            self.processing
+                .update_section(
+                    ui,
+                    &["Peptides", "Proteins"],
+                    &[],
+                    dir,
+                    |level| match level {
+                        0 => Ok(Source {
+                            name: "lfq.peptides".to_string(),
+                            id: "peptide",
+                            matrix: QuantMatrix {
+                                samples,
+                                rows: matrix.rows.clone(),
+                            },
+                        }),
+                        _ => {
+                            let proteins = rollup
+                                .proteins()
+                                .ok_or("Roll up proteins first (Proteins section)")?;
+                            let rows = proteins
+                                .iter()
+                                .map(|row| (row.protein.clone(), row.values.clone()))
+                                .collect();
+                            Ok(Source {
+                                name: "lfq.proteins".to_string(),
+                                id: "protein",
+                                matrix: QuantMatrix::from_rows(samples, rows),
+                            })
+                        }
+                    },
+                );
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Export Peptide Matrix").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv"])
+                    .set_file_name("lfq_peptides.tsv")
+                    .save_file()
+                {
+                    self.message = match export(&path, matrix, design) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            ui.label(&self.message);
+        });
+
+        ui.collapsing("Peptides", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Search:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("peptide sequence or protein accession"),
+                );
+            });
+            let matches: Vec<usize> = matrix
+                .rows
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| row.matches(&self.query))
+                .map(|(i, _)| i)
+                .take(MAX_MATCHES)
+                .collect();
+            if matches.len() == MAX_MATCHES {
+                ui.weak(format!("Showing the first {} matches", MAX_MATCHES));
+            }
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            egui::ScrollArea::vertical()
+                .id_salt("lfq_peptides")
+                .max_height(200.0)
+                .show_rows(ui, row_height, matches.len(), |ui, range| {
+                    for &i in &matches[range] {
+                        let row = &matrix.rows[i];
+                        let text = format!("{}  ({})", row.label(), row.proteins);
+                        if ui
+                            .selectable_label(self.selected == Some(i), text)
+                            .clicked()
+                        {
+                            self.selected = Some(i);
+                        }
+                    }
+                });
+
+            if let Some(row) = self.selected.and_then(|i| matrix.rows.get(i)) {
+                ui.strong(row.label());
+                ui.label(&row.proteins);
+                plot::bars(ui, &names, &row.values, "intensity");
+            }
+        });
+    }
+}
@@ -0,0 +1,483 @@
+/// Spectral libraries for DIA from the confident PSMs of a DDA search.
+///
+/// Every precursor (modified peptide and charge) is represented by its best scoring
+/// spectrum, with the fragments sage annotated in `matched_fragments.sage.tsv` scaled
+/// to the most intense one. Written as an OpenSWATH style TSV, which DIA-NN and
+/// Spectronaut read as well, and as NIST `.msp`.
+///
+/// `RetentionTime` is the median observed retention time in minutes over all confident
+/// PSMs of the precursor, not that of the best spectrum. `NormalizedRetentionTime`
+/// is sage's predicted retention time, on its 0 to 1 scale aligned to the gradient,
+/// the normalized scale those tools expect in that column.
+use crate::psi::{parse_peptide, unimod, PROTON};
+use crate::results::{self, Fragment, Psm, FRAGMENTS_FILE_NAME};
+use crate::stats;
+use eframe::egui;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const TSV_FILE_NAME: &str = "library.tsv";
+pub const MSP_FILE_NAME: &str = "library.msp";
+/// Intensity of the most intense fragment of every spectrum.
+const BASE_PEAK: f64 = 10000.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub q_value: f64,
+    /// Most intense fragments kept per precursor.
+    pub max_fragments: usize,
+    /// Precursors with fewer annotated fragments are left out.
+    pub min_fragments: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            q_value: 0.01,
+            max_fragments: 12,
+            min_fragments: 3,
+        }
+    }
+}
+
+struct Entry<'a> {
+    psm: &'a Psm,
+    /// Median over all confident PSMs of the precursor, in minutes.
+    rt: Option<f64>,
+    fragments: Vec<Fragment>,
+}
+
+impl Entry<'_> {
+    fn precursor_mz(&self) -> f64 {
+        let z = self.psm.charge.unwrap_or(1).max(1) as f64;
+        (self.psm.calcmass + z * PROTON) / z
+    }
+
+    fn ion_mobility(&self) -> Option<f64> {
+        self.psm.ion_mobility.filter(|im| *im > 0.0)
+    }
+}
+
+/// Peptide with modifications as `(UniMod:35)`, the notation DIA tools read; shifts
+/// without a Unimod entry keep sage's `[+mass]`.
+fn unimod_sequence(peptide: &str) -> String {
+    let (sequence, mods) = parse_peptide(peptide);
+    let notation = |mass: f64| match unimod(mass) {
+        Some((accession, _)) => format!("(UniMod:{})", accession),
+        None => format!("[{:+}]", mass),
+    };
+    let mut out = String::new();
+    for &(_, mass) in mods.iter().filter(|(position, _)| *position == 0) {
+        out.push_str(&notation(mass));
+    }
+    for (i, residue) in sequence.chars().enumerate() {
+        out.push(residue);
+        for &(_, mass) in mods.iter().filter(|(position, _)| *position == i + 1) {
+            out.push_str(&notation(mass));
+        }
+    }
+    for &(_, mass) in mods.iter().filter(|(p, _)| *p > sequence.len()) {
+        out.push_str(&notation(mass));
+    }
+    out
+}
+
+/// NIST `Mods=` field: count, then 0-based position, residue and name of each.
+fn msp_mods(peptide: &str) -> String {
+    let (sequence, mods) = parse_peptide(peptide);
+    if mods.is_empty() {
+        return "0".to_string();
+    }
+    let residues: Vec<char> = sequence.chars().collect();
+    let mut out = mods.len().to_string();
+    for (position, mass) in mods {
+        let index = position
+            .saturating_sub(1)
+            .min(residues.len().saturating_sub(1));
+        let name = match unimod(mass) {
+            Some((_, name)) => name.to_string(),
+            None => format!("{:+}", mass),
+        };
+        let residue = residues.get(index).copied().unwrap_or('X');
+        out.push_str(&format!("/{},{},{}", index, residue, name));
+    }
+    out
+}
+
+fn annotation(fragment: &Fragment) -> String {
+    match fragment.charge {
+        1 => format!("{}{}", fragment.kind, fragment.ordinal),
+        z => format!("{}{}^{}", fragment.kind, fragment.ordinal, z),
+    }
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Best spectrum per precursor among the confident target PSMs, with its fragments.
+fn entries<'a>(psms: &'a [Psm], fragments: Vec<Fragment>, settings: &Settings) -> Vec<Entry<'a>> {
+    let mut precursors: BTreeMap<(&str, Option<u8>), Vec<&Psm>> = BTreeMap::new();
+    for psm in psms.iter().filter(|psm| {
+        psm.is_target()
+            && psm.rank == 1
+            && psm.spectrum_q <= settings.q_value
+            && psm.peptide_q <= settings.q_value
+    }) {
+        precursors
+            .entry((psm.peptide.as_str(), psm.charge))
+            .or_default()
+            .push(psm);
+    }
+
+    let best: Vec<(&Psm, Option<f64>)> = precursors
+        .into_values()
+        .filter_map(|psms| {
+            let rts: Vec<f64> = psms.iter().filter_map(|psm| psm.rt).collect();
+            let best = psms
+                .into_iter()
+                .max_by(|a, b| a.hyperscore.total_cmp(&b.hyperscore))?;
+            Some((best, stats::median(&rts)))
+        })
+        .collect();
+    let mut peaks: HashMap<u64, Vec<Fragment>> = best
+        .iter()
+        .map(|(psm, _)| (psm.psm_id, Vec::new()))
+        .collect();
+    for fragment in fragments {
+        if let Some(peaks) = peaks.get_mut(&fragment.psm_id) {
+            peaks.push(fragment);
+        }
+    }
+
+    best.into_iter()
+        .filter_map(|(psm, rt)| {
+            let mut fragments = peaks.remove(&psm.psm_id)?;
+            fragments.sort_by(|a, b| b.intensity.total_cmp(&a.intensity));
+            fragments.truncate(settings.max_fragments);
+            let base = fragments.first()?.intensity;
+            if fragments.len() < settings.min_fragments || base <= 0.0 {
+                return None;
+            }
+            for fragment in &mut fragments {
+                fragment.intensity *= BASE_PEAK / base;
+            }
+            Some(Entry { psm, rt, fragments })
+        })
+        .collect()
+}
+
+const TSV_HEADER: [&str; 15] = [
+    "PrecursorMz",
+    "ProductMz",
+    "Annotation",
+    "ProteinId",
+    "PeptideSequence",
+    "ModifiedPeptideSequence",
+    "PrecursorCharge",
+    "LibraryIntensity",
+    "RetentionTime",
+    "NormalizedRetentionTime",
+    "FragmentType",
+    "FragmentSeriesNumber",
+    "FragmentCharge",
+    "Decoy",
+    "PrecursorIonMobility",
+];
+
+fn write_tsv(path: &Path, entries: &[Entry]) -> Result<(), String> {
+    // Ion mobility only for timsTOF data
+    let mobility = entries.iter().any(|e| e.ion_mobility().is_some());
+    let columns = match mobility {
+        true => &TSV_HEADER[..],
+        false => &TSV_HEADER[..TSV_HEADER.len() - 1],
+    };
+    let mut out = columns.join("\t");
+    out.push('\n');
+    for entry in entries {
+        let psm = entry.psm;
+        let (sequence, _) = parse_peptide(&psm.peptide);
+        let modified = unimod_sequence(&psm.peptide);
+        for fragment in &entry.fragments {
+            let mut fields = vec![
+                entry.precursor_mz().to_string(),
+                fragment.mz_calculated.to_string(),
+                annotation(fragment),
+                psm.proteins.clone(),
+                sequence.clone(),
+                modified.clone(),
+                psm.charge.unwrap_or(0).to_string(),
+                format!("{:.1}", fragment.intensity),
+                optional(entry.rt),
+                optional(psm.predicted_rt),
+                fragment.kind.clone(),
+                fragment.ordinal.to_string(),
+                fragment.charge.to_string(),
+                "0".to_string(),
+            ];
+            if mobility {
+                fields.push(optional(entry.ion_mobility()));
+            }
+            out.push_str(&fields.join("\t"));
+            out.push('\n');
+        }
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+fn write_msp(path: &Path, entries: &[Entry]) -> Result<(), String> {
+    let mut out = String::new();
+    for entry in entries {
+        let psm = entry.psm;
+        let (sequence, _) = parse_peptide(&psm.peptide);
+        let charge = psm.charge.unwrap_or(0);
+        out.push_str(&format!("Name: {}/{}\n", sequence, charge));
+        out.push_str(&format!("MW: {:.4}\n", psm.calcmass));
+        let mut comment = format!(
+            "Parent={:.4} Mods={} RT={} Protein={} Score={} Q={}",
+            entry.precursor_mz(),
+            msp_mods(&psm.peptide),
+            optional(entry.rt),
+            psm.proteins,
+            psm.hyperscore,
+            psm.spectrum_q
+        );
+        if let Some(rt) = psm.predicted_rt {
+            comment.push_str(&format!(" PredictedRT={}", rt));
+        }
+        if let Some(mobility) = entry.ion_mobility() {
+            comment.push_str(&format!(" IonMobility={}", mobility));
+        }
+        out.push_str(&format!("Comment: {}\n", comment));
+        out.push_str(&format!("Num peaks: {}\n", entry.fragments.len()));
+        let mut peaks: Vec<&Fragment> = entry.fragments.iter().collect();
+        peaks.sort_by(|a, b| a.mz_calculated.total_cmp(&b.mz_calculated));
+        for fragment in peaks {
+            out.push_str(&format!(
+                "{:.4}\t{:.1}\t\"{}\"\n",
+                fragment.mz_calculated,
+                fragment.intensity,
+                annotation(fragment)
+            ));
+        }
+        out.push('\n');
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+/// Write `library.tsv` and `library.msp`; returns the number of precursors.
+pub fn build(dir: &Path, settings: &Settings) -> Result<usize, String> {
+    if !dir.join(FRAGMENTS_FILE_NAME).exists() {
+        return Err(format!(
+            "{} not found, search with 'Annotate Matches' ticked",
+            FRAGMENTS_FILE_NAME
+        ));
+    }
+    let psms = results::read_psms(dir)?;
+    let fragments = results::read_fragments(dir)?;
+    if fragments.iter().all(|f| f.intensity <= 0.0) {
+        return Err(format!(
+            "{} has no fragment intensities",
+            FRAGMENTS_FILE_NAME
+        ));
+    }
+    let entries = entries(&psms, fragments, settings);
+    if entries.is_empty() {
+        return Err("No confident precursor has enough annotated fragments".to_string());
+    }
+    write_tsv(&dir.join(TSV_FILE_NAME), &entries)?;
+    write_msp(&dir.join(MSP_FILE_NAME), &entries)?;
+    Ok(entries.len())
+}
+
+#[derive(Default)]
+pub struct LibraryBuilder {
+    settings: Settings,
+    message: String,
+}
+
+impl LibraryBuilder {
+    pub fn update_section(&mut self, ui: &mut egui::Ui, dir: &Path) {
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("q-value ≤");
+            ui.add(
+                egui::DragValue::new(&mut settings.q_value)
+                    .speed(0.001)
+                    .range(0.001..=0.1),
+            );
+            ui.label("Fragments:");
+            ui.add(
+                egui::DragValue::new(&mut settings.min_fragments)
+                    .range(1..=settings.max_fragments)
+                    .prefix("min: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut settings.max_fragments)
+                    .range(settings.min_fragments..=50)
+                    .prefix("max: "),
+            );
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Build library.tsv and library.msp").clicked() {
+                self.message = match build(dir, &self.settings) {
+                    Ok(n) => format!("{} precursors written", n),
+                    Err(e) => format!("Error: {}", e),
+                };
+            }
+            ui.weak("best spectrum per precursor, needs a search with 'Annotate Matches'");
+        });
+        ui.label(&self.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psm(psm_id: u64, peptide: &str, charge: u8, hyperscore: f64, rt: f64) -> Psm {
+        Psm {
+            psm_id,
+            peptide: peptide.to_string(),
+            proteins: "P1".to_string(),
+            filename: "run.mzML".to_string(),
+            scannr: psm_id.to_string(),
+            charge: Some(charge),
+            rt: Some(rt),
+            predicted_rt: None,
+            ion_mobility: None,
+            rank: 1,
+            label: 1,
+            expmass: 1000.0,
+            calcmass: 1000.0,
+            hyperscore,
+            discriminant: 1.0,
+            spectrum_q: 0.001,
+            peptide_q: 0.001,
+            protein_q: 0.001,
+        }
+    }
+
+    fn fragment(psm_id: u64, ordinal: u32, intensity: f64) -> Fragment {
+        Fragment {
+            psm_id,
+            kind: "y".to_string(),
+            ordinal,
+            charge: 1,
+            mz_experimental: 100.0 * ordinal as f64,
+            mz_calculated: 100.0 * ordinal as f64,
+            intensity,
+        }
+    }
+
+    fn fragments(psm_id: u64, intensities: &[f64]) -> Vec<Fragment> {
+        intensities
+            .iter()
+            .enumerate()
+            .map(|(i, &intensity)| fragment(psm_id, i as u32 + 1, intensity))
+            .collect()
+    }
+
+    #[test]
+    fn unimod_sequences() {
+        assert_eq!(
+            unimod_sequence("[+42.010565]-M[+15.994915]PEPTIDE"),
+            "(UniMod:1)M(UniMod:35)PEPTIDE"
+        );
+        assert_eq!(unimod_sequence("PEPTIDE-[-0.984016]"), "PEPTIDE(UniMod:2)");
+        assert_eq!(unimod_sequence("PEPT[+12.3456]IDE"), "PEPT[+12.3456]IDE");
+        assert_eq!(unimod_sequence("PEPTIDE"), "PEPTIDE");
+    }
+
+    #[test]
+    fn msp_mod_fields() {
+        assert_eq!(msp_mods("PEPTIDE"), "0");
+        assert_eq!(
+            msp_mods("[+42.010565]-M[+15.994915]PEPTIDE"),
+            "2/0,M,Acetyl/0,M,Oxidation"
+        );
+        assert_eq!(msp_mods("PEPTIDE-[-0.984016]"), "1/6,E,Amidated");
+        assert_eq!(msp_mods("PEPT[+12.3456]IDE"), "1/3,T,+12.3456");
+    }
+
+    #[test]
+    fn best_spectrum_per_precursor() {
+        let psms = vec![
+            psm(1, "PEPTIDE", 2, 10.0, 10.0),
+            psm(2, "PEPTIDE", 2, 30.0, 12.0),
+            psm(3, "PEPTIDE", 2, 20.0, 20.0),
+            psm(4, "PEPTIDE", 3, 5.0, 11.0),
+        ];
+        let peaks = [1, 2, 3, 4]
+            .into_iter()
+            .flat_map(|id| fragments(id, &[1.0, 2.0, 3.0]))
+            .collect();
+        let entries = entries(&psms, peaks, &Settings::default());
+        let best: Vec<(u64, Option<f64>)> = entries.iter().map(|e| (e.psm.psm_id, e.rt)).collect();
+        // Retention time is the median of all PSMs of the precursor
+        assert_eq!(best, vec![(2, Some(12.0)), (4, Some(11.0))]);
+        assert!(entries[0].fragments.iter().all(|f| f.psm_id == 2));
+    }
+
+    #[test]
+    fn only_confident_rank_one_targets() {
+        let mut spectrum = psm(1, "AAA", 2, 10.0, 1.0);
+        spectrum.spectrum_q = 0.05;
+        let mut peptide = psm(2, "CCC", 2, 10.0, 1.0);
+        peptide.peptide_q = 0.05;
+        let mut decoy = psm(3, "DDD", 2, 10.0, 1.0);
+        decoy.label = -1;
+        let mut second = psm(4, "EEE", 2, 10.0, 1.0);
+        second.rank = 2;
+        let psms = vec![
+            spectrum,
+            peptide,
+            decoy,
+            second,
+            psm(5, "FFF", 2, 10.0, 1.0),
+        ];
+        let peaks = (1..=5)
+            .flat_map(|id| fragments(id, &[1.0, 2.0, 3.0]))
+            .collect();
+        let entries = entries(&psms, peaks, &Settings::default());
+        let ids: Vec<u64> = entries.iter().map(|e| e.psm.psm_id).collect();
+        assert_eq!(ids, vec![5]);
+    }
+
+    #[test]
+    fn keeps_the_most_intense_fragments_scaled_to_the_base_peak() {
+        let psms = vec![psm(1, "PEPTIDE", 2, 10.0, 1.0)];
+        let settings = Settings {
+            max_fragments: 3,
+            min_fragments: 2,
+            ..Default::default()
+        };
+        let peaks = fragments(1, &[10.0, 50.0, 20.0, 40.0, 5.0]);
+        let entries = entries(&psms, peaks, &settings);
+        let kept: Vec<(u32, f64)> = entries[0]
+            .fragments
+            .iter()
+            .map(|f| (f.ordinal, f.intensity))
+            .collect();
+        assert_eq!(kept, vec![(2, BASE_PEAK), (4, 8000.0), (3, 4000.0)]);
+    }
+
+    #[test]
+    fn too_few_fragments_or_no_intensity() {
+        let psms = vec![
+            psm(1, "AAA", 2, 10.0, 1.0),
+            psm(2, "CCC", 2, 10.0, 1.0),
+            psm(3, "DDD", 2, 10.0, 1.0),
+        ];
+        let peaks = [
+            fragments(1, &[1.0, 2.0]),
+            fragments(2, &[0.0, 0.0, 0.0]),
+            fragments(3, &[1.0, 2.0, 3.0]),
+        ]
+        .concat();
+        let entries = entries(&psms, peaks, &Settings::default());
+        let ids: Vec<u64> = entries.iter().map(|e| e.psm.psm_id).collect();
+        assert_eq!(ids, vec![3]);
+    }
+}
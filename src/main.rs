@@ -1,8 +1,47 @@
 /// Is this pretty? No ... but is it well tested.... also no ... was an I on a deadline
 /// well ... not really. BUT I learned a lot about Rust and sage and I'm glad I did.
 /// I am more than happy to take PRs and suggestions for improvements!
+mod calibration;
+mod cloud;
+mod compare;
+mod design;
+mod differential;
+mod export;
+mod identifications;
+mod impurity;
+mod lfq;
+mod library;
+mod msstats;
+mod mztab;
+mod open_search;
+mod output;
+mod plot;
+mod presets;
+mod processing;
+mod provenance;
+mod psi;
+mod quant;
+mod results;
+mod rollup;
+mod stats;
+mod sweep;
+mod tmt;
+mod validation;
+mod watch;
+
+use calibration::Calibration;
+use chrono::Local;
+use cloud::{BrowsePick, BrowseTarget, CloudSettings, S3Browser};
+use compare::Compare;
+use design::Design;
+use differential::Differential;
 use eframe::egui;
 use egui::include_image;
+use export::Exports;
+use lfq::LfqExplorer;
+use open_search::OpenSearch;
+use presets::{PresetAction, Presets};
+use provenance::{HistoryAction, Provenance, RunHistory};
 use rfd::FileDialog;
 use sage_cli::{
     input::{Input, LfqOptions, QuantOptions, TmtOptions, TmtSettings},
@@ -19,12 +58,16 @@ use sage_core::{
 use sage_core::{ion_series::Kind, lfq::PeakScoringStrategy};
 use sage_core::{lfq::IntegrationStrategy, scoring::ScoreType};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::mpsc::{self, Receiver};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
+use sweep::{Sweep, SweepAction};
+use tmt::TmtExplorer;
+use validation::{Field, Report};
+use watch::{WatchAction, WatchFolder};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct EnzymeConfig {
@@ -56,31 +99,88 @@ impl Default for EnzymeConfig {
 #[derive(Debug)]
 enum ThreadMessage {
     Progress(String),
+    // Sent right before `Completed`
+    Provenance(Box<Provenance>),
     Completed(Result<String, String>),
 }
 
+/// Where a search came from, so its completion can be reported back.
+#[derive(Debug, Clone)]
+enum JobOrigin {
+    Manual,
+    Watch(PathBuf),
+    // Index of the combination in the sweep table
+    Sweep(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Job {
+    config: Config,
+    origin: JobOrigin,
+    /// `config.output_directory` with the output template expanded.
+    output_directory: String,
+}
+
+impl Job {
+    /// The template is expanded here, so `{date}` and `{time}` are those of the
+    /// moment the search was requested.
+    fn new(config: Config, origin: JobOrigin) -> Self {
+        let first_run = config
+            .mzml_paths
+            .iter()
+            .chain(config.dotd_paths.iter())
+            .map(|p| p.to_string_lossy().to_string())
+            .next()
+            .or_else(|| config.cloud_paths.first().cloned());
+        let output_directory = output::resolve(
+            &config.output_directory,
+            &config.output_template,
+            &config.database.fasta,
+            &config.name,
+            first_run.as_deref(),
+            Local::now(),
+        );
+        Self {
+            config,
+            origin,
+            output_directory,
+        }
+    }
+}
+
+/// A manual launch waiting for the user to decide what to do with existing results.
+struct PendingOverwrite {
+    job: Job,
+    existing: Vec<&'static str>,
+}
+
+enum OverwriteChoice {
+    Subfolder,
+    Overwrite,
+    Cancel,
+}
+
 impl EnzymeConfig {
-    fn update_section(&mut self, ui: &mut egui::Ui) {
+    fn update_section(&mut self, ui: &mut egui::Ui, report: &Report) {
         ui.heading("Enzyme Settings");
         ui.add(egui::Slider::new(&mut self.missed_cleavages, 0..=5).text("Missed Cleavages"));
         ui.add(egui::Slider::new(&mut self.min_len, 1..=20).text("Min Length"));
         ui.add(egui::Slider::new(&mut self.max_len, 6..=100).text("Max Length"));
+        report.show(ui, Field::PeptideLength);
         ui.horizontal(|ui| {
             ui.label("Cleave At:");
             ui.add(egui::TextEdit::singleline(&mut self.cleave_at).desired_width(10.0));
         });
+        report.show(ui, Field::CleaveAt);
         ui.horizontal(|ui| {
             ui.label("Restrict:");
             ui.checkbox(&mut self.enable_restrict, "Enable Restrict");
             if self.enable_restrict {
                 ui.label("Restrict Char:");
                 ui.add(egui::TextEdit::singleline(&mut self.restrict_char).desired_width(10.0));
-                // Show warning if more than 1 character is written
-                if self.restrict_char.len() > 1 {
-                    ui.label("Warning: Only one character is allowed! Skipping restriction.");
-                }
             }
         });
+        report.show(ui, Field::RestrictChar);
         ui.checkbox(&mut self.c_terminal, "C-Terminal");
         ui.checkbox(&mut self.semi_enzymatic, "Semi-Enzymatic");
     }
@@ -150,7 +250,8 @@ impl IonKindSelection {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 struct DatabaseConfig {
     bucket_size: usize,
     enzyme: EnzymeConfig,
@@ -164,6 +265,9 @@ struct DatabaseConfig {
     static_mods: StaticModConfig,
     variable_mods: VariableModConfig,
     fasta: String,
+    prefilter: bool,
+    prefilter_chunk_size: usize,
+    prefilter_low_memory: bool,
 }
 
 impl From<DatabaseConfig> for Builder {
@@ -182,22 +286,45 @@ impl From<DatabaseConfig> for Builder {
             static_mods: Some(val.static_mods.as_hashmap()),
             variable_mods: Some(val.variable_mods.as_hashmap()),
 
-            // New in sage 0.15.0-beta.2; None defers to sage defaults
-            // (prefilter: false, chunk_size: 0 = auto, low_memory: true)
-            prefilter: None,
-            prefilter_chunk_size: None,
-            prefilter_low_memory: None,
+            prefilter: Some(val.prefilter),
+            prefilter_chunk_size: Some(val.prefilter_chunk_size),
+            prefilter_low_memory: Some(val.prefilter_low_memory),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+// Only the modifications are saved, the input buffers are UI state.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(into = "HashMap<String, f32>", try_from = "HashMap<String, f32>")]
 struct StaticModConfig {
     static_mods: HashMap<ModificationSpecificity, f32>,
     new_mod_buffer: String,
     new_mass_buffer: f32,
 }
 
+impl From<StaticModConfig> for HashMap<String, f32> {
+    fn from(val: StaticModConfig) -> Self {
+        val.as_hashmap()
+    }
+}
+
+impl TryFrom<HashMap<String, f32>> for StaticModConfig {
+    type Error = String;
+
+    fn try_from(value: HashMap<String, f32>) -> Result<Self, Self::Error> {
+        let mut static_mods = HashMap::new();
+        for (mod_, mass) in value {
+            let parsed = ModificationSpecificity::from_str(&mod_)
+                .map_err(|_| format!("Invalid modification '{}'", mod_))?;
+            static_mods.insert(parsed, mass);
+        }
+        Ok(Self {
+            static_mods,
+            ..Self::default()
+        })
+    }
+}
+
 impl Default for StaticModConfig {
     fn default() -> Self {
         Self {
@@ -208,7 +335,8 @@ impl Default for StaticModConfig {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
 struct VariableModConfig {
     // variable_mods: HashMap<ModificationSpecificity, Vec<f32>>,
     // Adding multiple variable mods later ...
@@ -328,21 +456,26 @@ impl Default for DatabaseConfig {
             fasta: String::new(),
             static_mods: StaticModConfig::default(),
             variable_mods: VariableModConfig::default(),
+            // Same as sage's defaults
+            prefilter: false,
+            prefilter_chunk_size: 0,
+            prefilter_low_memory: true,
         }
     }
 }
 
 impl DatabaseConfig {
-    fn update_section(&mut self, ui: &mut egui::Ui) {
+    fn update_section(&mut self, ui: &mut egui::Ui, report: &Report) {
         // Enzyme Configuration
         ui.group(|ui| {
-            self.enzyme.update_section(ui);
+            self.enzyme.update_section(ui, report);
         });
 
         ui.group(|ui| {
             ui.heading("Modifications");
             self.static_mods.update_section(ui);
             self.variable_mods.update_section(ui);
+            report.show(ui, Field::VariableMods);
         });
 
         // Mass Ranges
@@ -356,22 +489,25 @@ impl DatabaseConfig {
                 egui::Slider::new(&mut self.peptide_max_mass, 3000.0..=7000.0)
                     .text("Peptide Max Mass"),
             );
+            report.show(ui, Field::PeptideMass);
         });
 
         ui.group(|ui| {
             ui.heading("Ion Kinds");
             self.ion_kinds.update_section(ui);
+            report.show(ui, Field::IonKinds);
         });
 
         ui.group(|ui| {
             ui.heading("Extras");
             ui.checkbox(&mut self.generate_decoys, "Generate Decoys");
+            report.show(ui, Field::Decoys);
             ui.add(egui::Slider::new(&mut self.bucket_size, 8192..=65536).text("Bucket Size"));
         });
     }
 }
 
-#[derive(Serialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 enum ToleranceConfig {
     #[serde(rename = "da")]
     Da(f32, f32),
@@ -393,16 +529,63 @@ impl ToleranceConfig {
                 ui.add(egui::DragValue::new(b).speed(1));
             }
             ToleranceConfig::Da(a, b) => {
-                ui.add(egui::DragValue::new(a).speed(0.01));
-                ui.add(egui::DragValue::new(b).speed(0.01));
+                // Open search windows are hundreds of Da wide, drag faster there
+                let speed = if *b - *a > 10.0 { 1.0 } else { 0.01 };
+                ui.add(egui::DragValue::new(a).speed(speed));
+                ui.add(egui::DragValue::new(b).speed(speed));
             }
         }
     }
 }
 
-#[derive(Serialize, Debug, Clone)]
+/// Paired min/max editor for an inclusive range.
+fn update_range<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    label: &str,
+    range: &mut (T, T),
+    bounds: std::ops::RangeInclusive<T>,
+) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(
+            egui::DragValue::new(&mut range.0)
+                .range(bounds.clone())
+                .prefix("min: "),
+        );
+        ui.add(
+            egui::DragValue::new(&mut range.1)
+                .range(bounds)
+                .prefix("max: "),
+        );
+    });
+}
+
+/// Which sample sits in a TMT channel.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct ChannelAnnotation {
+    channel: String,
+    sample: String,
+    condition: String,
+    replicate: u32,
+    reference: bool,
+}
+
+impl ChannelAnnotation {
+    /// Column name in exports, the channel itself until a sample is assigned.
+    fn name(&self) -> &str {
+        match self.sample.trim() {
+            "" => &self.channel,
+            sample => sample,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct IsobarSelection {
     selected: Isobaric,
+    #[serde(default)]
+    channels: Vec<ChannelAnnotation>,
 }
 
 impl From<IsobarSelection> for Isobaric {
@@ -415,21 +598,82 @@ impl Default for IsobarSelection {
     fn default() -> Self {
         Self {
             selected: Isobaric::Tmt6,
+            channels: Vec::new(),
         }
     }
 }
 
 impl IsobarSelection {
+    /// Reporter channels of the plex, in the order sage writes them to `tmt.tsv`.
+    fn channel_labels(&self) -> Vec<String> {
+        let labels: &[&str] = match &self.selected {
+            Isobaric::Tmt6 => &["126", "127", "128", "129", "130", "131"],
+            Isobaric::Tmt10 => &[
+                "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131",
+            ],
+            Isobaric::Tmt11 => &[
+                "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131N",
+                "131C",
+            ],
+            Isobaric::Tmt16 => &[
+                "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131N",
+                "131C", "132N", "132C", "133N", "133C", "134N",
+            ],
+            Isobaric::Tmt18 => &[
+                "126", "127N", "127C", "128N", "128C", "129N", "129C", "130N", "130C", "131N",
+                "131C", "132N", "132C", "133N", "133C", "134N", "134C", "135N",
+            ],
+            // Custom reporter ions are named after their m/z
+            Isobaric::User(masses) => return masses.iter().map(|m| format!("{:.4}", m)).collect(),
+        };
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    /// One annotation per channel of the plex, keeping what was entered by position.
+    fn resize_channels(&mut self) {
+        let labels = self.channel_labels();
+        self.channels.resize_with(labels.len(), Default::default);
+        for (annotation, label) in self.channels.iter_mut().zip(labels) {
+            annotation.channel = label;
+        }
+    }
+
     fn update_section(&mut self, ui: &mut egui::Ui) {
         ui.radio_value(&mut self.selected, Isobaric::Tmt6, "TMT 6-plex");
         ui.radio_value(&mut self.selected, Isobaric::Tmt10, "TMT 10-plex");
         ui.radio_value(&mut self.selected, Isobaric::Tmt11, "TMT 11-plex");
         ui.radio_value(&mut self.selected, Isobaric::Tmt16, "TMT 16-plex");
         ui.radio_value(&mut self.selected, Isobaric::Tmt18, "TMT 18-plex");
+        self.resize_channels();
+
+        ui.collapsing("Channel Annotation", |ui| {
+            egui::Grid::new("tmt_channels")
+                .striped(true)
+                .show(ui, |ui| {
+                    for header in ["Channel", "Sample", "Condition", "Replicate", "Reference"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for annotation in &mut self.channels {
+                        ui.label(&annotation.channel);
+                        ui.add(
+                            egui::TextEdit::singleline(&mut annotation.sample).desired_width(120.0),
+                        );
+                        ui.add(
+                            egui::TextEdit::singleline(&mut annotation.condition)
+                                .desired_width(100.0),
+                        );
+                        ui.add(egui::DragValue::new(&mut annotation.replicate).range(0..=99));
+                        ui.checkbox(&mut annotation.reference, "");
+                        ui.end_row();
+                    }
+                });
+            ui.weak("Sample names become the columns of the wide matrix export in the TMT tab.");
+        });
     }
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy)]
 enum SupportedQuantTypes {
     #[serde(rename = "lfq")]
     Lfq,
@@ -437,10 +681,48 @@ enum SupportedQuantTypes {
     Tmt,
 }
 
-#[derive(Serialize, Debug, Clone)]
+/// The LFQ settings offered in the UI, passed on to sage as `LfqOptions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct LfqConfig {
+    ppm_tolerance: f32,
+    spectral_angle: f64,
+    combine_charge_states: bool,
+}
+
+impl Default for LfqConfig {
+    fn default() -> Self {
+        let sage = LfqSettings::default();
+        Self {
+            ppm_tolerance: sage.ppm_tolerance,
+            spectral_angle: sage.spectral_angle,
+            combine_charge_states: sage.combine_charge_states,
+        }
+    }
+}
+
+/// The TMT settings offered in the UI, passed on to sage as `TmtOptions`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct TmtConfig {
+    level: u8,
+    sn: bool,
+}
+
+impl Default for TmtConfig {
+    fn default() -> Self {
+        let sage = TmtSettings::default();
+        Self {
+            level: sage.level,
+            sn: sage.sn,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 enum QuantType {
-    Lfq(LfqSettings),
-    Tmt(IsobarSelection, TmtSettings),
+    Lfq(LfqConfig),
+    Tmt(IsobarSelection, TmtConfig),
 }
 
 impl QuantType {
@@ -464,6 +746,7 @@ impl QuantType {
                     ui.heading("TMT Settings");
                     isobar.update_section(ui);
                     ui.add(egui::Slider::new(&mut tmt.level, 1..=10).text("Level"));
+                    ui.checkbox(&mut tmt.sn, "Report signal to noise instead of intensity");
                 });
             }
         }
@@ -471,10 +754,8 @@ impl QuantType {
 
     fn type_default(supported: SupportedQuantTypes) -> Self {
         match supported {
-            SupportedQuantTypes::Lfq => Self::Lfq(LfqSettings::default()),
-            SupportedQuantTypes::Tmt => {
-                Self::Tmt(IsobarSelection::default(), TmtSettings::default())
-            }
+            SupportedQuantTypes::Lfq => Self::Lfq(LfqConfig::default()),
+            SupportedQuantTypes::Tmt => Self::Tmt(IsobarSelection::default(), TmtConfig::default()),
         }
     }
 }
@@ -525,16 +806,19 @@ impl From<QuantType> for QuantOptions {
 
 impl Default for QuantType {
     fn default() -> Self {
-        Self::Lfq(LfqSettings::default())
+        Self::Lfq(LfqConfig::default())
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 struct Config {
+    name: String,
     database: DatabaseConfig,
     precursor_tol: ToleranceConfig,
     fragment_tol: ToleranceConfig,
     precursor_charge: (u8, u8),
+    override_precursor_charge: bool,
     isotope_errors: (i8, i8),
     deisotope: bool,
 
@@ -548,16 +832,25 @@ struct Config {
     report_psms: usize,
     mzml_paths: Vec<PathBuf>,
     dotd_paths: Vec<PathBuf>,
+    // s3:// URIs, mzML files or .d prefixes alike; sage tells them apart by extension.
+    cloud_paths: Vec<String>,
+    design: Design,
 
     quant: QuantType,
     quant_enabled: bool,
     quant_class: SupportedQuantTypes,
 
+    #[serde(skip)]
     bruker_config: Option<BrukerProcessingConfig>,
     annotate_matches: bool,
     write_pin: bool,
     score_type: ScoreType,
+    protein_grouping: bool,
+    protein_grouping_peptide_fdr: f32,
+    write_report: bool,
     output_directory: String,
+    // Expanded below `output_directory` at launch, e.g. `{date}/{fasta_stem}`
+    output_template: String,
 }
 
 impl From<Config> for Input {
@@ -580,6 +873,7 @@ impl From<Config> for Input {
             .collect();
 
         mzml_path_strings.extend(dotd_path_strings);
+        mzml_path_strings.extend(val.cloud_paths);
 
         Input {
             database: val.database.into(),
@@ -593,7 +887,7 @@ impl From<Config> for Input {
             max_fragment_charge: Some(val.max_fragment_charge),
             min_matched_peaks: Some(val.min_matched_peaks),
             precursor_charge: Some(val.precursor_charge),
-            override_precursor_charge: None, // TODO
+            override_precursor_charge: Some(val.override_precursor_charge),
             isotope_errors: Some(val.isotope_errors),
             deisotope: Some(val.deisotope),
             quant,
@@ -606,25 +900,38 @@ impl From<Config> for Input {
             write_pin: Some(val.write_pin),
             score_type: Some(val.score_type),
 
-            // New in sage 0.15.0-beta.2; None defers to sage defaults
-            // (protein_grouping: true, peptide_fdr: 0.01, write_report: false)
-            protein_grouping: None,
-            protein_grouping_peptide_fdr: None,
-            write_report: None,
+            protein_grouping: Some(val.protein_grouping),
+            protein_grouping_peptide_fdr: Some(val.protein_grouping_peptide_fdr),
+            write_report: Some(val.write_report),
         }
     }
 }
 
+impl Config {
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         let cwd_str: Option<String> = std::env::current_dir()
             .ok()
             .map(|p| p.to_string_lossy().to_string());
         Self {
+            name: "default".to_string(),
             database: DatabaseConfig::default(),
             precursor_tol: ToleranceConfig::default(),
             fragment_tol: ToleranceConfig::default(),
             precursor_charge: (2, 4),
+            override_precursor_charge: false,
             isotope_errors: (-1, 3),
             deisotope: false,
             chimera: false,
@@ -637,6 +944,8 @@ impl Default for Config {
             report_psms: 1,
             mzml_paths: Vec::new(),
             dotd_paths: Vec::new(),
+            cloud_paths: Vec::new(),
+            design: Design::default(),
             quant_enabled: true,
             quant: QuantType::default(),
             bruker_config: None,
@@ -644,13 +953,28 @@ impl Default for Config {
             annotate_matches: false,
             write_pin: false,
             score_type: ScoreType::SageHyperScore,
+            protein_grouping: true,
+            protein_grouping_peptide_fdr: 0.01,
+            write_report: false,
             output_directory: cwd_str.unwrap_or_else(|| "output".to_string()),
+            output_template: String::new(),
         }
     }
 }
 
+/// Top level views of the main window.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Tab {
+    Search,
+    Lfq,
+    Tmt,
+    Stats,
+    Export,
+}
+
 struct SageLauncher {
     config: Config,
+    tab: Tab,
     status_message: String,
     precursor_tolerance_type: ToleranceType,
     fragment_tolerance_type: ToleranceType,
@@ -659,6 +983,34 @@ struct SageLauncher {
     start_time: Option<Instant>,
     elapsed_time: String,
     is_running: bool,
+    cloud_settings: CloudSettings,
+    s3_browser: S3Browser,
+    cloud_path_buffer: String,
+    design_message: String,
+    queue: VecDeque<Job>,
+    current_origin: Option<JobOrigin>,
+    /// Resolved output directory of the running search.
+    current_output: Option<String>,
+    /// Bumped whenever the config may have changed, so panels can cache what
+    /// they derive from it.
+    config_generation: u64,
+    watcher: WatchFolder,
+    watch_config: Option<Config>,
+    history: RunHistory,
+    last_run: Option<Provenance>,
+    pending_overwrite: Option<PendingOverwrite>,
+    presets: Presets,
+    calibration: Calibration,
+    open_search: OpenSearch,
+    sweep: Sweep,
+    compare: Compare,
+    lfq: LfqExplorer,
+    tmt: TmtExplorer,
+    differential: Differential,
+    exports: Exports,
+    // Recomputed every frame from `config`
+    validation: Report,
+    path_checks: validation::PathChecks,
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -668,6 +1020,13 @@ enum ToleranceType {
 }
 
 impl ToleranceType {
+    fn of(tolerance: &ToleranceConfig) -> Self {
+        match tolerance {
+            ToleranceConfig::Ppm(..) => ToleranceType::Ppm,
+            ToleranceConfig::Da(..) => ToleranceType::Da,
+        }
+    }
+
     fn get_default_tolerance(&self) -> ToleranceConfig {
         match self {
             ToleranceType::Ppm => ToleranceConfig::Ppm(-10.0, 10.0),
@@ -689,6 +1048,7 @@ impl Default for SageLauncher {
     fn default() -> Self {
         Self {
             config: Config::default(),
+            tab: Tab::Search,
             status_message: String::new(),
             precursor_tolerance_type: ToleranceType::Ppm,
             fragment_tolerance_type: ToleranceType::Ppm,
@@ -697,11 +1057,44 @@ impl Default for SageLauncher {
             start_time: None,
             is_running: false,
             elapsed_time: String::new(),
+            cloud_settings: CloudSettings::from_env(),
+            s3_browser: S3Browser::default(),
+            cloud_path_buffer: String::new(),
+            design_message: String::new(),
+            queue: VecDeque::new(),
+            current_origin: None,
+            current_output: None,
+            config_generation: 0,
+            watcher: WatchFolder::default(),
+            watch_config: None,
+            history: RunHistory::default(),
+            last_run: None,
+            pending_overwrite: None,
+            presets: Presets::default(),
+            calibration: Calibration::default(),
+            open_search: OpenSearch::default(),
+            sweep: Sweep::default(),
+            compare: Compare::default(),
+            lfq: LfqExplorer::default(),
+            tmt: TmtExplorer::default(),
+            differential: Differential::default(),
+            exports: Exports::default(),
+            validation: Report::default(),
+            path_checks: validation::PathChecks::default(),
         }
     }
 }
 
 impl SageLauncher {
+    /// Replace the whole config, keeping the tolerance unit selectors in sync
+    /// (otherwise they would reset the loaded tolerances to defaults).
+    fn set_config(&mut self, config: Config) {
+        self.precursor_tolerance_type = ToleranceType::of(&config.precursor_tol);
+        self.fragment_tolerance_type = ToleranceType::of(&config.fragment_tol);
+        self.config = config;
+        self.config_generation += 1;
+    }
+
     fn update_tolerances(&mut self, ui: &mut egui::Ui) {
         ui.group(|ui| {
             ui.heading("Precursor Tolerance");
@@ -724,6 +1117,32 @@ impl SageLauncher {
                 _ => {}
             }
             self.config.precursor_tol.update_section(ui);
+            self.validation.show(ui, Field::PrecursorTol);
+        });
+
+        ui.group(|ui| {
+            ui.heading("Precursor Charge and Isotope Errors");
+            update_range(
+                ui,
+                "Precursor Charge:",
+                &mut self.config.precursor_charge,
+                1..=10,
+            );
+            ui.checkbox(
+                &mut self.config.override_precursor_charge,
+                "Override Precursor Charge",
+            );
+            ui.weak("By default the range is only searched for spectra without a charge annotation. Override ignores the annotated charges and searches the range for every spectrum.");
+            self.validation.show(ui, Field::PrecursorCharge);
+
+            update_range(
+                ui,
+                "Isotope Errors:",
+                &mut self.config.isotope_errors,
+                -5..=5,
+            );
+            ui.weak("Also match precursors picked this many C13 isotopes off the monoisotopic peak (default: -1 to 3).");
+            self.validation.show(ui, Field::IsotopeErrors);
         });
 
         ui.group(|ui| {
@@ -741,6 +1160,27 @@ impl SageLauncher {
                 _ => {}
             }
             self.config.fragment_tol.update_section(ui);
+            self.validation.show(ui, Field::FragmentTol);
+        });
+
+        ui.collapsing("Estimate Tolerances", |ui| {
+            if let Some((precursor, fragment)) =
+                self.calibration
+                    .update_section(ui, &self.config, self.is_running)
+            {
+                self.config.precursor_tol = precursor;
+                self.config.fragment_tol = fragment;
+                self.precursor_tolerance_type = ToleranceType::of(&precursor);
+                self.fragment_tolerance_type = ToleranceType::of(&fragment);
+                self.status_message = "Applied the estimated tolerances".to_string();
+            }
+        });
+
+        ui.collapsing("Open Search", |ui| {
+            if self.open_search.update_mode_section(ui, &mut self.config) {
+                self.precursor_tolerance_type = ToleranceType::Da;
+                self.status_message = "Switched to open search tolerances".to_string();
+            }
         });
     }
 
@@ -754,14 +1194,87 @@ impl SageLauncher {
             egui::Slider::new(&mut self.config.max_fragment_charge, 1..=5)
                 .text("Max Fragment Charge"),
         );
+        self.validation.show(ui, Field::Peaks);
         ui.add(egui::Slider::new(&mut self.config.report_psms, 1..=10).text("Report PSMs"));
 
         ui.checkbox(&mut self.config.deisotope, "Deisotope");
         ui.checkbox(&mut self.config.chimera, "Chimera");
         ui.checkbox(&mut self.config.wide_window, "Wide Window");
+        self.validation.show(ui, Field::WideWindow);
         ui.checkbox(&mut self.config.predict_rt, "Predict RT");
     }
 
+    /// Everything else sage's `Input` accepts. Defaults match sage's own.
+    fn update_advanced_settings(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            ui.heading("Database");
+            let db = &mut self.config.database;
+            ui.horizontal(|ui| {
+                ui.label("Decoy Tag:");
+                let mut tag = db.decoy_tag.clone().unwrap_or_default();
+                if ui
+                    .add(egui::TextEdit::singleline(&mut tag).desired_width(60.0))
+                    .changed()
+                {
+                    db.decoy_tag = (!tag.is_empty()).then_some(tag);
+                }
+            });
+            ui.weak("Prefix marking decoy proteins, generated or already in the FASTA (default: rev_).");
+            ui.add(egui::Slider::new(&mut db.min_ion_index, 0..=5).text("Min Ion Index"));
+            ui.weak("Fragments below this index (e.g. b1, y1) are not used for the initial search (default: 2).");
+            ui.add(egui::Slider::new(&mut db.max_variable_mods, 0..=5).text("Max Variable Mods"));
+            ui.weak("Maximum variable modifications per peptide (default: 2).");
+
+            ui.checkbox(&mut db.prefilter, "Prefilter");
+            ui.weak("Pre-screen the database against the spectra in chunks before the full search. Saves memory on huge databases (default: off).");
+            ui.add_enabled_ui(db.prefilter, |ui| {
+                ui.add(
+                    egui::DragValue::new(&mut db.prefilter_chunk_size)
+                        .speed(1000)
+                        .prefix("Chunk size: "),
+                );
+                ui.weak("Peptides per prefilter chunk, 0 picks it automatically (default: 0).");
+                ui.checkbox(&mut db.prefilter_low_memory, "Low Memory Prefilter");
+                ui.weak("Trade some speed for lower memory while prefiltering (default: on).");
+            });
+        });
+
+        ui.group(|ui| {
+            ui.heading("Scoring");
+            let sage = matches!(self.config.score_type, ScoreType::SageHyperScore);
+            if ui.radio(sage, "Sage HyperScore").clicked() {
+                self.config.score_type = ScoreType::SageHyperScore;
+            }
+            if ui.radio(!sage, "OpenMS HyperScore").clicked() {
+                self.config.score_type = ScoreType::OpenMSHyperScore;
+            }
+            ui.weak("Score used to rank candidate peptides (default: Sage HyperScore).");
+        });
+
+        ui.group(|ui| {
+            ui.heading("Protein Inference");
+            ui.checkbox(&mut self.config.protein_grouping, "Protein Grouping");
+            ui.weak("Group proteins that can't be told apart by their peptides (default: on).");
+            ui.add_enabled(
+                self.config.protein_grouping,
+                egui::Slider::new(&mut self.config.protein_grouping_peptide_fdr, 0.001..=0.1)
+                    .logarithmic(true)
+                    .text("Peptide FDR for grouping"),
+            );
+            ui.weak("Only peptides passing this q-value are used to build groups (default: 0.01).");
+        });
+
+        ui.group(|ui| {
+            ui.heading("Outputs");
+            ui.checkbox(&mut self.config.annotate_matches, "Annotate Matches");
+            ui.weak("Write matched fragment ions to matched_fragments.sage.tsv (default: off).");
+            ui.checkbox(&mut self.config.write_pin, "Write PIN");
+            ui.weak("Write a Percolator input file, results.sage.pin (default: off).");
+            ui.checkbox(&mut self.config.write_report, "Write Report");
+            ui.weak("Write an HTML summary report next to the results (default: off).");
+        });
+    }
+
     fn update_quant_options(&mut self, ui: &mut egui::Ui) {
         ui.checkbox(&mut self.config.quant_enabled, "Enable Quantification");
 
@@ -788,7 +1301,344 @@ impl SageLauncher {
             }
 
             self.config.quant.update_section(ui);
+            self.validation.show(ui, Field::Quant);
+        }
+    }
+
+    /// Every selected input, local or on S3.
+    fn input_files(&self) -> Vec<String> {
+        self.config
+            .mzml_paths
+            .iter()
+            .chain(self.config.dotd_paths.iter())
+            .map(|path| path.display().to_string())
+            .chain(self.config.cloud_paths.iter().cloned())
+            .collect()
+    }
+
+    /// Condition of every sample name, from the experimental design and the TMT
+    /// channel annotation.
+    fn sample_conditions(&self) -> HashMap<String, String> {
+        let mut conditions = HashMap::new();
+        for row in &self.config.design.rows {
+            if !row.condition.trim().is_empty() {
+                let name = self.config.design.column_name(&row.file);
+                conditions.insert(name, row.condition.trim().to_string());
+            }
+        }
+        if let QuantType::Tmt(isobar, _) = &self.config.quant {
+            for channel in &isobar.channels {
+                if !channel.condition.trim().is_empty() {
+                    conditions.insert(
+                        channel.name().to_string(),
+                        channel.condition.trim().to_string(),
+                    );
+                }
+            }
+        }
+        conditions
+    }
+
+    /// Directory the result views start from: the last run, or the output location.
+    fn default_results_dir(&self) -> String {
+        self.last_run
+            .as_ref()
+            .map(|run| run.output_directory.clone())
+            .unwrap_or_else(|| self.config.output_directory.clone())
+    }
+
+    /// Everything needed to set up and launch a search.
+    fn update_search_tab(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Presets", |ui| {
+            match self.presets.update_section(ui, &self.config) {
+                Some(PresetAction::Apply(config)) => {
+                    self.status_message = format!("Applied preset '{}'", config.name);
+                    self.set_config(*config);
+                }
+                Some(PresetAction::Message(message)) => self.status_message = message,
+                None => {}
+            }
+        });
+
+        // File Selection Section
+        ui.collapsing("File Selection", |ui| {
+            // Output loc picker
+            ui.horizontal(|ui| {
+                ui.label("Output Location:");
+                ui.text_edit_singleline(&mut self.config.output_directory);
+                if ui.button("Browse").clicked() {
+                    if let Some(path) = FileDialog::new().pick_folder() {
+                        self.config.output_directory = path.display().to_string();
+                    }
+                }
+                if ui.button("Browse S3").clicked() {
+                    self.s3_browser
+                        .open(BrowseTarget::Output, &self.config.output_directory);
+                }
+            });
+            self.validation.show(ui, Field::Output);
+
+            ui.horizontal(|ui| {
+                ui.label("Config Name:");
+                ui.text_edit_singleline(&mut self.config.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Subfolder Template:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.config.output_template)
+                        .hint_text("{date}/{fasta_stem}/{config_name}"),
+                )
+                .on_hover_text(output::TEMPLATE_HELP);
+            });
+            if !self.config.output_template.trim().is_empty() {
+                let preview = Job::new(self.config.clone(), JobOrigin::Manual);
+                ui.label(format!("Results will go to: {}", preview.output_directory));
+            }
+
+            // FASTA file picker
+            ui.horizontal(|ui| {
+                ui.label("FASTA File:");
+                ui.text_edit_singleline(&mut self.config.database.fasta);
+                if ui.button("Browse").clicked() {
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("FASTA", &["fasta"])
+                        .pick_file()
+                    {
+                        self.config.database.fasta = path.display().to_string();
+                    }
+                }
+                if ui.button("Browse S3").clicked() {
+                    self.s3_browser
+                        .open(BrowseTarget::Fasta, &self.config.database.fasta);
+                }
+            });
+            self.validation.show(ui, Field::Fasta);
+
+            // mzML file picker
+            ui.horizontal(|ui| {
+                ui.label("mzML/.d Files:");
+                if ui.button("Pick mzmls").clicked() {
+                    if let Some(paths) = FileDialog::new()
+                        .add_filter("mzml", &["mzML", "gz", "mzml"])
+                        .pick_files()
+                    {
+                        self.config.mzml_paths = paths
+                    } else {
+                        self.config.mzml_paths = Vec::new();
+                    }
+                }
+                if ui.button("Pick .d files").clicked() {
+                    if let Some(paths) = FileDialog::new()
+                        .add_filter("Bruker Raw Data", &["d"])
+                        .pick_folders()
+                    {
+                        self.config.dotd_paths = paths
+                    } else {
+                        self.config.dotd_paths = Vec::new();
+                    }
+                }
+                if ui.button("Browse S3").clicked() {
+                    let start = self.config.cloud_paths.last().cloned().unwrap_or_default();
+                    self.s3_browser.open(BrowseTarget::Spectra, &start);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Add S3 URI:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.cloud_path_buffer)
+                        .hint_text("s3://bucket/run.mzML"),
+                );
+                let valid = cloud::S3Uri::parse(&self.cloud_path_buffer).is_ok();
+                if ui.add_enabled(valid, egui::Button::new("Add")).clicked() {
+                    let uri = std::mem::take(&mut self.cloud_path_buffer);
+                    if !self.config.cloud_paths.contains(&uri) {
+                        self.config.cloud_paths.push(uri);
+                    }
+                }
+            });
+
+            self.validation.show(ui, Field::Spectra);
+
+            ui.collapsing("Cloud Storage (S3)", |ui| {
+                self.cloud_settings.update_section(ui);
+            });
+
+            // Show the picked files in a scrollable list
+            ui.label("Picked Files:");
+            ui.spacing();
+            ui.separator();
+            ui.spacing();
+            for path in self
+                .config
+                .mzml_paths
+                .iter()
+                .chain(self.config.dotd_paths.iter())
+            {
+                ui.label(path.to_string_lossy());
+            }
+            let mut remove_cloud = None;
+            for (i, uri) in self.config.cloud_paths.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(uri);
+                    if ui.small_button("Remove").clicked() {
+                        remove_cloud = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = remove_cloud {
+                self.config.cloud_paths.remove(i);
+            }
+
+            ui.collapsing("Experimental Design", |ui| {
+                let files = self.input_files();
+                self.config
+                    .design
+                    .update_section(ui, &files, &mut self.design_message);
+            });
+        });
+
+        // Database Configuration Section
+        ui.collapsing("Database Configuration", |ui| {
+            self.config.database.update_section(ui, &self.validation);
+        });
+
+        // Tolerance Configuration Section
+        ui.collapsing("Tolerance Settings", |ui| {
+            self.update_tolerances(ui);
+        });
+
+        ui.collapsing("Quantification Options", |ui| {
+            self.update_quant_options(ui);
+        });
+
+        // General Settings Section
+        ui.collapsing("General Settings", |ui| {
+            self.update_general_settings(ui);
+        });
+
+        ui.collapsing("Advanced Settings", |ui| {
+            self.update_advanced_settings(ui);
+        });
+
+        ui.collapsing("Run History", |ui| {
+            if let Some(action) = self.history.update_section(ui) {
+                self.handle_history_action(action);
+            }
+        });
+
+        ui.collapsing("Watch Folder", |ui| {
+            if let Some(action) = self.watcher.update_section(ui) {
+                self.handle_watch_action(action);
+            }
+        });
+
+        ui.collapsing("Parameter Sweep", |ui| {
+            if let Some(action) =
+                self.sweep
+                    .update_section(ui, &self.config, self.config_generation)
+            {
+                self.handle_sweep_action(action);
+            }
+        });
+
+        ui.collapsing("Open Search Results", |ui| {
+            let default_dir = self.default_results_dir();
+            self.open_search.update_results_section(ui, &default_dir);
+        });
+
+        ui.collapsing("Compare Runs", |ui| {
+            self.compare.update_section(ui);
+        });
+
+        ui.add_space(20.0);
+
+        ui.horizontal(|ui| {
+            let launch_button = ui
+                .add_enabled(
+                    // Disable when a search is running or the config has errors
+                    !self.is_running
+                        && !self.calibration.is_running()
+                        && !self.validation.has_errors(),
+                    egui::Button::new("Launch"),
+                )
+                .on_disabled_hover_text(if self.validation.has_errors() {
+                    "Fix the configuration errors listed below first"
+                } else {
+                    "Wait for the running search to finish"
+                });
+
+            if launch_button.clicked() {
+                match self.launch_application() {
+                    Ok(_) if self.pending_overwrite.is_some() => {
+                        self.status_message = "Output location already contains results".to_string()
+                    }
+                    Ok(_) => self.status_message = "Analysis started".to_string(),
+                    Err(e) => self.status_message = format!("Error: {}", e),
+                }
+            }
+
+            if ui.button("Save Config").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("JSON", &["json"])
+                    .set_file_name("sagegui_config.json")
+                    .save_file()
+                {
+                    self.status_message = match self.config.save(&path) {
+                        Ok(_) => format!("Config saved to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            if ui.button("Load Config").clicked() {
+                if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+                    match Config::load(&path) {
+                        Ok(config) => {
+                            self.set_config(config);
+                            self.status_message = format!("Config loaded from {}", path.display());
+                        }
+                        Err(e) => self.status_message = format!("Error: {}", e),
+                    }
+                }
+            }
+        });
+
+        let issues = self.validation.issues();
+        if !issues.is_empty() {
+            let errors = self.validation.error_count();
+            ui.collapsing(
+                format!(
+                    "Configuration check: {} errors, {} warnings",
+                    errors,
+                    issues.len() - errors
+                ),
+                |ui| {
+                    for issue in issues {
+                        validation::show_issue(ui, issue);
+                    }
+                },
+            );
+        }
+
+        if !self.status_message.is_empty() {
+            ui.colored_label(
+                if self.status_message.starts_with("Error") {
+                    egui::Color32::RED
+                } else {
+                    egui::Color32::GREEN
+                },
+                &self.status_message,
+            );
+        }
+
+        if let Some(last_run) = &self.last_run {
+            ui.add_space(10.0);
+            ui.group(|ui| {
+                last_run.summary_section(ui);
+            });
         }
+
+        ui.add_space(20.0);
     }
 }
 
@@ -796,6 +1646,13 @@ impl eframe::App for SageLauncher {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Update process status and elapsed time
         self.check_thread_status();
+        self.poll_watch_folder();
+        self.start_next_job();
+        self.calibration.poll(ctx);
+        self.validation = validation::validate(&self.config, &mut self.path_checks);
+        // Settings only change in reaction to input. Panels drawn above the widget
+        // that changed them catch up in the next frame, with the next generation.
+        let had_input = has_user_input(ctx);
 
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
@@ -811,6 +1668,9 @@ impl eframe::App for SageLauncher {
                             self.elapsed_time = format_duration(start_time.elapsed());
                             ui.label(format!("({})", self.elapsed_time));
                         }
+                        if !self.queue.is_empty() {
+                            ui.label(format!("{} more queued", self.queue.len()));
+                        }
                     });
 
                     ui.add_space(10.0);
@@ -819,117 +1679,49 @@ impl eframe::App for SageLauncher {
                 ui.add(egui::Image::new(include_image!("../assets/logo.png")).max_width(400.0));
                 ui.add_space(20.0);
 
-                // File Selection Section
-                ui.collapsing("File Selection", |ui| {
-                    // Output loc picker
-                    ui.horizontal(|ui| {
-                        ui.label("Output Location:");
-                        ui.text_edit_singleline(&mut self.config.output_directory);
-                        if ui.button("Browse").clicked() {
-                            if let Some(path) = FileDialog::new().pick_folder() {
-                                self.config.output_directory = path.display().to_string();
-                            }
-                        }
-                    });
-
-                    // FASTA file picker
-                    ui.horizontal(|ui| {
-                        ui.label("FASTA File:");
-                        ui.text_edit_singleline(&mut self.config.database.fasta);
-                        if ui.button("Browse").clicked() {
-                            if let Some(path) = FileDialog::new()
-                                .add_filter("FASTA", &["fasta"])
-                                .pick_file()
-                            {
-                                self.config.database.fasta = path.display().to_string();
-                            }
-                        }
-                    });
-
-                    // mzML file picker
-                    ui.horizontal(|ui| {
-                        ui.label("mzML/.d Files:");
-                        if ui.button("Pick mzmls").clicked() {
-                            if let Some(paths) = FileDialog::new()
-                                .add_filter("mzml", &["mzML", "gz", "mzml"])
-                                .pick_files()
-                            {
-                                self.config.mzml_paths = paths
-                            } else {
-                                self.config.mzml_paths = Vec::new();
-                            }
-                        }
-                        if ui.button("Pick .d files").clicked() {
-                            if let Some(paths) = FileDialog::new()
-                                .add_filter("Bruker Raw Data", &["d"])
-                                .pick_folders()
-                            {
-                                self.config.dotd_paths = paths
-                            } else {
-                                self.config.dotd_paths = Vec::new();
-                            }
-                        }
-                    });
-
-                    // Show the picked files in a scrollable list
-                    ui.label("Picked Files:");
-                    ui.spacing();
-                    ui.separator();
-                    ui.spacing();
-                    for path in self
-                        .config
-                        .mzml_paths
-                        .iter()
-                        .chain(self.config.dotd_paths.iter())
-                    {
-                        ui.label(path.to_string_lossy());
-                    }
-                });
-
-                // Database Configuration Section
-                ui.collapsing("Database Configuration", |ui| {
-                    self.config.database.update_section(ui);
-                });
-
-                // Tolerance Configuration Section
-                ui.collapsing("Tolerance Settings", |ui| {
-                    self.update_tolerances(ui);
-                });
-
-                ui.collapsing("Quantification Options", |ui| {
-                    self.update_quant_options(ui);
-                });
-
-                // General Settings Section
-                ui.collapsing("General Settings", |ui| {
-                    self.update_general_settings(ui);
-                });
-
-                ui.add_space(20.0);
-
                 ui.horizontal(|ui| {
-                    let launch_button = ui.add_enabled(
-                        !self.is_running, // Disable when process is running
-                        egui::Button::new("Launch"),
-                    );
-
-                    if launch_button.clicked() {
-                        match self.launch_application() {
-                            Ok(_) => self.status_message = "Analysis started".to_string(),
-                            Err(e) => self.status_message = format!("Error: {}", e),
-                        }
-                    }
+                    ui.selectable_value(&mut self.tab, Tab::Search, "Search");
+                    ui.selectable_value(&mut self.tab, Tab::Lfq, "LFQ");
+                    ui.selectable_value(&mut self.tab, Tab::Tmt, "TMT");
+                    ui.selectable_value(&mut self.tab, Tab::Stats, "Statistics");
+                    ui.selectable_value(&mut self.tab, Tab::Export, "Export");
                 });
-
-                if !self.status_message.is_empty() {
-                    ui.colored_label(
-                        if self.status_message.starts_with("Error") {
-                            egui::Color32::RED
-                        } else {
-                            egui::Color32::GREEN
-                        },
-                        &self.status_message,
-                    );
+                ui.separator();
+
+                match self.tab {
+                    Tab::Search => self.update_search_tab(ui),
+                    Tab::Lfq => {
+                        let default_dir = self.default_results_dir();
+                        self.lfq
+                            .update_section(ui, &default_dir, &self.config.design);
+                    }
+                    Tab::Tmt => {
+                        let default_dir = self.default_results_dir();
+                        let channels = match &self.config.quant {
+                            QuantType::Tmt(isobar, _) => isobar.channels.as_slice(),
+                            QuantType::Lfq(_) => &[],
+                        };
+                        self.tmt.update_section(ui, &default_dir, channels);
+                    }
+                    Tab::Stats => {
+                        let default_dir = self.default_results_dir();
+                        let conditions = self.sample_conditions();
+                        self.differential
+                            .update_section(ui, &default_dir, &conditions);
+                    }
+                    Tab::Export => {
+                        let default_dir = self.default_results_dir();
+                        let channels = match &self.config.quant {
+                            QuantType::Tmt(isobar, _) => isobar.channels.as_slice(),
+                            QuantType::Lfq(_) => &[],
+                        };
+                        self.exports.update_section(
+                            ui,
+                            &default_dir,
+                            &self.config.design,
+                            channels,
+                        );
+                    }
                 }
 
                 ui.add_space(20.0);
@@ -947,9 +1739,33 @@ impl eframe::App for SageLauncher {
             });
         });
 
+        self.show_overwrite_dialog(ctx);
+
+        if let Some(pick) = self.s3_browser.show(ctx, &self.cloud_settings) {
+            match pick {
+                BrowsePick::Fasta(uri) => self.config.database.fasta = uri,
+                BrowsePick::Output(uri) => self.config.output_directory = uri,
+                BrowsePick::Spectra(uris) => {
+                    for uri in uris {
+                        if !self.config.cloud_paths.contains(&uri) {
+                            self.config.cloud_paths.push(uri);
+                        }
+                    }
+                }
+            }
+        }
+
+        if had_input {
+            self.config_generation += 1;
+            ctx.request_repaint();
+        }
+
         // Request continuous repaint while process is running
         if self.is_running {
             ctx.request_repaint_after(Duration::from_millis(100));
+        } else if self.watcher.is_active() {
+            // Keep polling the watch folder even when nobody touches the window
+            ctx.request_repaint_after(Duration::from_secs(1));
         }
     }
 }
@@ -962,11 +1778,16 @@ impl SageLauncher {
                 Ok(ThreadMessage::Progress(msg)) => {
                     self.status_message = msg;
                 }
+                Ok(ThreadMessage::Provenance(record)) => {
+                    self.history.push((*record).clone());
+                    self.last_run = Some(*record);
+                }
                 Ok(ThreadMessage::Completed(result)) => {
-                    match result {
-                        Ok(msg) => self.status_message = msg,
+                    match &result {
+                        Ok(msg) => self.status_message = msg.clone(),
                         Err(err) => self.status_message = format!("Error: {}", err),
                     }
+                    self.finish_job(&result);
                     self.cleanup_thread();
                 }
                 Err(mpsc::TryRecvError::Empty) => {
@@ -974,6 +1795,7 @@ impl SageLauncher {
                 }
                 Err(mpsc::TryRecvError::Disconnected) => {
                     // Thread has finished or been disconnected
+                    self.finish_job(&Err("Analysis thread stopped unexpectedly".to_string()));
                     self.cleanup_thread();
                 }
             }
@@ -991,18 +1813,232 @@ impl SageLauncher {
         self.is_running = false;
     }
 
-    fn launch_application(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.config.database.fasta.is_empty() {
-            return Err("FASTA file is not selected".into());
+    fn finish_job(&mut self, result: &Result<String, String>) {
+        let runtime = self
+            .start_time
+            .map(|start| start.elapsed())
+            .unwrap_or_default();
+        let output_directory = self.current_output.take();
+        match self.current_origin.take() {
+            Some(JobOrigin::Watch(run)) => self.watcher.record(&run, result),
+            Some(JobOrigin::Sweep(index)) => {
+                self.sweep
+                    .record(index, result, output_directory.as_deref(), runtime)
+            }
+            Some(JobOrigin::Manual) | None => {}
+        }
+    }
+
+    fn handle_sweep_action(&mut self, action: SweepAction) {
+        match action {
+            SweepAction::Queue(jobs) => {
+                for (index, config) in jobs {
+                    match check_config(&config) {
+                        Ok(_) => self
+                            .queue
+                            .push_back(Job::new(config, JobOrigin::Sweep(index))),
+                        Err(e) => self.sweep.record(index, &Err(e), None, Duration::ZERO),
+                    }
+                }
+                self.status_message = "Parameter sweep queued".to_string();
+            }
+            SweepAction::CancelRemaining => {
+                self.queue
+                    .retain(|job| !matches!(job.origin, JobOrigin::Sweep(..)));
+                self.sweep.cancel_remaining();
+            }
+        }
+    }
+
+    fn handle_history_action(&mut self, action: HistoryAction) {
+        match action {
+            HistoryAction::ReopenConfig(value) => match serde_json::from_value(value) {
+                Ok(config) => {
+                    self.set_config(config);
+                    self.status_message = "Config restored from run history".to_string();
+                }
+                Err(e) => self.status_message = format!("Error: {}", e),
+            },
+            HistoryAction::OpenFolder(path) => {
+                if let Err(e) = provenance::open_in_file_manager(&path) {
+                    self.status_message = format!("Error: {}", e);
+                }
+            }
+        }
+    }
+
+    fn handle_watch_action(&mut self, action: WatchAction) {
+        match action {
+            WatchAction::Start => {
+                let started = Config::load(Path::new(&self.watcher.config_path))
+                    .map_err(|e| format!("Cannot load watch config: {}", e))
+                    .and_then(|config| {
+                        self.watch_config = Some(config);
+                        self.watcher.start()
+                    });
+                if let Err(e) = started {
+                    self.status_message = format!("Error: {}", e);
+                }
+            }
+            WatchAction::Stop => {
+                self.watcher.stop();
+                // Drop searches that were waiting for their turn, they will be
+                // picked up again the next time the folder is watched.
+                self.queue
+                    .retain(|job| !matches!(job.origin, JobOrigin::Watch(..)));
+            }
         }
-        if self.config.mzml_paths.is_empty() && self.config.dotd_paths.is_empty() {
-            return Err("mzML file is not selected".into());
+    }
+
+    fn poll_watch_folder(&mut self) {
+        let Some(template) = &self.watch_config else {
+            return;
+        };
+        for run in self.watcher.poll() {
+            let mut config = template.clone();
+            config.mzml_paths.clear();
+            config.dotd_paths.clear();
+            config.cloud_paths.clear();
+            if run.is_dir() {
+                config.dotd_paths.push(run.clone());
+            } else {
+                config.mzml_paths.push(run.clone());
+            }
+            config.output_directory = self.watcher.output_for(&run).display().to_string();
+            self.queue
+                .push_back(Job::new(config, JobOrigin::Watch(run)));
+        }
+    }
+
+    fn start_next_job(&mut self) {
+        // Queued searches wait for a calibration search, both use every core
+        if self.is_running || self.calibration.is_running() {
+            return;
+        }
+        if let Some(mut job) = self.queue.pop_front() {
+            if !output::existing_outputs(&job.output_directory).is_empty() {
+                // Nobody is around to ask, so queued searches never overwrite
+                job.output_directory =
+                    output::timestamped_subfolder(&job.output_directory, Local::now());
+            }
+            let origin = job.origin.clone();
+            if let Err(e) = self.start_job(job) {
+                self.status_message = format!("Error: {}", e);
+                self.current_origin = Some(origin);
+                self.finish_job(&Err(e.to_string()));
+            }
+        }
+    }
+
+    /// Starts the search unless the output directory already has results, in which
+    /// case the job is parked in `pending_overwrite` until the user decides.
+    fn launch_application(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        check_config(&self.config)?;
+        let job = Job::new(self.config.clone(), JobOrigin::Manual);
+        let existing = output::existing_outputs(&job.output_directory);
+        if !existing.is_empty() {
+            self.pending_overwrite = Some(PendingOverwrite { job, existing });
+            return Ok(());
         }
+        self.start_job(job)
+    }
+
+    fn show_overwrite_dialog(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &self.pending_overwrite else {
+            return;
+        };
+        let mut choice = None;
+        egui::Window::new("Existing Results")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} already contains results of a previous search:",
+                    pending.job.output_directory
+                ));
+                for name in &pending.existing {
+                    ui.monospace(*name);
+                }
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Use Timestamped Subfolder").clicked() {
+                        choice = Some(OverwriteChoice::Subfolder);
+                    }
+                    if ui.button("Overwrite").clicked() {
+                        choice = Some(OverwriteChoice::Overwrite);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(OverwriteChoice::Cancel);
+                    }
+                });
+            });
+
+        let Some(choice) = choice else {
+            return;
+        };
+        let Some(PendingOverwrite { mut job, .. }) = self.pending_overwrite.take() else {
+            return;
+        };
+        let started = match choice {
+            OverwriteChoice::Subfolder => {
+                job.output_directory =
+                    output::timestamped_subfolder(&job.output_directory, Local::now());
+                self.start_job(job)
+            }
+            OverwriteChoice::Overwrite => self.start_job(job),
+            OverwriteChoice::Cancel => {
+                self.status_message = "Launch cancelled".to_string();
+                return;
+            }
+        };
+        self.status_message = match started {
+            Ok(_) => "Analysis started".to_string(),
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn start_job(&mut self, job: Job) -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = job.config;
+        check_config(&config)?;
+        // Provenance keeps the config as the user wrote it (template included),
+        // sage gets the resolved directory.
+        let launched_config = serde_json::to_value(&config)?;
+        config.output_directory = job.output_directory;
+        let output_directory = config.output_directory.clone();
+        let resolved_config = serde_json::to_value(&config)?;
+        let remote_inputs: Vec<String> = std::iter::once(&config.database.fasta)
+            .chain(config.cloud_paths.iter())
+            .filter(|p| cloud::is_s3_uri(p))
+            .cloned()
+            .collect();
+        let remote_output =
+            cloud::is_s3_uri(&config.output_directory).then(|| config.output_directory.clone());
+        let cloud_settings = self.cloud_settings.clone();
 
         let parallel = num_cpus::get() as u16 / 2;
         println!("Parallel: {}", parallel);
         let parquet = false;
-        let sage_input: Input = self.config.clone().into();
+
+        let fasta = config.database.fasta.clone();
+        let spectrum_files: Vec<String> = config
+            .mzml_paths
+            .iter()
+            .chain(config.dotd_paths.iter())
+            .map(|p| p.to_string_lossy().to_string())
+            .chain(config.cloud_paths.iter().cloned())
+            .collect();
+        let input_files = std::iter::once(fasta.clone())
+            .chain(spectrum_files.iter().cloned())
+            .collect();
+        let mut provenance = Provenance::new(
+            launched_config,
+            resolved_config,
+            config.output_directory.clone(),
+            input_files,
+            parallel,
+        );
+        let sage_input: Input = config.into();
 
         // Create channel for thread communication
         let (sender, receiver) = mpsc::channel();
@@ -1013,19 +2049,73 @@ impl SageLauncher {
             // Send initial progress
             let _ = sender.send(ThreadMessage::Progress("Starting analysis...".to_string()));
 
+            if !remote_inputs.is_empty() || remote_output.is_some() {
+                let _ = sender.send(ThreadMessage::Progress(
+                    "Checking remote inputs...".to_string(),
+                ));
+                let checked = remote_inputs
+                    .iter()
+                    .try_for_each(|uri| cloud::check_exists(&cloud_settings, uri))
+                    .and_then(|_| match &remote_output {
+                        Some(uri) => cloud::check_bucket(&cloud_settings, uri),
+                        None => Ok(()),
+                    });
+                if let Err(e) = checked {
+                    let result = Err(e);
+                    provenance.finish(&result);
+                    if let Err(e) = provenance.write() {
+                        println!("Could not write provenance: {}", e);
+                    }
+                    if let Err(e) = RunHistory::append(&provenance) {
+                        println!("Could not update run history: {}", e);
+                    }
+                    let _ = sender.send(ThreadMessage::Provenance(Box::new(provenance)));
+                    let _ = sender.send(ThreadMessage::Completed(result));
+                    return;
+                }
+            }
+
+            let _ = sender.send(ThreadMessage::Progress(
+                "Hashing input files...".to_string(),
+            ));
+            provenance.hash_inputs();
+            if let Err(e) = provenance.write() {
+                println!("Could not write provenance: {}", e);
+            }
+
             // TODO: I could build the input and on update and provide real time feedback.
 
             // Run the analysis
-            let result = match run_sage(sage_input, parallel, parquet) {
+            let _ = sender.send(ThreadMessage::Progress("Running sage...".to_string()));
+            let result = match run_sage(sage_input, parallel, parquet, &mut provenance) {
                 Ok(_) => Ok("Analysis completed successfully".to_string()),
                 Err(e) => Err(e.to_string()),
             };
 
+            if result.is_ok() {
+                let _ = sender.send(ThreadMessage::Progress("Counting results...".to_string()));
+                let output_directory = provenance.output_directory.clone();
+                provenance
+                    .stats
+                    .count(&fasta, &spectrum_files, &output_directory);
+            }
+            provenance.read_sage_version();
+            provenance.finish(&result);
+            if let Err(e) = provenance.write() {
+                println!("Could not write provenance: {}", e);
+            }
+            if let Err(e) = RunHistory::append(&provenance) {
+                println!("Could not update run history: {}", e);
+            }
+
             // Send completion message
+            let _ = sender.send(ThreadMessage::Provenance(Box::new(provenance)));
             let _ = sender.send(ThreadMessage::Completed(result));
         });
 
         self.thread_handle = Some(thread_handle);
+        self.current_origin = Some(job.origin);
+        self.current_output = Some(output_directory);
         self.start_time = Some(Instant::now());
         self.is_running = true;
 
@@ -1033,16 +2123,61 @@ impl SageLauncher {
     }
 }
 
-fn run_sage(input: Input, parallel: u16, parquet: bool) -> Result<(), Box<dyn std::error::Error>> {
+/// Whether this frame had input that can change settings. Hovering does not count,
+/// dragging does.
+fn has_user_input(ctx: &egui::Context) -> bool {
+    ctx.input(|i| {
+        i.pointer.any_down()
+            || i.events.iter().any(|event| {
+                !matches!(
+                    event,
+                    egui::Event::PointerMoved(_) | egui::Event::MouseMoved(_)
+                )
+            })
+    })
+}
+
+/// Refuse configs with validation errors, reporting the first one.
+fn check_config(config: &Config) -> Result<(), String> {
+    let report = validation::validate(config, &mut validation::PathChecks::default());
+    match report
+        .issues()
+        .iter()
+        .find(|i| i.severity == validation::Severity::Error)
+    {
+        Some(first) if report.error_count() > 1 => Err(format!(
+            "{} (and {} more configuration errors)",
+            first.message,
+            report.error_count() - 1
+        )),
+        Some(first) => Err(first.message.clone()),
+        None => Ok(()),
+    }
+}
+
+fn run_sage(
+    input: Input,
+    parallel: u16,
+    parquet: bool,
+    provenance: &mut Provenance,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("Running analysis... Building");
-    let runner = input
-        .build()
-        .and_then(|p| Runner::new(p, parallel.into()))?;
+    let started = Instant::now();
+    let search = input.build()?;
+    let runner = Runner::new(search, parallel.into())?;
+    provenance.stats.database_secs = Some(started.elapsed().as_secs_f64());
     println!("Running analysis... Executing");
-    let _tel = runner.run(parallel.into(), parquet)?;
+    let started = Instant::now();
+    let _telemetry = runner.run(parallel.into(), parquet)?;
+    provenance.stats.search_secs = Some(started.elapsed().as_secs_f64());
     Ok(())
 }
 
+/// Where sagegui keeps its own files (run history, presets ...).
+fn app_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("sagegui"))
+}
+
 // Helper function to format duration
 fn format_duration(duration: Duration) -> String {
     let total_secs = duration.as_secs();
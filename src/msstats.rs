@@ -0,0 +1,625 @@
+/// Long format input for MSstats (label free) and MSstatsTMT.
+///
+/// MSstats takes one line per feature and run, annotated with the run's condition and
+/// biological replicate from the experimental design. MSstatsTMT takes one line per
+/// PSM and reporter channel; conditions come from the channel annotation and the
+/// mixture (plex) of each run from the sample column of the experimental design.
+/// Missing intensities are written as `NA`.
+use crate::design::Design;
+use crate::impurity::CORRECTED_FILE_NAME;
+use crate::output::stem;
+use crate::quant::{self, TMT_FILE_NAME};
+use crate::results::{self, field, Psm, Tsv};
+use crate::ChannelAnnotation;
+use eframe::egui;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const MSSTATS_FILE_NAME: &str = "msstats.csv";
+pub const MSSTATS_TMT_FILE_NAME: &str = "msstats_tmt.csv";
+const Q_VALUE: f64 = 0.01;
+
+const LFQ_HEADER: [&str; 10] = [
+    "ProteinName",
+    "PeptideSequence",
+    "PrecursorCharge",
+    "FragmentIon",
+    "ProductCharge",
+    "IsotopeLabelType",
+    "Condition",
+    "BioReplicate",
+    "Run",
+    "Intensity",
+];
+
+const TMT_HEADER: [&str; 12] = [
+    "ProteinName",
+    "PeptideSequence",
+    "Charge",
+    "PSM",
+    "Mixture",
+    "TechRepMixture",
+    "Fraction",
+    "Run",
+    "Channel",
+    "Condition",
+    "BioReplicate",
+    "Intensity",
+];
+
+/// Problems with the annotations; errors block the export, warnings do not.
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Quoted if it would otherwise break the CSV, e.g. protein names with commas.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+fn csv_line(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    fields.join(",") + "\n"
+}
+
+fn intensity(value: Option<f64>) -> String {
+    value.map_or_else(|| "NA".to_string(), |v| v.to_string())
+}
+
+fn charge(charge: Option<u8>) -> String {
+    charge.map_or_else(|| "NA".to_string(), |c| c.to_string())
+}
+
+/// MSstats reads equal BioReplicate labels as the same subject, so the replicate
+/// number is qualified by its condition.
+fn bio_replicate(condition: &str, replicate: u32) -> String {
+    format!("{}_{}", condition, replicate)
+}
+
+/// `runs` are the intensity columns of `lfq.tsv`; `charges` is false when charge
+/// states were combined.
+pub fn validate_lfq(runs: &[String], design: &Design, charges: bool) -> Validation {
+    let mut validation = Validation::default();
+    let mut conditions = BTreeSet::new();
+    let mut seen: HashMap<(String, u32, u32, u32), String> = HashMap::new();
+    for run in runs {
+        let Some(row) = design.get(run) else {
+            validation
+                .errors
+                .push(format!("{}: not in the experimental design", stem(run)));
+            continue;
+        };
+        let condition = row.condition.trim();
+        if condition.is_empty() {
+            validation
+                .errors
+                .push(format!("{}: no condition set", stem(run)));
+            continue;
+        }
+        conditions.insert(condition);
+        let key = (
+            condition.to_string(),
+            row.bio_replicate,
+            row.tech_replicate,
+            row.fraction,
+        );
+        if let Some(other) = seen.insert(key, stem(run)) {
+            validation.warnings.push(format!(
+                "{} and {} have the same condition, replicates and fraction",
+                other,
+                stem(run)
+            ));
+        }
+    }
+    if conditions.len() < 2 {
+        validation
+            .warnings
+            .push("MSstats needs at least two conditions to compare".to_string());
+    }
+    if !charges {
+        validation.warnings.push(
+            "lfq.tsv has no charge column (charge states were combined), PrecursorCharge is written as NA"
+                .to_string(),
+        );
+    }
+    validation
+}
+
+/// Write `msstats.csv` from `lfq.tsv`; returns the number of lines written.
+pub fn write_lfq(dir: &Path, design: &Design) -> Result<(usize, Validation), String> {
+    let matrix = quant::read_lfq(dir)?;
+    let charges = matrix.rows.iter().any(|row| row.charge.is_some());
+    let validation = validate_lfq(&matrix.samples, design, charges);
+    if !validation.errors.is_empty() {
+        return Ok((0, validation));
+    }
+
+    let runs: Vec<(String, String, String, String)> = matrix
+        .samples
+        .iter()
+        .filter_map(|run| {
+            let row = design.get(run)?;
+            let condition = row.condition.trim().to_string();
+            Some((
+                bio_replicate(&condition, row.bio_replicate),
+                condition,
+                stem(run),
+                row.fraction.to_string(),
+            ))
+        })
+        .collect();
+    let fractionated = runs.iter().any(|(.., fraction)| fraction != "1");
+
+    let mut header = LFQ_HEADER.to_vec();
+    if fractionated {
+        header.insert(LFQ_HEADER.len() - 1, "Fraction");
+    }
+    let mut out = csv_line(&header);
+    let mut lines = 0;
+    for row in &matrix.rows {
+        let precursor_charge = charge(row.charge);
+        for ((bio, condition, run, fraction), value) in runs.iter().zip(&row.values) {
+            let value = intensity(*value);
+            let mut fields = vec![
+                row.proteins.as_str(),
+                row.peptide.as_str(),
+                precursor_charge.as_str(),
+                "NA",
+                "NA",
+                "L",
+                condition.as_str(),
+                bio.as_str(),
+                run.as_str(),
+            ];
+            if fractionated {
+                fields.push(fraction.as_str());
+            }
+            fields.push(value.as_str());
+            out.push_str(&csv_line(&fields));
+            lines += 1;
+        }
+    }
+    fs::write(dir.join(MSSTATS_FILE_NAME), out).map_err(|e| e.to_string())?;
+    Ok((lines, validation))
+}
+
+/// Mixture of a TMT run: the sample set in the experimental design.
+fn mixture(design: &Design, run: &str) -> Option<String> {
+    design
+        .get(run)
+        .map(|row| row.sample.trim().to_string())
+        .filter(|sample| !sample.is_empty())
+}
+
+/// `columns` are the reporter channels of `tmt.tsv`, `runs` its distinct files.
+pub fn validate_tmt(
+    columns: &[String],
+    runs: &[String],
+    channels: &[ChannelAnnotation],
+    design: &Design,
+) -> Validation {
+    let mut validation = Validation::default();
+    let labels: Vec<&str> = channels.iter().map(|c| c.channel.as_str()).collect();
+    if labels != columns {
+        validation.errors.push(format!(
+            "The channel annotation ({}) does not match the reporter columns of tmt.tsv ({})",
+            labels.join(", "),
+            columns.join(", ")
+        ));
+    }
+    if !channels.iter().any(|c| !c.condition.trim().is_empty()) {
+        validation
+            .errors
+            .push("No channel has a condition".to_string());
+    }
+    let empty: Vec<&str> = channels
+        .iter()
+        .filter(|c| !c.reference && c.condition.trim().is_empty())
+        .map(|c| c.channel.as_str())
+        .collect();
+    if !empty.is_empty() {
+        validation.warnings.push(format!(
+            "Channels without a condition are written as 'Empty': {}",
+            empty.join(", ")
+        ));
+    }
+
+    let missing: Vec<String> = runs
+        .iter()
+        .filter(|run| mixture(design, run).is_none())
+        .map(|run| stem(run))
+        .collect();
+    if missing.len() == runs.len() {
+        validation
+            .warnings
+            .push("No mixtures set in the experimental design, all runs are mixture 1".to_string());
+    } else if !missing.is_empty() {
+        validation.errors.push(format!(
+            "No mixture (design sample) set for: {}",
+            missing.join(", ")
+        ));
+    }
+    validation
+}
+
+/// Condition and BioReplicate of a channel. Reference channels follow the MSstatsTMT
+/// convention of `Norm`; with several mixtures the channel's samples are different
+/// subjects in each.
+fn channel_labels(channel: &ChannelAnnotation, mixture: &str, mixtures: usize) -> (String, String) {
+    if channel.reference {
+        return ("Norm".to_string(), "Norm".to_string());
+    }
+    let condition = match channel.condition.trim() {
+        "" => return ("Empty".to_string(), "Empty".to_string()),
+        condition => condition.to_string(),
+    };
+    let bio = match channel.sample.trim() {
+        "" => bio_replicate(&condition, channel.replicate),
+        sample => sample.to_string(),
+    };
+    match mixtures > 1 {
+        true => (condition, format!("{}_{}", mixture, bio)),
+        false => (condition, bio),
+    }
+}
+
+/// Run, peptide and charge.
+type Feature = (String, String, Option<u8>);
+
+/// Write `msstats_tmt.csv` from the confident PSMs and their reporter ions. Of
+/// several spectra of a peptide and charge in one run, the most intense is kept.
+pub fn write_tmt(
+    dir: &Path,
+    corrected: bool,
+    channels: &[ChannelAnnotation],
+    design: &Design,
+) -> Result<(usize, Validation), String> {
+    let psms: HashMap<(String, String), Psm> = results::read_psms(dir)?
+        .into_iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && psm.spectrum_q <= Q_VALUE)
+        .map(|psm| ((psm.filename.clone(), psm.scannr.clone()), psm))
+        .collect();
+
+    let file_name = match corrected {
+        true => CORRECTED_FILE_NAME,
+        false => TMT_FILE_NAME,
+    };
+    let tsv = Tsv::open(&dir.join(file_name))?;
+    let filename = tsv.column("filename")?;
+    let scannr = tsv.column("scannr")?;
+    let (columns, indices): (Vec<String>, Vec<usize>) = tsv
+        .header()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((name.strip_prefix("tmt_")?.to_string(), i)))
+        .unzip();
+
+    let mut best: HashMap<Feature, (&Psm, Vec<Option<f64>>)> = HashMap::new();
+    for row in tsv {
+        let Some(psm) = row
+            .get(filename)
+            .zip(row.get(scannr))
+            .and_then(|(file, scan)| psms.get(&(file.clone(), scan.clone())))
+        else {
+            continue;
+        };
+        let values: Vec<Option<f64>> = indices
+            .iter()
+            .map(|&i| field::<f64>(&row, i).filter(|v| *v > 0.0 && v.is_finite()))
+            .collect();
+        let total: f64 = values.iter().flatten().sum();
+        let key = (psm.filename.clone(), psm.peptide.clone(), psm.charge);
+        match best.get(&key) {
+            Some((_, kept)) if kept.iter().flatten().sum::<f64>() >= total => {}
+            _ => {
+                best.insert(key, (psm, values));
+            }
+        }
+    }
+    if best.is_empty() {
+        return Err(format!(
+            "No spectra in {} match a confident identification",
+            file_name
+        ));
+    }
+
+    let runs: Vec<String> = best
+        .keys()
+        .map(|(run, ..)| run.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let validation = validate_tmt(&columns, &runs, channels, design);
+    if !validation.errors.is_empty() {
+        return Ok((0, validation));
+    }
+    let mixtures = runs
+        .iter()
+        .filter_map(|run| mixture(design, run))
+        .collect::<BTreeSet<_>>()
+        .len();
+
+    let mut features: Vec<_> = best.into_iter().collect();
+    features.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut out = csv_line(&TMT_HEADER);
+    let mut lines = 0;
+    for ((run, peptide, precursor_charge), (psm, values)) in features {
+        let row = design.get(&run);
+        let mix = mixture(design, &run).unwrap_or_else(|| "1".to_string());
+        let tech = row.map_or(1, |row| row.tech_replicate).to_string();
+        let fraction = row.map_or(1, |row| row.fraction).to_string();
+        let precursor_charge = charge(precursor_charge);
+        let feature = format!("{}_{}", peptide, precursor_charge);
+        let run = stem(&run);
+        for (channel, value) in channels.iter().zip(values) {
+            let (condition, bio) = channel_labels(channel, &mix, mixtures);
+            let value = intensity(value);
+            out.push_str(&csv_line(&[
+                &psm.proteins,
+                &peptide,
+                &precursor_charge,
+                &feature,
+                &mix,
+                &tech,
+                &fraction,
+                &run,
+                &channel.channel,
+                &condition,
+                &bio,
+                &value,
+            ]));
+            lines += 1;
+        }
+    }
+    fs::write(dir.join(MSSTATS_TMT_FILE_NAME), out).map_err(|e| e.to_string())?;
+    Ok((lines, validation))
+}
+
+#[derive(Default)]
+pub struct MsstatsExport {
+    corrected: bool,
+    validation: Validation,
+    message: String,
+}
+
+impl MsstatsExport {
+    fn finish(&mut self, result: Result<(usize, Validation), String>, file_name: &str) {
+        (self.message, self.validation) = match result {
+            Ok((_, validation)) if !validation.errors.is_empty() => (
+                "Not written, fix the annotations below".to_string(),
+                validation,
+            ),
+            Ok((lines, validation)) => (
+                format!("{} lines written to {}", lines, file_name),
+                validation,
+            ),
+            Err(e) => (format!("Error: {}", e), Validation::default()),
+        };
+    }
+
+    /// `channels` is the TMT channel annotation, empty for label free searches.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        dir: &Path,
+        design: &Design,
+        channels: &[ChannelAnnotation],
+    ) {
+        ui.horizontal(|ui| {
+            if ui.button("Write msstats.csv").clicked() {
+                let result = write_lfq(dir, design);
+                self.finish(result, MSSTATS_FILE_NAME);
+            }
+            ui.weak("from lfq.tsv and the experimental design");
+        });
+        ui.horizontal(|ui| {
+            let enabled = !channels.is_empty();
+            let button = ui
+                .add_enabled(enabled, egui::Button::new("Write msstats_tmt.csv"))
+                .on_disabled_hover_text("Select TMT quantification and annotate its channels");
+            if button.clicked() {
+                let result = write_tmt(dir, self.corrected, channels, design);
+                self.finish(result, MSSTATS_TMT_FILE_NAME);
+            }
+            ui.checkbox(&mut self.corrected, "Impurity corrected");
+        });
+        ui.weak(
+            "For TMT the sample column of the experimental design names the mixture of each run.",
+        );
+        ui.label(&self.message);
+
+        for error in &self.validation.errors {
+            ui.colored_label(egui::Color32::RED, format!("⛔ {}", error));
+        }
+        for warning in &self.validation.warnings {
+            ui.colored_label(
+                egui::Color32::from_rgb(230, 160, 0),
+                format!("⚠ {}", warning),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::design::DesignRow;
+
+    fn design(rows: &[(&str, &str, &str, u32, u32)]) -> Design {
+        Design {
+            rows: rows
+                .iter()
+                .map(|&(file, sample, condition, bio, fraction)| DesignRow {
+                    file: file.to_string(),
+                    sample: sample.to_string(),
+                    condition: condition.to_string(),
+                    bio_replicate: bio,
+                    tech_replicate: 1,
+                    fraction,
+                })
+                .collect(),
+        }
+    }
+
+    fn runs(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("sp|P1|A_HUMAN"), "sp|P1|A_HUMAN");
+        assert_eq!(csv_field("P1,P2"), "\"P1,P2\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(
+            csv_line(&["P1;P2", "a,b", "NA"]),
+            "P1;P2,\"a,b\",NA\n".to_string()
+        );
+    }
+
+    #[test]
+    fn validates_the_lfq_design() {
+        let design = design(&[
+            ("a1", "", "A", 1, 1),
+            ("a2", "", "A", 1, 1),
+            ("b1", "", "B", 1, 1),
+            ("blank", "", " ", 1, 1),
+        ]);
+        let validation = validate_lfq(
+            &runs(&[
+                "/d/a1.mzML",
+                "a2.mzML",
+                "b1.mzML",
+                "blank.mzML",
+                "missing.mzML",
+            ]),
+            &design,
+            true,
+        );
+        assert_eq!(
+            validation.errors,
+            [
+                "blank: no condition set",
+                "missing: not in the experimental design"
+            ]
+        );
+        assert_eq!(
+            validation.warnings,
+            ["a1 and a2 have the same condition, replicates and fraction"]
+        );
+
+        // One condition, and charge states combined
+        let validation = validate_lfq(&runs(&["a1.mzML"]), &design, false);
+        assert!(validation.errors.is_empty());
+        assert_eq!(validation.warnings.len(), 2);
+    }
+
+    #[test]
+    fn writes_fractions_only_when_fractionated() {
+        let dir = std::env::temp_dir().join(format!("sagegui_msstats_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(quant::LFQ_FILE_NAME),
+            "peptide\tcharge\tproteins\tq_value\ta1.mzML\tb1.mzML\n\
+             PEPTIDE\t2\tsp|P1|A,B;P2\t0.001\t100\t0\n",
+        )
+        .unwrap();
+
+        let plain = design(&[("a1", "", "A", 1, 1), ("b1", "", "B", 1, 1)]);
+        let (lines, validation) = write_lfq(&dir, &plain).unwrap();
+        assert_eq!(lines, 2);
+        assert!(validation.errors.is_empty());
+        let text = fs::read_to_string(dir.join(MSSTATS_FILE_NAME)).unwrap();
+        assert_eq!(
+            text,
+            "ProteinName,PeptideSequence,PrecursorCharge,FragmentIon,ProductCharge,IsotopeLabelType,Condition,BioReplicate,Run,Intensity\n\
+             \"sp|P1|A,B;P2\",PEPTIDE,2,NA,NA,L,A,A_1,a1,100\n\
+             \"sp|P1|A,B;P2\",PEPTIDE,2,NA,NA,L,B,B_1,b1,NA\n"
+        );
+
+        let fractionated = design(&[("a1", "", "A", 1, 1), ("b1", "", "B", 1, 2)]);
+        write_lfq(&dir, &fractionated).unwrap();
+        let text = fs::read_to_string(dir.join(MSSTATS_FILE_NAME)).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].ends_with(",Run,Fraction,Intensity"));
+        assert!(lines[2].ends_with(",b1,2,NA"));
+
+        // Annotation errors write nothing
+        fs::remove_file(dir.join(MSSTATS_FILE_NAME)).unwrap();
+        let incomplete = design(&[("a1", "", "A", 1, 1)]);
+        let (lines, validation) = write_lfq(&dir, &incomplete).unwrap();
+        assert_eq!((lines, validation.errors.len()), (0, 1));
+        assert!(!dir.join(MSSTATS_FILE_NAME).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn channel(name: &str, sample: &str, condition: &str, reference: bool) -> ChannelAnnotation {
+        ChannelAnnotation {
+            channel: name.to_string(),
+            sample: sample.to_string(),
+            condition: condition.to_string(),
+            replicate: 1,
+            reference,
+        }
+    }
+
+    #[test]
+    fn validates_the_tmt_annotation() {
+        let channels = [
+            channel("126", "", "ctrl", false),
+            channel("127N", "", "", false),
+            channel("127C", "", "", true),
+        ];
+        let columns = runs(&["126", "127N", "127C"]);
+        let mixtures = design(&[("r1", "plex1", "", 1, 1), ("r2", "", "", 1, 1)]);
+
+        let validation = validate_tmt(&columns, &runs(&["r1.mzML"]), &channels, &mixtures);
+        assert!(validation.errors.is_empty());
+        assert_eq!(
+            validation.warnings,
+            ["Channels without a condition are written as 'Empty': 127N"]
+        );
+
+        // A mixture for some runs only, and a different plex
+        let validation = validate_tmt(
+            &runs(&["126", "127"]),
+            &runs(&["r1.mzML", "r2.mzML"]),
+            &channels,
+            &mixtures,
+        );
+        assert_eq!(validation.errors.len(), 2);
+        assert!(validation.errors[1].ends_with("for: r2"));
+
+        let unlabelled = [channel("126", "", "", false)];
+        let validation = validate_tmt(&runs(&["126"]), &runs(&["r2"]), &unlabelled, &mixtures);
+        assert_eq!(validation.errors, ["No channel has a condition"]);
+        assert!(validation.warnings[1].contains("all runs are mixture 1"));
+    }
+
+    #[test]
+    fn labels_tmt_channels() {
+        let labels = |c: &ChannelAnnotation, mixtures| channel_labels(c, "plex1", mixtures);
+        let pair = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(
+            labels(&channel("126", "", "ctrl", true), 1),
+            pair("Norm", "Norm")
+        );
+        assert_eq!(
+            labels(&channel("126", "", " ", false), 1),
+            pair("Empty", "Empty")
+        );
+        assert_eq!(
+            labels(&channel("126", "", "ctrl", false), 1),
+            pair("ctrl", "ctrl_1")
+        );
+        assert_eq!(
+            labels(&channel("126", "mouse3", "ctrl", false), 2),
+            pair("ctrl", "plex1_mouse3")
+        );
+    }
+}
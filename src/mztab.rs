@@ -0,0 +1,812 @@
+/// mzTab 1.0 (Summary mode) export of identifications and label free quantities, as
+/// PRIDE takes them for submission.
+///
+/// Metadata comes from the search settings of the run, PSMs and peptides from
+/// `results.sage.tsv`, protein groups from sage's protein q-values and their
+/// abundances from `lfq.tsv` and `lfq.proteins.tsv` when those exist. The written file
+/// is checked against the metadata and columns mzTab requires for its mode and type.
+use crate::compare;
+use crate::design::Design;
+use crate::output::stem;
+use crate::psi::{self, location, mod_site, parse_peptide, searched_mods, unimod, PROTON};
+use crate::quant;
+use crate::results::{self, Psm, Tsv};
+use crate::rollup::PROTEIN_FILE_NAME;
+use crate::stats;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const MZTAB_FILE_NAME: &str = "results.mzTab";
+const Q_VALUE: f64 = 0.01;
+
+const PSM_COLUMNS: [&str; 19] = [
+    "sequence",
+    "PSM_ID",
+    "accession",
+    "unique",
+    "database",
+    "database_version",
+    "search_engine",
+    "search_engine_score[1]",
+    "search_engine_score[2]",
+    "modifications",
+    "retention_time",
+    "charge",
+    "exp_mass_to_charge",
+    "calc_mass_to_charge",
+    "spectra_ref",
+    "pre",
+    "post",
+    "start",
+    "end",
+];
+const PEPTIDE_COLUMNS: [&str; 13] = [
+    "sequence",
+    "accession",
+    "unique",
+    "database",
+    "database_version",
+    "search_engine",
+    "best_search_engine_score[1]",
+    "modifications",
+    "retention_time",
+    "retention_time_window",
+    "charge",
+    "mass_to_charge",
+    "spectra_ref",
+];
+const PROTEIN_COLUMNS: [&str; 10] = [
+    "accession",
+    "description",
+    "taxid",
+    "species",
+    "database",
+    "database_version",
+    "search_engine",
+    "best_search_engine_score[1]",
+    "ambiguity_members",
+    "modifications",
+];
+
+/// Parameter for the metadata section.
+fn mod_param(mass: f64) -> String {
+    match unimod(mass) {
+        Some((accession, name)) => format!("[UNIMOD, UNIMOD:{}, {}, ]", accession, name),
+        None => format!("[CHEMMOD, CHEMMOD:{:+}, , ]", mass),
+    }
+}
+
+fn modifications(mods: &[(usize, f64)]) -> String {
+    if mods.is_empty() {
+        return "null".to_string();
+    }
+    let mods: Vec<String> = mods
+        .iter()
+        .map(|&(position, mass)| match unimod(mass) {
+            Some((accession, _)) => format!("{}-UNIMOD:{}", position, accession),
+            None => format!("{}-CHEMMOD:{:+}", position, mass),
+        })
+        .collect();
+    mods.join(",")
+}
+
+/// `1` if the peptide maps to a single protein.
+fn unique(accessions: &[&str]) -> String {
+    match accessions.len() {
+        1 => "1".to_string(),
+        _ => "0".to_string(),
+    }
+}
+
+fn number(value: Option<f64>) -> String {
+    match value {
+        Some(value) if value.is_nan() => "NaN".to_string(),
+        Some(value) => value.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+fn mass_to_charge(mass: f64, charge: Option<u8>) -> Option<f64> {
+    let z = charge.filter(|&z| z > 0)? as f64;
+    Some((mass + z * PROTON) / z)
+}
+
+/// An input file of the search, `ms_run[i]` in this order.
+struct Run {
+    stem: String,
+    location: String,
+    /// A native ID of the run, to tell the ID format from.
+    example_id: Option<String>,
+}
+
+/// Native ID format of a run, from sage's `scannr` values.
+fn id_format(example: &str) -> String {
+    let (accession, name) = psi::id_format(example);
+    format!("[MS, {}, {}, ]", accession, name)
+}
+
+fn spectra_ref(run: usize, scannr: &str) -> String {
+    match scannr.contains('=') {
+        true => format!("ms_run[{}]:{}", run + 1, scannr),
+        false => format!("ms_run[{}]:scan={}", run + 1, scannr),
+    }
+}
+
+fn tolerance(value: Option<&Value>) -> Option<String> {
+    let (unit, bounds) = value?.as_object()?.iter().next()?;
+    let bounds = bounds.as_array()?;
+    Some(format!(
+        "{} to {} {}",
+        bounds.first()?,
+        bounds.get(1)?,
+        unit
+    ))
+}
+
+fn enzyme(settings: &Value) -> Option<String> {
+    let enzyme = settings.pointer("/database/enzyme")?;
+    let cleave_at = enzyme.get("cleave_at")?.as_str()?;
+    let restrict = enzyme.get("restrict").and_then(Value::as_str);
+    let missed = enzyme
+        .get("missed_cleavages")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let name = match (cleave_at, restrict) {
+        ("KR", Some("P")) => "Trypsin",
+        ("KR", None) => "Trypsin/P",
+        _ => "custom",
+    };
+    let restrict = restrict
+        .map(|r| format!(", not before '{}'", r))
+        .unwrap_or_default();
+    Some(format!(
+        "{} (cleave at '{}'{}, {} missed cleavages)",
+        name, cleave_at, restrict, missed
+    ))
+}
+
+/// Label free quantities per run; assay `i` is `ms_run[i]`.
+struct Quant {
+    /// Description and assays of every study variable.
+    variables: Vec<(String, Vec<usize>)>,
+    peptides: HashMap<(String, Option<u8>), Vec<Option<f64>>>,
+    proteins: HashMap<String, Vec<Option<f64>>>,
+}
+
+impl Quant {
+    fn read(dir: &Path, runs: &[Run], design: &Design) -> Result<Option<Self>, String> {
+        if !dir.join(quant::LFQ_FILE_NAME).exists() {
+            return Ok(None);
+        }
+        let matrix = quant::read_lfq(dir)?;
+        let columns: Vec<Option<usize>> = runs
+            .iter()
+            .map(|run| matrix.samples.iter().position(|s| stem(s) == run.stem))
+            .collect();
+        let peptides = matrix
+            .rows
+            .iter()
+            .map(|row| {
+                let values = columns.iter().map(|c| c.and_then(|c| row.values[c]));
+                ((row.peptide.clone(), row.charge), values.collect())
+            })
+            .collect();
+
+        // Rolled up proteins are written under the design's column names
+        let mut proteins = HashMap::new();
+        if let Ok(tsv) = Tsv::open(&dir.join(PROTEIN_FILE_NAME)) {
+            let header = tsv.header();
+            let protein = tsv.column("protein")?;
+            let columns: Vec<Option<usize>> = runs
+                .iter()
+                .map(|run| {
+                    let name = design.column_name(&run.stem);
+                    header.iter().position(|h| *h == name)
+                })
+                .collect();
+            for row in tsv {
+                let Some(accession) = row.get(protein) else {
+                    continue;
+                };
+                let values = columns
+                    .iter()
+                    .map(|c| c.and_then(|c| results::field::<f64>(&row, c)))
+                    .collect();
+                proteins.insert(accession.clone(), values);
+            }
+        }
+
+        let conditions: Option<Vec<&str>> =
+            runs.iter().map(|run| design.condition(&run.stem)).collect();
+        let variables = match conditions {
+            Some(conditions) => design
+                .conditions()
+                .into_iter()
+                .map(|condition| {
+                    let assays = (0..runs.len())
+                        .filter(|&i| conditions[i] == condition)
+                        .collect();
+                    (condition, assays)
+                })
+                .filter(|(_, assays): &(String, Vec<usize>)| !assays.is_empty())
+                .collect(),
+            None => runs
+                .iter()
+                .enumerate()
+                .map(|(i, run)| (design.column_name(&run.stem), vec![i]))
+                .collect(),
+        };
+        Ok(Some(Self {
+            variables,
+            peptides,
+            proteins,
+        }))
+    }
+
+    fn header(&self, section: &str, runs: usize) -> Vec<String> {
+        let mut columns: Vec<String> = (1..=runs)
+            .map(|i| format!("{}_abundance_assay[{}]", section, i))
+            .collect();
+        for j in 1..=self.variables.len() {
+            for kind in ["", "stdev_", "std_error_"] {
+                columns.push(format!(
+                    "{}_abundance_{}study_variable[{}]",
+                    section, kind, j
+                ));
+            }
+        }
+        columns
+    }
+
+    /// Assay values, then mean, standard deviation and standard error per study variable.
+    fn cells(&self, values: Option<&Vec<Option<f64>>>, runs: usize) -> Vec<String> {
+        let values = values.cloned().unwrap_or_else(|| vec![None; runs]);
+        let mut cells: Vec<String> = values.iter().map(|v| number(*v)).collect();
+        for (_, assays) in &self.variables {
+            let present: Vec<f64> = assays.iter().filter_map(|&i| values[i]).collect();
+            let sd = stats::variance(&present).map(f64::sqrt);
+            cells.push(number(stats::mean(&present)));
+            cells.push(number(sd));
+            cells.push(number(sd.map(|sd| sd / (present.len() as f64).sqrt())));
+        }
+        cells
+    }
+}
+
+fn line(prefix: &str, cells: &[String]) -> String {
+    format!("{}\t{}\n", prefix, cells.join("\t"))
+}
+
+fn mtd(out: &mut String, key: &str, value: &str) {
+    out.push_str(&format!("MTD\t{}\t{}\n", key, value));
+}
+
+/// Write `results.mzTab`; returns the number of PSM rows and the problems found by
+/// `validate`.
+pub fn write(dir: &Path, design: &Design) -> Result<(usize, Vec<String>), String> {
+    let settings =
+        compare::settings(dir).ok_or("No search settings found, they come from results.json")?;
+    let psms: Vec<Psm> = results::read_psms(dir)?
+        .into_iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && psm.spectrum_q <= Q_VALUE)
+        .collect();
+
+    let mut runs: Vec<Run> = settings
+        .get("mzml_paths")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|path| Run {
+            stem: stem(path),
+            location: location(path),
+            example_id: None,
+        })
+        .collect();
+    let mut run_of: HashMap<String, usize> = HashMap::new();
+    for psm in &psms {
+        let name = stem(&psm.filename);
+        let i = match runs.iter().position(|run| run.stem == name) {
+            Some(i) => i,
+            None => {
+                runs.push(Run {
+                    stem: name.clone(),
+                    location: location(&psm.filename),
+                    example_id: None,
+                });
+                runs.len() - 1
+            }
+        };
+        runs[i].example_id.get_or_insert_with(|| psm.scannr.clone());
+        run_of.insert(psm.filename.clone(), i);
+    }
+    if runs.is_empty() {
+        return Err("The search has no input files".to_string());
+    }
+    let quant = Quant::read(dir, &runs, design)?;
+
+    let version = settings
+        .get("version")
+        .and_then(Value::as_str)
+        .unwrap_or("unknown");
+    let engine = format!("[, , Sage, {}]", version);
+    let database = settings
+        .pointer("/database/fasta")
+        .and_then(Value::as_str)
+        .map(stem)
+        .unwrap_or_else(|| "null".to_string());
+
+    let mut out = String::new();
+    mtd(&mut out, "mzTab-version", "1.0.0");
+    mtd(&mut out, "mzTab-mode", "Summary");
+    let kind = match quant {
+        Some(_) => "Quantification",
+        None => "Identification",
+    };
+    mtd(&mut out, "mzTab-type", kind);
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "sage".to_string());
+    mtd(&mut out, "mzTab-ID", &name);
+    mtd(
+        &mut out,
+        "description",
+        &format!("Sage search results {}", name),
+    );
+    for (i, run) in runs.iter().enumerate() {
+        let key = format!("ms_run[{}]", i + 1);
+        if run.location.to_lowercase().ends_with(".mzml") {
+            mtd(
+                &mut out,
+                &format!("{}-format", key),
+                "[MS, MS:1000584, mzML format, ]",
+            );
+        }
+        mtd(&mut out, &format!("{}-location", key), &run.location);
+        if let Some(example) = &run.example_id {
+            mtd(&mut out, &format!("{}-id_format", key), &id_format(example));
+        }
+    }
+    if let Some(quant) = &quant {
+        for i in 1..=runs.len() {
+            mtd(
+                &mut out,
+                &format!("assay[{}]-quantification_reagent", i),
+                "[MS, MS:1002038, unlabeled sample, ]",
+            );
+            mtd(
+                &mut out,
+                &format!("assay[{}]-ms_run_ref", i),
+                &format!("ms_run[{}]", i),
+            );
+        }
+        for (j, (description, assays)) in quant.variables.iter().enumerate() {
+            let refs: Vec<String> = assays.iter().map(|i| format!("assay[{}]", i + 1)).collect();
+            mtd(
+                &mut out,
+                &format!("study_variable[{}]-assay_refs", j + 1),
+                &refs.join(","),
+            );
+            mtd(
+                &mut out,
+                &format!("study_variable[{}]-description", j + 1),
+                description,
+            );
+        }
+    }
+
+    mtd(&mut out, "software[1]", &engine);
+    let mut setting = Vec::new();
+    setting.extend(enzyme(&settings).map(|e| format!("enzyme = {}", e)));
+    setting.extend(
+        tolerance(settings.get("precursor_tol")).map(|t| format!("precursor tolerance = {}", t)),
+    );
+    setting.extend(
+        tolerance(settings.get("fragment_tol")).map(|t| format!("fragment tolerance = {}", t)),
+    );
+    if let Some(tag) = settings
+        .pointer("/database/decoy_tag")
+        .and_then(Value::as_str)
+    {
+        setting.push(format!("decoy tag = {}", tag));
+    }
+    setting.push(format!("q-value threshold = {}", Q_VALUE));
+    for (i, s) in setting.iter().enumerate() {
+        mtd(&mut out, &format!("software[1]-setting[{}]", i + 1), s);
+    }
+    mtd(
+        &mut out,
+        "software[2]",
+        &format!("[, , sagegui, {}]", env!("CARGO_PKG_VERSION")),
+    );
+    mtd(
+        &mut out,
+        "psm_search_engine_score[1]",
+        "[MS, MS:1001143, search engine specific score for PSMs, ]",
+    );
+    mtd(
+        &mut out,
+        "psm_search_engine_score[2]",
+        "[MS, MS:1002354, PSM-level q-value, ]",
+    );
+    mtd(
+        &mut out,
+        "peptide_search_engine_score[1]",
+        "[MS, MS:1001868, distinct peptide-level q-value, ]",
+    );
+    mtd(
+        &mut out,
+        "protein_search_engine_score[1]",
+        "[MS, MS:1002373, protein group-level q-value, ]",
+    );
+    for (kind, key, none) in [
+        (
+            "fixed_mod",
+            "static_mods",
+            "[MS, MS:1002453, No fixed modifications searched, ]",
+        ),
+        (
+            "variable_mod",
+            "variable_mods",
+            "[MS, MS:1002454, No variable modifications searched, ]",
+        ),
+    ] {
+        let mods = searched_mods(&settings, key);
+        if mods.is_empty() {
+            mtd(&mut out, &format!("{}[1]", kind), none);
+        }
+        for (i, (site, mass)) in mods.iter().enumerate() {
+            let (site, position) = mod_site(site);
+            let key = format!("{}[{}]", kind, i + 1);
+            mtd(&mut out, &key, &mod_param(*mass));
+            mtd(&mut out, &format!("{}-site", key), &site);
+            mtd(&mut out, &format!("{}-position", key), position);
+        }
+    }
+    if quant.is_some() {
+        let unit = "[PRIDE, PRIDE:0000393, Relative quantification unit, ]";
+        mtd(
+            &mut out,
+            "quantification_method",
+            "[MS, MS:1001834, LC-MS label-free quantitation analysis, ]",
+        );
+        mtd(&mut out, "protein-quantification_unit", unit);
+        mtd(&mut out, "peptide-quantification_unit", unit);
+    }
+    out.push('\n');
+
+    // Proteins: one row per protein group, the first accession leads
+    let mut groups: BTreeMap<&str, f64> = BTreeMap::new();
+    for psm in psms.iter().filter(|psm| psm.protein_q <= Q_VALUE) {
+        let q = groups.entry(psm.proteins.as_str()).or_insert(psm.protein_q);
+        *q = q.min(psm.protein_q);
+    }
+    let mut header: Vec<String> = PROTEIN_COLUMNS.iter().map(|c| c.to_string()).collect();
+    if let Some(quant) = &quant {
+        header.extend(quant.header("protein", runs.len()));
+    }
+    out.push_str(&line("PRH", &header));
+    for (group, q) in &groups {
+        let accessions: Vec<&str> = group.split(';').collect();
+        let members = match accessions.len() {
+            1 => "null".to_string(),
+            _ => accessions[1..].join(","),
+        };
+        let mut cells = vec![
+            accessions[0].to_string(),
+            "null".to_string(),
+            "null".to_string(),
+            "null".to_string(),
+            database.clone(),
+            "null".to_string(),
+            engine.clone(),
+            q.to_string(),
+            members,
+            "null".to_string(),
+        ];
+        if let Some(quant) = &quant {
+            cells.extend(quant.cells(quant.proteins.get(*group), runs.len()));
+        }
+        out.push_str(&line("PRT", &cells));
+    }
+    out.push('\n');
+
+    // Peptides: the best PSM of every peptide and charge
+    let mut best: BTreeMap<(&str, Option<u8>), &Psm> = BTreeMap::new();
+    for psm in psms.iter().filter(|psm| psm.peptide_q <= Q_VALUE) {
+        let entry = best
+            .entry((psm.peptide.as_str(), psm.charge))
+            .or_insert(psm);
+        if psm.hyperscore > entry.hyperscore {
+            *entry = psm;
+        }
+    }
+    let mut header: Vec<String> = PEPTIDE_COLUMNS.iter().map(|c| c.to_string()).collect();
+    if let Some(quant) = &quant {
+        header.extend(quant.header("peptide", runs.len()));
+    }
+    out.push_str(&line("PEH", &header));
+    for ((peptide, charge), psm) in &best {
+        let (sequence, mods) = parse_peptide(peptide);
+        let accessions: Vec<&str> = psm.proteins.split(';').collect();
+        let run = run_of[&psm.filename];
+        let mut cells = vec![
+            sequence,
+            accessions[0].to_string(),
+            unique(&accessions),
+            database.clone(),
+            "null".to_string(),
+            engine.clone(),
+            psm.peptide_q.to_string(),
+            modifications(&mods),
+            number(psm.rt.map(|rt| rt * 60.0)),
+            "null".to_string(),
+            charge.map_or_else(|| "null".to_string(), |z| z.to_string()),
+            number(mass_to_charge(psm.calcmass, *charge)),
+            spectra_ref(run, &psm.scannr),
+        ];
+        if let Some(quant) = &quant {
+            let values = quant
+                .peptides
+                .get(&(peptide.to_string(), *charge))
+                .or_else(|| quant.peptides.get(&(peptide.to_string(), None)));
+            cells.extend(quant.cells(values, runs.len()));
+        }
+        out.push_str(&line("PEP", &cells));
+    }
+    out.push('\n');
+
+    // PSMs: one row per protein the peptide maps to
+    let header: Vec<String> = PSM_COLUMNS.iter().map(|c| c.to_string()).collect();
+    out.push_str(&line("PSH", &header));
+    let mut rows = 0;
+    for psm in &psms {
+        let (sequence, mods) = parse_peptide(&psm.peptide);
+        let accessions: Vec<&str> = psm.proteins.split(';').collect();
+        let run = run_of[&psm.filename];
+        for accession in &accessions {
+            let cells = vec![
+                sequence.clone(),
+                psm.psm_id.to_string(),
+                accession.to_string(),
+                unique(&accessions),
+                database.clone(),
+                "null".to_string(),
+                engine.clone(),
+                psm.hyperscore.to_string(),
+                psm.spectrum_q.to_string(),
+                modifications(&mods),
+                number(psm.rt.map(|rt| rt * 60.0)),
+                psm.charge
+                    .map_or_else(|| "null".to_string(), |z| z.to_string()),
+                number(mass_to_charge(psm.expmass, psm.charge)),
+                number(mass_to_charge(psm.calcmass, psm.charge)),
+                spectra_ref(run, &psm.scannr),
+                "null".to_string(),
+                "null".to_string(),
+                "null".to_string(),
+                "null".to_string(),
+            ];
+            out.push_str(&line("PSM", &cells));
+            rows += 1;
+        }
+    }
+
+    fs::write(dir.join(MZTAB_FILE_NAME), &out).map_err(|e| e.to_string())?;
+    Ok((rows, validate(&out)))
+}
+
+/// Problems with an mzTab document: metadata or columns missing for its mode and type,
+/// rows that do not match their header, empty cells, references to undefined runs.
+pub fn validate(text: &str) -> Vec<String> {
+    const MAX_PROBLEMS: usize = 20;
+    let mut problems = Vec::new();
+    let mut metadata: HashMap<&str, &str> = HashMap::new();
+    let mut headers: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut current: Vec<&str> = Vec::new();
+
+    for (n, text_line) in text.lines().enumerate() {
+        let cells: Vec<&str> = text_line.split('\t').collect();
+        match cells[0] {
+            "" | "COM" => {}
+            "MTD" if cells.len() == 3 => {
+                metadata.insert(cells[1], cells[2]);
+            }
+            "MTD" => problems.push(format!("Line {}: metadata needs a key and a value", n + 1)),
+            "PRH" | "PEH" | "PSH" => {
+                headers.insert(cells[0], cells[1..].to_vec());
+                current = cells[1..].to_vec();
+            }
+            "PRT" | "PEP" | "PSM" => {
+                if cells.len() - 1 != current.len() {
+                    problems.push(format!(
+                        "Line {}: {} cells for {} columns",
+                        n + 1,
+                        cells.len() - 1,
+                        current.len()
+                    ));
+                    continue;
+                }
+                for (column, cell) in current.iter().zip(&cells[1..]) {
+                    if cell.trim().is_empty() {
+                        problems.push(format!("Line {}: empty '{}', use null", n + 1, column));
+                    }
+                    if *column == "spectra_ref" {
+                        let run = cell.split(':').next().unwrap_or_default();
+                        if !metadata.contains_key(format!("{}-location", run).as_str()) {
+                            problems.push(format!("Line {}: {} is not defined", n + 1, run));
+                        }
+                    }
+                }
+            }
+            other => problems.push(format!("Line {}: unknown line type '{}'", n + 1, other)),
+        }
+        if problems.len() >= MAX_PROBLEMS {
+            problems.push("Further problems not listed".to_string());
+            return problems;
+        }
+    }
+
+    let mut required = vec![
+        "mzTab-version",
+        "mzTab-mode",
+        "mzTab-type",
+        "description",
+        "ms_run[1]-location",
+        "software[1]",
+        "fixed_mod[1]",
+        "variable_mod[1]",
+    ];
+    let quantification = metadata.get("mzTab-type") == Some(&"Quantification");
+    let sections = [
+        ("PRH", "protein", &PROTEIN_COLUMNS[..]),
+        ("PEH", "peptide", &PEPTIDE_COLUMNS[..]),
+        ("PSH", "psm", &PSM_COLUMNS[..]),
+    ];
+    let score_keys: Vec<String> = sections
+        .iter()
+        .filter(|(header, ..)| headers.contains_key(header))
+        .map(|(_, section, _)| format!("{}_search_engine_score[1]", section))
+        .collect();
+    required.extend(score_keys.iter().map(String::as_str));
+    if quantification {
+        required.extend(["quantification_method", "study_variable[1]-description"]);
+        if headers.contains_key("PRH") {
+            required.push("protein-quantification_unit");
+        }
+        if headers.contains_key("PEH") {
+            required.push("peptide-quantification_unit");
+        }
+    }
+    for key in required {
+        if !metadata.contains_key(key) {
+            problems.push(format!("Metadata '{}' is missing", key));
+        }
+    }
+
+    let variables = (1..)
+        .take_while(|j| {
+            metadata.contains_key(format!("study_variable[{}]-description", j).as_str())
+        })
+        .count();
+    for (header, section, columns) in sections {
+        let Some(present) = headers.get(header) else {
+            continue;
+        };
+        let mut columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        if quantification && header != "PSH" {
+            for j in 1..=variables {
+                for kind in ["", "stdev_", "std_error_"] {
+                    columns.push(format!(
+                        "{}_abundance_{}study_variable[{}]",
+                        section, kind, j
+                    ));
+                }
+            }
+        }
+        for column in columns {
+            if !present.contains(&column.as_str()) {
+                problems.push(format!("{} section lacks the '{}' column", section, column));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &[(&str, &str)] = &[
+        ("mzTab-version", "1.0.0"),
+        ("mzTab-mode", "Summary"),
+        ("mzTab-type", "Identification"),
+        ("description", "test"),
+        ("ms_run[1]-location", "file:///run.mzML"),
+        ("software[1]", "[MS, MS:1002998, sage, 0.15]"),
+        (
+            "fixed_mod[1]",
+            "[MS, MS:1002453, No fixed modifications searched, ]",
+        ),
+        (
+            "variable_mod[1]",
+            "[MS, MS:1002454, No variable modifications searched, ]",
+        ),
+        (
+            "psm_search_engine_score[1]",
+            "[MS, MS:1001143, search engine specific score, ]",
+        ),
+    ];
+
+    /// A one PSM identification file referencing `spectra_ref`.
+    fn document(metadata: &[(&str, &str)], spectra_ref: &str) -> String {
+        let mut out = String::new();
+        for (key, value) in metadata {
+            mtd(&mut out, key, value);
+        }
+        out.push_str(&line("PSH", &PSM_COLUMNS.map(String::from)));
+        let cells = PSM_COLUMNS.map(|column| match column {
+            "spectra_ref" => spectra_ref.to_string(),
+            _ => "null".to_string(),
+        });
+        out.push_str(&line("PSM", &cells));
+        out
+    }
+
+    #[test]
+    fn valid_document() {
+        let text = document(METADATA, "ms_run[1]:scan=1");
+        assert_eq!(validate(&text), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_metadata() {
+        let text = document(&METADATA[1..], "ms_run[1]:scan=1");
+        assert_eq!(validate(&text), vec!["Metadata 'mzTab-version' is missing"]);
+    }
+
+    #[test]
+    fn undefined_run() {
+        let text = document(METADATA, "ms_run[2]:scan=1");
+        assert_eq!(validate(&text), vec!["Line 11: ms_run[2] is not defined"]);
+    }
+
+    #[test]
+    fn cell_count_and_empty_cells() {
+        let text = document(METADATA, "ms_run[1]:scan=1");
+        let short = text.replacen("PSM\tnull\t", "PSM\t", 1);
+        assert_eq!(validate(&short), vec!["Line 11: 18 cells for 19 columns"]);
+        let empty = text.replacen("PSM\tnull\t", "PSM\t\t", 1);
+        assert_eq!(
+            validate(&empty),
+            vec!["Line 11: empty 'sequence', use null"]
+        );
+    }
+
+    #[test]
+    fn missing_column() {
+        let text = document(METADATA, "ms_run[1]:scan=1")
+            .replace("\tdatabase_version\t", "\tdatabase_versions\t");
+        assert_eq!(
+            validate(&text),
+            vec!["psm section lacks the 'database_version' column"]
+        );
+    }
+
+    #[test]
+    fn quantification_needs_study_variables() {
+        let mut metadata = METADATA.to_vec();
+        metadata[2] = ("mzTab-type", "Quantification");
+        let problems = validate(&document(&metadata, "ms_run[1]:scan=1"));
+        assert_eq!(
+            problems,
+            vec![
+                "Metadata 'quantification_method' is missing",
+                "Metadata 'study_variable[1]-description' is missing",
+            ]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+/// Open search: a precursor window of hundreds of Da, so that peptides carrying
+/// unexpected modifications still match. The modifications then show up as peaks in
+/// the distribution of `expmass - calcmass` of confident PSMs, which are picked and
+/// matched against the excerpt of Unimod in `psi`.
+use crate::plot::{Histogram, Marker};
+use crate::psi::UNIMOD;
+use crate::results;
+use crate::{Config, ToleranceConfig};
+use eframe::egui;
+use rfd::FileDialog;
+use std::fs;
+use std::path::Path;
+
+const NEUTRON: f64 = 1.003_354_8;
+/// Bin width used for peak picking.
+const PICK_BIN: f64 = 0.01;
+
+/// Residue masses, for peaks caused by an extra or missing amino acid.
+const RESIDUES: &[(char, f64)] = &[
+    ('G', 57.021464),
+    ('A', 71.037114),
+    ('S', 87.032028),
+    ('P', 97.052764),
+    ('V', 99.068414),
+    ('T', 101.047679),
+    ('C', 103.009185),
+    ('L', 113.084064),
+    ('N', 114.042927),
+    ('D', 115.026943),
+    ('Q', 128.058578),
+    ('K', 128.094963),
+    ('E', 129.042593),
+    ('M', 131.040485),
+    ('H', 137.058912),
+    ('F', 147.068414),
+    ('R', 156.101111),
+    ('Y', 163.063329),
+    ('W', 186.079313),
+];
+
+/// Is this precursor window wide enough to count as an open search?
+pub fn is_open(tolerance: &ToleranceConfig) -> bool {
+    matches!(*tolerance, ToleranceConfig::Da(lo, hi) if hi - lo > 50.0)
+}
+
+/// Switch a config to open search with the given precursor window.
+pub fn apply(config: &mut Config, window: (f32, f32)) {
+    config.precursor_tol = ToleranceConfig::Da(window.0, window.1);
+    // Isotope errors are just more peaks in the delta mass histogram
+    config.isotope_errors = (0, 0);
+    config.wide_window = false;
+    config.chimera = false;
+}
+
+/// Names of everything within `tolerance` of `mass`.
+pub fn annotate(mass: f64, tolerance: f64) -> String {
+    if mass.abs() <= tolerance {
+        return "Unmodified".to_string();
+    }
+    let mut names = Vec::new();
+    for isotopes in 0..=2 {
+        let suffix = match isotopes {
+            0 => String::new(),
+            n => format!(" (+{} C13)", n),
+        };
+        let target = mass - isotopes as f64 * NEUTRON;
+        if isotopes > 0 && target.abs() <= tolerance {
+            names.push(format!("Isotope error{}", suffix));
+        }
+        for &(_, name, shift) in UNIMOD {
+            if (target - shift).abs() <= tolerance {
+                names.push(format!("{}{}", name, suffix));
+            }
+        }
+        for (residue, shift) in RESIDUES {
+            if (target - shift).abs() <= tolerance {
+                names.push(format!("Extra {}{}", residue, suffix));
+            }
+            if (target + shift).abs() <= tolerance {
+                names.push(format!("Missing {}{}", residue, suffix));
+            }
+        }
+        // Prefer the plain explanation when there is one
+        if !names.is_empty() {
+            break;
+        }
+    }
+    names.join("; ")
+}
+
+#[derive(Debug, Clone)]
+pub struct Peak {
+    pub mass: f64,
+    pub psms: usize,
+    pub annotation: String,
+}
+
+pub struct Analysis {
+    deltas: Vec<f64>,
+    peaks: Vec<Peak>,
+    psms: usize,
+}
+
+/// Local maxima of a fine histogram of `deltas`, centered on the mean of their PSMs.
+pub fn pick_peaks(deltas: &[f64], min_psms: usize, tolerance: f64) -> Vec<Peak> {
+    let (Some(lo), Some(hi)) = (
+        deltas.iter().copied().reduce(f64::min),
+        deltas.iter().copied().reduce(f64::max),
+    ) else {
+        return Vec::new();
+    };
+    let bins = ((hi - lo) / PICK_BIN) as usize + 1;
+    let histogram = Histogram::new(deltas, lo, lo + bins as f64 * PICK_BIN, bins);
+    let counts = &histogram.counts;
+    let radius = ((tolerance / PICK_BIN).ceil() as usize).max(1);
+
+    let mut sorted = deltas.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mut peaks = Vec::new();
+    for (i, &count) in counts.iter().enumerate() {
+        let from = i.saturating_sub(radius);
+        let to = (i + radius).min(counts.len() - 1);
+        // Plateaus count once, at their first bin
+        let is_max = (from..=to).all(|j| counts[j] < count || (counts[j] == count && j >= i));
+        if count == 0 || !is_max {
+            continue;
+        }
+        let center = lo + (i as f64 + 0.5) * PICK_BIN;
+        let start = sorted.partition_point(|d| *d < center - tolerance);
+        let end = sorted.partition_point(|d| *d <= center + tolerance);
+        let members = &sorted[start..end];
+        if members.len() < min_psms {
+            continue;
+        }
+        let mass = members.iter().sum::<f64>() / members.len() as f64;
+        peaks.push(Peak {
+            mass,
+            psms: members.len(),
+            annotation: annotate(mass, tolerance),
+        });
+    }
+    peaks.sort_by_key(|peak| std::cmp::Reverse(peak.psms));
+    peaks
+}
+
+fn analyze(dir: &Path, q_value: f64, min_psms: usize, tolerance: f64) -> Result<Analysis, String> {
+    let deltas: Vec<f64> = results::read_psms(dir)?
+        .into_iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && psm.spectrum_q <= q_value)
+        .map(|psm| psm.expmass - psm.calcmass)
+        .collect();
+    if deltas.is_empty() {
+        return Err(format!("No target PSMs at {} q-value", q_value));
+    }
+    Ok(Analysis {
+        peaks: pick_peaks(&deltas, min_psms, tolerance),
+        psms: deltas.len(),
+        deltas,
+    })
+}
+
+fn export(path: &Path, analysis: &Analysis) -> Result<(), String> {
+    let mut out = String::from("delta_mass\tpsms\tfraction\tannotation\n");
+    for peak in &analysis.peaks {
+        out.push_str(&format!(
+            "{:.4}\t{}\t{:.5}\t{}\n",
+            peak.mass,
+            peak.psms,
+            peak.psms as f64 / analysis.psms as f64,
+            peak.annotation
+        ));
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+pub struct OpenSearch {
+    pub window: (f32, f32),
+    pub results_dir: String,
+    pub q_value: f64,
+    pub min_psms: usize,
+    pub tolerance: f64,
+    view: (f64, f64),
+    analysis: Option<Result<Analysis, String>>,
+    message: String,
+}
+
+impl Default for OpenSearch {
+    fn default() -> Self {
+        Self {
+            window: (-500.0, 500.0),
+            results_dir: String::new(),
+            q_value: 0.01,
+            min_psms: 10,
+            tolerance: 0.02,
+            view: (-150.0, 500.0),
+            analysis: None,
+            message: String::new(),
+        }
+    }
+}
+
+impl OpenSearch {
+    /// Window editor for the tolerance section, returns true once the config was switched.
+    pub fn update_mode_section(&mut self, ui: &mut egui::Ui, config: &mut Config) -> bool {
+        let mut applied = false;
+        ui.horizontal(|ui| {
+            ui.label("Precursor Window (Da):");
+            ui.add(
+                egui::DragValue::new(&mut self.window.0)
+                    .range(-1000.0..=0.0)
+                    .speed(1.0),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.window.1)
+                    .range(0.0..=1000.0)
+                    .speed(1.0),
+            );
+            if ui.button("Use Open Search").clicked() {
+                apply(config, self.window);
+                applied = true;
+            }
+        });
+        if is_open(&config.precursor_tol) {
+            ui.label("Open search is active; see Open Search Results after the run.");
+        } else {
+            ui.weak("Sets a wide precursor window in Da and disables isotope errors, chimera and wide window.");
+        }
+        applied
+    }
+
+    pub fn update_results_section(&mut self, ui: &mut egui::Ui, default_dir: &str) {
+        if self.results_dir.is_empty() {
+            self.results_dir = default_dir.to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Results Folder:");
+            ui.text_edit_singleline(&mut self.results_dir);
+            if ui.button("Browse").clicked() {
+                if let Some(path) = FileDialog::new().pick_folder() {
+                    self.results_dir = path.display().to_string();
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("PSM q-value:");
+            ui.add(
+                egui::DragValue::new(&mut self.q_value)
+                    .range(0.0..=1.0)
+                    .speed(0.001),
+            );
+            ui.label("Min PSMs per peak:");
+            ui.add(egui::DragValue::new(&mut self.min_psms).range(1..=10_000));
+            ui.label("Match tolerance (Da):");
+            ui.add(
+                egui::DragValue::new(&mut self.tolerance)
+                    .range(0.001..=0.5)
+                    .speed(0.001),
+            );
+            if ui.button("Analyze").clicked() {
+                self.analysis = Some(analyze(
+                    Path::new(&self.results_dir),
+                    self.q_value,
+                    self.min_psms,
+                    self.tolerance,
+                ));
+                self.message.clear();
+            }
+        });
+
+        let analysis = match &self.analysis {
+            Some(Ok(analysis)) => analysis,
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+                return;
+            }
+            None => return,
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("View (Da):");
+            ui.add(egui::DragValue::new(&mut self.view.0).speed(1.0));
+            ui.add(egui::DragValue::new(&mut self.view.1).speed(1.0));
+            if ui.button("Full Range").clicked() {
+                self.view = (self.window.0 as f64, self.window.1 as f64);
+            }
+        });
+        let (lo, hi) = self.view;
+        if lo < hi {
+            let markers: Vec<Marker> = analysis
+                .peaks
+                .iter()
+                .take(5)
+                .map(|peak| Marker {
+                    x: peak.mass,
+                    color: egui::Color32::from_rgb(230, 160, 0),
+                    label: format!("{:.2}", peak.mass),
+                })
+                .collect();
+            Histogram::new(&analysis.deltas, lo, hi, 400).show(
+                ui,
+                &format!("Delta mass (Da), {} PSMs", analysis.psms),
+                &markers,
+            );
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} peaks", analysis.peaks.len()));
+            if ui.button("Export TSV").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("TSV", &["tsv"])
+                    .set_file_name("open_search_peaks.tsv")
+                    .save_file()
+                {
+                    self.message = match export(&path, analysis) {
+                        Ok(_) => format!("Exported to {}", path.display()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+            }
+            ui.label(&self.message);
+        });
+
+        let mut zoom = None;
+        egui::ScrollArea::vertical()
+            .id_salt("open_search_peaks")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("open_search_peak_table")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Delta Mass");
+                        ui.strong("PSMs");
+                        ui.strong("Fraction");
+                        ui.strong("Annotation");
+                        ui.end_row();
+                        for peak in &analysis.peaks {
+                            if ui.link(format!("{:.4}", peak.mass)).clicked() {
+                                zoom = Some(peak.mass);
+                            }
+                            ui.label(peak.psms.to_string());
+                            ui.label(format!(
+                                "{:.2}%",
+                                100.0 * peak.psms as f64 / analysis.psms as f64
+                            ));
+                            ui.label(&peak.annotation);
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(mass) = zoom {
+            self.view = (mass - 2.0, mass + 2.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotates_known_shifts() {
+        assert_eq!(annotate(0.004, 0.01), "Unmodified");
+        // Sulfo is 9.5 mDa lighter than phospho
+        assert_eq!(annotate(79.9661, 0.01), "Phospho; Sulfo");
+        assert_eq!(annotate(79.9661, 0.005), "Phospho");
+        assert_eq!(annotate(15.9951, 0.01), "Oxidation");
+        assert_eq!(annotate(-17.0262, 0.01), "Gln->pyro-Glu");
+        // Unimod and residue masses that coincide are both listed
+        assert_eq!(annotate(57.0215, 0.01), "Carbamidomethyl; Extra G");
+        assert_eq!(annotate(-128.0950, 0.01), "Missing K");
+        // Plain explanations win over isotope errors
+        assert_eq!(annotate(80.9697, 0.005), "Phospho (+1 C13)");
+        assert_eq!(annotate(1.0034, 0.01), "Isotope error (+1 C13)");
+        assert_eq!(annotate(0.9840, 0.01), "Deamidated");
+        assert_eq!(annotate(333.3, 0.01), "");
+    }
+
+    /// `n` deltas spread by up to ±4 mDa around `mass`.
+    fn cluster(mass: f64, n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|i| mass + ((i % 9) as f64 - 4.0) * 0.001)
+            .collect()
+    }
+
+    #[test]
+    fn picks_peaks_from_a_histogram() {
+        let mut deltas = cluster(0.0, 200);
+        deltas.extend(cluster(15.994915, 40));
+        deltas.extend(cluster(79.966331, 25));
+        // Too few to be reported
+        deltas.extend(cluster(42.010565, 5));
+        // Background of random matches, one per Da
+        deltas.extend((-100..300).map(|i| i as f64 + 0.5));
+
+        let peaks = pick_peaks(&deltas, 10, 0.02);
+        let found: Vec<(&str, usize)> = peaks
+            .iter()
+            .map(|p| (p.annotation.as_str(), p.psms))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Unmodified", 200),
+                ("Oxidation", 40),
+                ("Phospho; Sulfo", 25)
+            ]
+        );
+        assert!((peaks[1].mass - 15.994915).abs() < 0.001);
+        assert!((peaks[2].mass - 79.966331).abs() < 0.001);
+
+        assert!(pick_peaks(&[], 1, 0.02).is_empty());
+        assert_eq!(pick_peaks(&cluster(0.0, 5), 10, 0.02).len(), 0);
+    }
+}
@@ -0,0 +1,207 @@
+/// Output directory handling: path templates and protection against overwriting the
+/// results of a previous search.
+use chrono::{DateTime, Local};
+use std::path::Path;
+
+/// Files sage (and sagegui) write into the output directory.
+pub const SAGE_OUTPUTS: &[&str] = &[
+    "results.sage.tsv",
+    "results.sage.parquet",
+    "results.sage.pin",
+    "matched_fragments.sage.tsv",
+    "matched_fragments.sage.parquet",
+    "lfq.tsv",
+    "lfq.parquet",
+    "tmt.tsv",
+    "tmt.parquet",
+    "results.json",
+    crate::provenance::PROVENANCE_FILE_NAME,
+];
+
+pub const TEMPLATE_HELP: &str =
+    "Placeholders: {date}, {time}, {fasta_stem}, {config_name}, {first_run}. \
+Leave empty to write directly into the output location.";
+
+/// Expand the output template (relative to `root`) at launch time. Every level of
+/// the template goes through `path_component`, so nothing can point outside `root`.
+pub fn resolve(
+    root: &str,
+    template: &str,
+    fasta: &str,
+    config_name: &str,
+    first_run: Option<&str>,
+    now: DateTime<Local>,
+) -> String {
+    let levels: Vec<String> = template
+        .split(['/', '\\'])
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .map(|level| {
+            let expanded = level
+                .replace("{date}", &now.format("%Y-%m-%d").to_string())
+                .replace("{time}", &now.format("%H%M%S").to_string())
+                .replace("{fasta_stem}", &path_component(&stem(fasta)))
+                .replace("{config_name}", &path_component(config_name))
+                .replace(
+                    "{first_run}",
+                    &path_component(&stem(first_run.unwrap_or(""))),
+                );
+            path_component(&expanded)
+        })
+        .collect();
+    if levels.is_empty() {
+        return root.to_string();
+    }
+    join(root, &levels.join("/"))
+}
+
+/// Sage outputs that already exist in `dir`. Remote directories are not checked,
+/// validation warns about that instead.
+pub fn existing_outputs(dir: &str) -> Vec<&'static str> {
+    if crate::cloud::is_s3_uri(dir) {
+        return Vec::new();
+    }
+    SAGE_OUTPUTS
+        .iter()
+        .filter(|name| Path::new(dir).join(name).exists())
+        .copied()
+        .collect()
+}
+
+pub fn timestamped_subfolder(dir: &str, now: DateTime<Local>) -> String {
+    join(dir, &now.format("%Y%m%d-%H%M%S").to_string())
+}
+
+fn join(root: &str, rel: &str) -> String {
+    if crate::cloud::is_s3_uri(root) {
+        format!("{}/{}", root.trim_end_matches('/'), rel)
+    } else {
+        Path::new(root).join(rel).display().to_string()
+    }
+}
+
+/// File name without directories or any of the usual extensions.
+pub fn stem(path: &str) -> String {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    let mut name = name;
+    for ext in [".gz", ".fasta", ".fa", ".mzML", ".mzml", ".d"] {
+        name = name.strip_suffix(ext).unwrap_or(name);
+    }
+    name.to_string()
+}
+
+/// Values substituted into the template must not introduce new path levels, or
+/// step out of the current one with `.` and `..`.
+pub fn path_component(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+    if cleaned.is_empty() {
+        "unnamed".to_string()
+    } else if cleaned.chars().all(|c| c == '.') {
+        cleaned.replace('.', "_")
+    } else {
+        cleaned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 14, 9, 26, 53).unwrap()
+    }
+
+    #[test]
+    fn expands_templates() {
+        let resolved = resolve(
+            "/data/out",
+            "{date}/{fasta_stem}/{config_name}_{time}",
+            "/db/human.fasta.gz",
+            "tmt run",
+            Some("s3://bucket/a/run01.mzML"),
+            now(),
+        );
+        assert_eq!(
+            resolved,
+            Path::new("/data/out")
+                .join("2026-03-14/human/tmt run_092653")
+                .display()
+                .to_string()
+        );
+        assert_eq!(
+            resolve(
+                "s3://bucket/out/",
+                "{first_run}",
+                "",
+                "",
+                Some("x.d"),
+                now()
+            ),
+            "s3://bucket/out/x"
+        );
+        assert_eq!(
+            resolve("/data/out", " / ", "", "", None, now()),
+            "/data/out"
+        );
+    }
+
+    #[test]
+    fn template_cannot_leave_the_root() {
+        let resolved = resolve("/data/out", "../{config_name}/./x", "", "..", None, now());
+        assert_eq!(
+            resolved,
+            Path::new("/data/out")
+                .join("__/__/_/x")
+                .display()
+                .to_string()
+        );
+        let resolved = resolve("/data/out", "{config_name}", "", "a/../../b", None, now());
+        assert_eq!(
+            resolved,
+            Path::new("/data/out")
+                .join("a_.._.._b")
+                .display()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn stems_and_components() {
+        assert_eq!(stem("/a/b/run.mzML.gz"), "run");
+        assert_eq!(stem("C:\\data\\human.fasta"), "human");
+        assert_eq!(stem("s3://bucket/run.d"), "run");
+        assert_eq!(path_component("a/b:c"), "a_b_c");
+        assert_eq!(path_component(""), "unnamed");
+        assert_eq!(path_component("."), "_");
+        assert_eq!(path_component(".."), "__");
+        assert_eq!(path_component("v1.2"), "v1.2");
+    }
+
+    #[test]
+    fn detects_existing_results() {
+        let dir = std::env::temp_dir().join(format!("sagegui_output_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_str = dir.display().to_string();
+        assert!(existing_outputs(&dir_str).is_empty());
+
+        std::fs::write(dir.join("results.sage.tsv"), "").unwrap();
+        std::fs::write(dir.join("lfq.tsv"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        assert_eq!(
+            existing_outputs(&dir_str),
+            vec!["results.sage.tsv", "lfq.tsv"]
+        );
+        assert!(existing_outputs("s3://bucket/out").is_empty());
+
+        let subfolder = timestamped_subfolder(&dir_str, now());
+        assert_eq!(subfolder, dir.join("20260314-092653").display().to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
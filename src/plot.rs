@@ -0,0 +1,622 @@
+/// Small plots drawn straight with the egui painter.
+///
+/// Just enough for the diagnostics sagegui shows; no zooming or panning.
+use eframe::egui::{self, Align2, Color32, FontId, Pos2, Rect, Sense, Stroke, Vec2};
+
+const PLOT_HEIGHT: f32 = 180.0;
+const MARGIN: f32 = 24.0;
+const BAR_COLOR: Color32 = Color32::from_rgb(90, 140, 200);
+
+/// A vertical line at `x`, e.g. a fitted center or a tolerance bound.
+pub struct Marker {
+    pub x: f64,
+    pub color: Color32,
+    pub label: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub lo: f64,
+    pub hi: f64,
+    pub counts: Vec<u32>,
+}
+
+impl Histogram {
+    /// Bin `values` into `bins` equal bins over `lo..hi`, values outside are dropped.
+    pub fn new(values: &[f64], lo: f64, hi: f64, bins: usize) -> Self {
+        let bins = bins.max(1);
+        let mut counts = vec![0; bins];
+        let width = (hi - lo) / bins as f64;
+        for &value in values {
+            if value < lo || value >= hi || width <= 0.0 {
+                continue;
+            }
+            let bin = ((value - lo) / width) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self { lo, hi, counts }
+    }
+
+    fn bin_width(&self) -> f64 {
+        (self.hi - self.lo) / self.counts.len() as f64
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui, x_label: &str, markers: &[Marker]) {
+        let width = ui.available_width().min(520.0);
+        let (response, painter) =
+            ui.allocate_painter(Vec2::new(width, PLOT_HEIGHT), Sense::hover());
+        let outer = response.rect;
+        let area = Rect::from_min_max(
+            outer.min + Vec2::new(MARGIN, 8.0),
+            outer.max - Vec2::new(8.0, MARGIN),
+        );
+        let text = ui.visuals().text_color();
+        let font = FontId::proportional(11.0);
+
+        painter.rect_stroke(area, 0.0, Stroke::new(1.0, ui.visuals().weak_text_color()));
+
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let to_x =
+            |x: f64| area.left() + ((x - self.lo) / (self.hi - self.lo)) as f32 * area.width();
+        let bar_width = area.width() / self.counts.len() as f32;
+        for (i, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let left = area.left() + i as f32 * bar_width;
+            let top = area.bottom() - count as f32 / max * area.height();
+            painter.rect_filled(
+                Rect::from_min_max(
+                    Pos2::new(left, top),
+                    Pos2::new(left + bar_width.max(1.0), area.bottom()),
+                ),
+                0.0,
+                BAR_COLOR,
+            );
+        }
+
+        for marker in markers {
+            if marker.x < self.lo || marker.x > self.hi {
+                continue;
+            }
+            let x = to_x(marker.x);
+            painter.line_segment(
+                [Pos2::new(x, area.top()), Pos2::new(x, area.bottom())],
+                Stroke::new(1.5, marker.color),
+            );
+            painter.text(
+                Pos2::new(x + 2.0, area.top()),
+                Align2::LEFT_TOP,
+                &marker.label,
+                font.clone(),
+                marker.color,
+            );
+        }
+
+        painter.text(
+            Pos2::new(area.left(), area.bottom() + 2.0),
+            Align2::LEFT_TOP,
+            format!("{:.3}", self.lo),
+            font.clone(),
+            text,
+        );
+        painter.text(
+            Pos2::new(area.center().x, area.bottom() + 2.0),
+            Align2::CENTER_TOP,
+            x_label,
+            font.clone(),
+            text,
+        );
+        painter.text(
+            Pos2::new(area.right(), area.bottom() + 2.0),
+            Align2::RIGHT_TOP,
+            format!("{:.3}", self.hi),
+            font.clone(),
+            text,
+        );
+        painter.text(
+            Pos2::new(area.left() - 2.0, area.top()),
+            Align2::RIGHT_TOP,
+            format!("{}", max as u32),
+            font,
+            text,
+        );
+
+        if let Some(pos) = response.hover_pos() {
+            if area.contains(pos) {
+                let i = (((pos.x - area.left()) / bar_width) as usize).min(self.counts.len() - 1);
+                let from = self.lo + i as f64 * self.bin_width();
+                response.on_hover_text(format!(
+                    "{:.3} to {:.3}: {}",
+                    from,
+                    from + self.bin_width(),
+                    self.counts[i]
+                ));
+            }
+        }
+    }
+}
+
+/// A straight line between two (x, y) points in data coordinates.
+pub type Line = ((f64, f64), (f64, f64));
+
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+    pub color: Color32,
+}
+
+/// Smallest and largest value, padded by 5% so points don't sit on the border.
+fn padded_range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (lo, hi) = values
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+            (lo.min(v), hi.max(v))
+        });
+    if lo > hi {
+        return (0.0, 1.0);
+    }
+    let pad = ((hi - lo) * 0.05).max(1e-6);
+    (lo - pad, hi + pad)
+}
+
+/// Scatter plot of `points`. `guides` are extra lines given by two (x, y) ends, e.g.
+/// the diagonal or significance thresholds. Returns the index of a clicked point.
+pub fn scatter(
+    ui: &mut egui::Ui,
+    points: &[Point],
+    x_label: &str,
+    y_label: &str,
+    guides: &[Line],
+) -> Option<usize> {
+    let width = ui.available_width().min(520.0);
+    let (response, painter) = ui.allocate_painter(Vec2::new(width, width * 0.75), Sense::click());
+    let outer = response.rect;
+    let area = Rect::from_min_max(
+        outer.min + Vec2::new(MARGIN * 2.0, 8.0),
+        outer.max - Vec2::new(8.0, MARGIN),
+    );
+    let text = ui.visuals().text_color();
+    let font = FontId::proportional(11.0);
+    painter.rect_stroke(area, 0.0, Stroke::new(1.0, ui.visuals().weak_text_color()));
+
+    let (x_lo, x_hi) = padded_range(points.iter().map(|p| p.x));
+    let (y_lo, y_hi) = padded_range(points.iter().map(|p| p.y));
+    let to_screen = |x: f64, y: f64| {
+        Pos2::new(
+            area.left() + ((x - x_lo) / (x_hi - x_lo)) as f32 * area.width(),
+            area.bottom() - ((y - y_lo) / (y_hi - y_lo)) as f32 * area.height(),
+        )
+    };
+
+    for point in points {
+        if point.x.is_finite() && point.y.is_finite() {
+            painter.circle_filled(to_screen(point.x, point.y), 1.5, point.color);
+        }
+    }
+    let clip = painter.with_clip_rect(area);
+    for ((x0, y0), (x1, y1)) in guides {
+        clip.line_segment(
+            [to_screen(*x0, *y0), to_screen(*x1, *y1)],
+            Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+    }
+
+    for (pos, align, label) in [
+        (area.left_bottom(), Align2::LEFT_TOP, format!("{:.2}", x_lo)),
+        (
+            area.center_bottom(),
+            Align2::CENTER_TOP,
+            x_label.to_string(),
+        ),
+        (
+            area.right_bottom(),
+            Align2::RIGHT_TOP,
+            format!("{:.2}", x_hi),
+        ),
+    ] {
+        painter.text(pos + Vec2::new(0.0, 2.0), align, label, font.clone(), text);
+    }
+    for (pos, align, label) in [
+        (
+            area.left_bottom(),
+            Align2::RIGHT_BOTTOM,
+            format!("{:.2}", y_lo),
+        ),
+        (
+            area.left_center(),
+            Align2::RIGHT_CENTER,
+            y_label.to_string(),
+        ),
+        (area.left_top(), Align2::RIGHT_TOP, format!("{:.2}", y_hi)),
+    ] {
+        painter.text(pos - Vec2::new(2.0, 0.0), align, label, font.clone(), text);
+    }
+
+    let clicked = match (response.clicked(), response.interact_pointer_pos()) {
+        (true, Some(pos)) => points
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.x.is_finite() && p.y.is_finite())
+            .map(|(i, p)| (i, to_screen(p.x, p.y).distance(pos)))
+            .filter(|(_, distance)| *distance < 8.0)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i),
+        _ => None,
+    };
+
+    if let Some(pos) = response.hover_pos() {
+        if area.contains(pos) {
+            let x = x_lo + ((pos.x - area.left()) / area.width()) as f64 * (x_hi - x_lo);
+            let y = y_lo + ((area.bottom() - pos.y) / area.height()) as f64 * (y_hi - y_lo);
+            response.on_hover_text(format!("{}: {:.3}\n{}: {:.3}", x_label, x, y_label, y));
+        }
+    }
+    clicked
+}
+
+/// Two overlapping circles with the counts of a two-set comparison.
+pub fn venn(ui: &mut egui::Ui, names: (&str, &str), only_a: usize, both: usize, only_b: usize) {
+    let (response, painter) = ui.allocate_painter(Vec2::new(320.0, 170.0), Sense::hover());
+    let rect = response.rect;
+    let radius = 70.0;
+    let a = Pos2::new(rect.center().x - 45.0, rect.center().y + 8.0);
+    let b = Pos2::new(rect.center().x + 45.0, rect.center().y + 8.0);
+    let text = ui.visuals().text_color();
+    let font = FontId::proportional(13.0);
+
+    painter.circle(
+        a,
+        radius,
+        Color32::from_rgba_unmultiplied(90, 140, 200, 60),
+        Stroke::new(1.0, BAR_COLOR),
+    );
+    painter.circle(
+        b,
+        radius,
+        Color32::from_rgba_unmultiplied(230, 160, 0, 60),
+        Stroke::new(1.0, Color32::from_rgb(230, 160, 0)),
+    );
+    painter.text(
+        a - Vec2::new(35.0, 0.0),
+        Align2::CENTER_CENTER,
+        only_a.to_string(),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        rect.center() + Vec2::new(0.0, 8.0),
+        Align2::CENTER_CENTER,
+        both.to_string(),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        b + Vec2::new(35.0, 0.0),
+        Align2::CENTER_CENTER,
+        only_b.to_string(),
+        font,
+        text,
+    );
+
+    let small = FontId::proportional(11.0);
+    painter.text(
+        Pos2::new(a.x - 35.0, rect.top()),
+        Align2::CENTER_TOP,
+        names.0,
+        small.clone(),
+        text,
+    );
+    painter.text(
+        Pos2::new(b.x + 35.0, rect.top()),
+        Align2::CENTER_TOP,
+        names.1,
+        small,
+        text,
+    );
+}
+
+/// Blue (0) over white (0.5) to red (1).
+pub fn diverging(t: f64) -> Color32 {
+    let t = t.clamp(0.0, 1.0) as f32;
+    let lerp = |a: f32, b: f32, t: f32| (a + (b - a) * t) as u8;
+    if t < 0.5 {
+        let t = t * 2.0;
+        Color32::from_rgb(
+            lerp(50.0, 245.0, t),
+            lerp(100.0, 245.0, t),
+            lerp(200.0, 245.0, t),
+        )
+    } else {
+        let t = (t - 0.5) * 2.0;
+        Color32::from_rgb(
+            lerp(245.0, 210.0, t),
+            lerp(245.0, 50.0, t),
+            lerp(245.0, 50.0, t),
+        )
+    }
+}
+
+/// Light (0) to dark (1) blue.
+pub fn sequential(t: f64) -> Color32 {
+    let t = t.clamp(0.0, 1.0) as f32;
+    let lerp = |a: f32, b: f32| (a + (b - a) * t) as u8;
+    Color32::from_rgb(lerp(240.0, 20.0), lerp(245.0, 60.0), lerp(250.0, 140.0))
+}
+
+fn short(label: &str, max: usize) -> String {
+    if label.chars().count() <= max {
+        return label.to_string();
+    }
+    let tail: String = label
+        .chars()
+        .rev()
+        .take(max - 1)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("…{}", tail)
+}
+
+/// One box per group: interquartile range, median, and whiskers at the 5th and 95th
+/// percentile.
+pub fn boxplots(ui: &mut egui::Ui, groups: &[(String, Vec<f64>)], y_label: &str) {
+    let width = ui.available_width().min(720.0);
+    let (response, painter) = ui.allocate_painter(Vec2::new(width, 220.0), Sense::hover());
+    let outer = response.rect;
+    let area = Rect::from_min_max(
+        outer.min + Vec2::new(MARGIN * 2.0, 8.0),
+        outer.max - Vec2::new(8.0, MARGIN * 1.5),
+    );
+    let text = ui.visuals().text_color();
+    let font = FontId::proportional(11.0);
+    painter.rect_stroke(area, 0.0, Stroke::new(1.0, ui.visuals().weak_text_color()));
+
+    let summaries: Vec<Option<[f64; 5]>> = groups
+        .iter()
+        .map(|(_, values)| {
+            Some([
+                crate::stats::quantile(values, 0.05)?,
+                crate::stats::quantile(values, 0.25)?,
+                crate::stats::quantile(values, 0.5)?,
+                crate::stats::quantile(values, 0.75)?,
+                crate::stats::quantile(values, 0.95)?,
+            ])
+        })
+        .collect();
+    let (lo, hi) = padded_range(summaries.iter().flatten().flat_map(|s| [s[0], s[4]]));
+    let to_y = |v: f64| area.bottom() - ((v - lo) / (hi - lo)) as f32 * area.height();
+    let slot = area.width() / groups.len().max(1) as f32;
+    let stroke = Stroke::new(1.0, text);
+
+    for (i, ((name, values), summary)) in groups.iter().zip(&summaries).enumerate() {
+        let center = area.left() + (i as f32 + 0.5) * slot;
+        let half = (slot * 0.3).min(20.0);
+        if let Some([p5, q1, median, q3, p95]) = *summary {
+            painter.line_segment(
+                [Pos2::new(center, to_y(p5)), Pos2::new(center, to_y(q1))],
+                stroke,
+            );
+            painter.line_segment(
+                [Pos2::new(center, to_y(q3)), Pos2::new(center, to_y(p95))],
+                stroke,
+            );
+            painter.rect(
+                Rect::from_min_max(
+                    Pos2::new(center - half, to_y(q3)),
+                    Pos2::new(center + half, to_y(q1)),
+                ),
+                0.0,
+                BAR_COLOR,
+                stroke,
+            );
+            painter.line_segment(
+                [
+                    Pos2::new(center - half, to_y(median)),
+                    Pos2::new(center + half, to_y(median)),
+                ],
+                Stroke::new(2.0, Color32::from_rgb(230, 160, 0)),
+            );
+        }
+        painter.text(
+            Pos2::new(center, area.bottom() + 2.0),
+            Align2::CENTER_TOP,
+            short(name, (slot / 6.0).max(4.0) as usize),
+            font.clone(),
+            text,
+        );
+        let slot_rect = Rect::from_min_max(
+            Pos2::new(center - slot / 2.0, area.top()),
+            Pos2::new(center + slot / 2.0, area.bottom()),
+        );
+        if response
+            .hover_pos()
+            .is_some_and(|pos| slot_rect.contains(pos))
+        {
+            let detail = match summary {
+                Some(s) => format!(
+                    "{}\nn = {}\nmedian {:.2}, IQR {:.2} to {:.2}",
+                    name,
+                    values.len(),
+                    s[2],
+                    s[1],
+                    s[3]
+                ),
+                None => format!("{}\nno values", name),
+            };
+            response.clone().on_hover_text(detail);
+        }
+    }
+    painter.text(
+        area.left_top() - Vec2::new(2.0, 0.0),
+        Align2::RIGHT_TOP,
+        format!("{:.1}", hi),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        area.left_bottom() - Vec2::new(2.0, 0.0),
+        Align2::RIGHT_BOTTOM,
+        format!("{:.1}", lo),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        area.left_center() - Vec2::new(2.0, 0.0),
+        Align2::RIGHT_CENTER,
+        y_label,
+        font,
+        text,
+    );
+}
+
+/// A grid of colored cells. `cell` returns the color and hover text of (row, column).
+pub fn heatmap(
+    ui: &mut egui::Ui,
+    rows: usize,
+    columns: usize,
+    row_labels: Option<&[String]>,
+    column_labels: &[String],
+    cell: impl Fn(usize, usize) -> (Color32, String),
+) {
+    let label_width = if row_labels.is_some() { 90.0 } else { 0.0 };
+    let width = ui.available_width().min(720.0);
+    let cell_width = ((width - label_width) / columns.max(1) as f32).min(60.0);
+    let cell_height = if row_labels.is_some() {
+        cell_width.min(24.0)
+    } else {
+        (200.0 / rows.max(1) as f32).max(1.0)
+    };
+    let size = Vec2::new(
+        label_width + cell_width * columns as f32,
+        cell_height * rows as f32 + MARGIN,
+    );
+    let (response, painter) = ui.allocate_painter(size, Sense::hover());
+    let origin = response.rect.min + Vec2::new(label_width, 0.0);
+    let text = ui.visuals().text_color();
+    let font = FontId::proportional(10.0);
+
+    let mut hovered = None;
+    for r in 0..rows {
+        for c in 0..columns {
+            let rect = Rect::from_min_size(
+                origin + Vec2::new(c as f32 * cell_width, r as f32 * cell_height),
+                Vec2::new(cell_width, cell_height),
+            );
+            let (color, hover) = cell(r, c);
+            painter.rect_filled(rect, 0.0, color);
+            if response.hover_pos().is_some_and(|pos| rect.contains(pos)) {
+                hovered = Some(hover);
+            }
+        }
+        if let Some(labels) = row_labels {
+            painter.text(
+                Pos2::new(origin.x - 2.0, origin.y + (r as f32 + 0.5) * cell_height),
+                Align2::RIGHT_CENTER,
+                short(&labels[r], 14),
+                font.clone(),
+                text,
+            );
+        }
+    }
+    for (c, label) in column_labels.iter().enumerate().take(columns) {
+        painter.text(
+            Pos2::new(
+                origin.x + (c as f32 + 0.5) * cell_width,
+                origin.y + rows as f32 * cell_height + 2.0,
+            ),
+            Align2::CENTER_TOP,
+            short(label, (cell_width / 6.0).max(3.0) as usize),
+            font.clone(),
+            text,
+        );
+    }
+    if let Some(hover) = hovered {
+        response.on_hover_text(hover);
+    }
+}
+
+/// Vertical bars, missing values are marked instead of drawn.
+pub fn bars(ui: &mut egui::Ui, labels: &[String], values: &[Option<f64>], y_label: &str) {
+    let width = ui.available_width().min(720.0);
+    let (response, painter) = ui.allocate_painter(Vec2::new(width, 200.0), Sense::hover());
+    let outer = response.rect;
+    let area = Rect::from_min_max(
+        outer.min + Vec2::new(MARGIN * 2.0, 8.0),
+        outer.max - Vec2::new(8.0, MARGIN * 1.5),
+    );
+    let text = ui.visuals().text_color();
+    let font = FontId::proportional(11.0);
+    painter.rect_stroke(area, 0.0, Stroke::new(1.0, ui.visuals().weak_text_color()));
+
+    let max = values
+        .iter()
+        .flatten()
+        .copied()
+        .fold(0.0, f64::max)
+        .max(f64::MIN_POSITIVE);
+    let slot = area.width() / values.len().max(1) as f32;
+    for (i, (label, value)) in labels.iter().zip(values).enumerate() {
+        let left = area.left() + i as f32 * slot;
+        let center = left + slot / 2.0;
+        match value {
+            Some(value) => {
+                let top = area.bottom() - (*value / max) as f32 * area.height();
+                painter.rect_filled(
+                    Rect::from_min_max(
+                        Pos2::new(left + slot * 0.15, top),
+                        Pos2::new(left + slot * 0.85, area.bottom()),
+                    ),
+                    0.0,
+                    BAR_COLOR,
+                );
+            }
+            None => {
+                painter.text(
+                    Pos2::new(center, area.bottom() - 2.0),
+                    Align2::CENTER_BOTTOM,
+                    "×",
+                    font.clone(),
+                    Color32::from_rgb(220, 60, 60),
+                );
+            }
+        }
+        painter.text(
+            Pos2::new(center, area.bottom() + 2.0),
+            Align2::CENTER_TOP,
+            short(label, (slot / 6.0).max(3.0) as usize),
+            font.clone(),
+            text,
+        );
+        let slot_rect = Rect::from_min_max(
+            Pos2::new(left, area.top()),
+            Pos2::new(left + slot, area.bottom()),
+        );
+        if response
+            .hover_pos()
+            .is_some_and(|pos| slot_rect.contains(pos))
+        {
+            let value = value
+                .map(|v| format!("{:.4e}", v))
+                .unwrap_or_else(|| "missing".to_string());
+            response
+                .clone()
+                .on_hover_text(format!("{}: {}", label, value));
+        }
+    }
+    painter.text(
+        area.left_top() - Vec2::new(2.0, 0.0),
+        Align2::RIGHT_TOP,
+        format!("{:.2e}", max),
+        font.clone(),
+        text,
+    );
+    painter.text(
+        area.left_center() - Vec2::new(2.0, 0.0),
+        Align2::RIGHT_CENTER,
+        y_label,
+        font,
+        text,
+    );
+}
@@ -0,0 +1,363 @@
+/// Parameter presets for common experiment types.
+///
+/// A preset is a complete `Config`. Applying one keeps the files and output location
+/// that are currently selected, everything else is replaced. User presets are plain
+/// saved configs in the `presets` folder of the app directory.
+use crate::{
+    Config, IsobarSelection, LfqConfig, QuantType, SupportedQuantTypes, TmtConfig, ToleranceConfig,
+};
+use eframe::egui;
+use sage_core::modification::ModificationSpecificity;
+use sage_core::tmt::Isobaric;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const CARBAMIDOMETHYL: f32 = 57.021_464;
+const OXIDATION: f32 = 15.994_915;
+const PHOSPHO: f32 = 79.966_33;
+const TMTPRO: f32 = 304.207_15;
+
+pub struct Preset {
+    pub name: String,
+    pub description: String,
+    pub config: Config,
+    // Only user presets live on disk
+    path: Option<PathBuf>,
+}
+
+impl Preset {
+    /// The preset's settings with the files and output location of `current`.
+    pub fn apply(&self, current: &Config) -> Config {
+        let mut config = self.config.clone();
+        config.name = self.name.clone();
+        config.database.fasta = current.database.fasta.clone();
+        config.mzml_paths = current.mzml_paths.clone();
+        config.dotd_paths = current.dotd_paths.clone();
+        config.cloud_paths = current.cloud_paths.clone();
+        config.design = current.design.clone();
+        config.output_directory = current.output_directory.clone();
+        config.output_template = current.output_template.clone();
+        config
+    }
+}
+
+fn preset(name: &str, description: &str, config: Config) -> Preset {
+    Preset {
+        name: name.to_string(),
+        description: description.to_string(),
+        config,
+        path: None,
+    }
+}
+
+fn mods(sites: &[(u8, f32)]) -> HashMap<ModificationSpecificity, f32> {
+    sites
+        .iter()
+        .map(|(residue, mass)| (ModificationSpecificity::Residue(*residue), *mass))
+        .collect()
+}
+
+fn lfq(mut config: Config) -> Config {
+    config.quant_enabled = true;
+    config.quant_class = SupportedQuantTypes::Lfq;
+    config.quant = QuantType::Lfq(LfqConfig::default());
+    config
+}
+
+fn tmtpro(selected: Isobaric, level: u8) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut static_mods = mods(&[(b'C', CARBAMIDOMETHYL), (b'K', TMTPRO)]);
+    let n_term = "^"
+        .parse()
+        .map_err(|e| format!("Cannot label peptide N-termini: {:?}", e))?;
+    static_mods.insert(n_term, TMTPRO);
+    config.database.static_mods.static_mods = static_mods;
+    config.quant_enabled = true;
+    config.quant_class = SupportedQuantTypes::Tmt;
+    config.quant = QuantType::Tmt(
+        IsobarSelection {
+            selected,
+            channels: Vec::new(),
+        },
+        TmtConfig { level, sn: false },
+    );
+    if level == 3 {
+        // SPS-MS3 methods acquire the identifying MS2 scans in the ion trap
+        config.fragment_tol = ToleranceConfig::Da(-0.5, 0.5);
+        config.max_fragment_charge = 2;
+    } else {
+        config.fragment_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+    }
+    Ok(config)
+}
+
+/// The built-in presets, and why any of them could not be built.
+pub fn builtin() -> (Vec<Preset>, Vec<String>) {
+    let mut presets = Vec::new();
+    let mut errors = Vec::new();
+
+    let mut config = lfq(Config::default());
+    config.precursor_tol = ToleranceConfig::Ppm(-10.0, 10.0);
+    config.fragment_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+    presets.push(preset(
+        "Orbitrap DDA LFQ",
+        "High resolution precursor and fragment scans, label-free quantification.",
+        config,
+    ));
+
+    for plex in [16, 18] {
+        for (level, name, description) in [
+            (
+                2,
+                format!("TMTpro {} MS2", plex),
+                "Reporter ions read from the identifying (Orbitrap) MS2 scan.",
+            ),
+            (
+                3,
+                format!("TMTpro {} SPS-MS3", plex),
+                "Ion trap MS2 for identification, reporter ions read from the MS3 scan.",
+            ),
+        ] {
+            let selected = match plex {
+                16 => Isobaric::Tmt16,
+                _ => Isobaric::Tmt18,
+            };
+            match tmtpro(selected, level) {
+                Ok(config) => presets.push(preset(&name, description, config)),
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+        }
+    }
+
+    let mut config = lfq(Config::default());
+    config.precursor_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+    config.fragment_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+    // PASEF MS2 spectra are not centroided to monoisotopic peaks by the instrument
+    config.deisotope = true;
+    config.max_fragment_charge = 2;
+    if let QuantType::Lfq(lfq) = &mut config.quant {
+        // MS1 features on a TOF are less accurate than on an Orbitrap
+        lfq.ppm_tolerance = 10.0;
+    }
+    presets.push(preset(
+        "timsTOF DDA-PASEF",
+        "Bruker .d input: TOF mass tolerances, deisotoped fragment spectra and a wider LFQ feature tolerance.",
+        config,
+    ));
+
+    let mut config = lfq(Config::default());
+    // The isolation window is used instead of the precursor tolerance
+    config.precursor_tol = ToleranceConfig::Da(-2.5, 2.5);
+    config.fragment_tol = ToleranceConfig::Ppm(-15.0, 15.0);
+    config.wide_window = true;
+    config.chimera = true;
+    config.report_psms = 5;
+    config.min_matched_peaks = 4;
+    presets.push(preset(
+        "Wide-window / DIA",
+        "Searches the whole isolation window and reports several co-isolated peptides per spectrum.",
+        config,
+    ));
+
+    let mut config = lfq(Config::default());
+    config.precursor_tol = ToleranceConfig::Ppm(-10.0, 10.0);
+    config.fragment_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+    config.database.max_variable_mods = 3;
+    config.database.variable_mods.variable_mods.static_mods = mods(&[
+        (b'M', OXIDATION),
+        (b'S', PHOSPHO),
+        (b'T', PHOSPHO),
+        (b'Y', PHOSPHO),
+    ]);
+    presets.push(preset(
+        "Phosphoproteomics (STY)",
+        "Variable phosphorylation on S, T and Y in addition to methionine oxidation.",
+        config,
+    ));
+
+    let mut config = Config {
+        precursor_tol: ToleranceConfig::Da(-500.0, 500.0),
+        fragment_tol: ToleranceConfig::Ppm(-20.0, 20.0),
+        isotope_errors: (0, 0),
+        quant_enabled: false,
+        ..Config::default()
+    };
+    config.database.max_variable_mods = 1;
+    presets.push(preset(
+        "Open search (±500 Da)",
+        "Wide precursor window to discover unexpected modifications from the mass shifts.",
+        config,
+    ));
+
+    (presets, errors)
+}
+
+/// Actions requested from the preset panel, handled by the launcher.
+pub enum PresetAction {
+    Apply(Box<Config>),
+    Message(String),
+}
+
+#[derive(Default)]
+pub struct Presets {
+    builtin: Vec<Preset>,
+    user: Vec<Preset>,
+    loaded: bool,
+    new_name: String,
+    /// Presets that could not be built or read.
+    errors: Vec<String>,
+    /// Set after the first click on save when a preset of that name exists.
+    confirm_overwrite: Option<String>,
+}
+
+impl Presets {
+    fn dir() -> Option<PathBuf> {
+        crate::app_dir().map(|d| d.join("presets"))
+    }
+
+    fn reload(&mut self) {
+        self.loaded = true;
+        (self.builtin, self.errors) = builtin();
+        self.user.clear();
+        let Some(entries) = Self::dir().and_then(|d| fs::read_dir(d).ok()) else {
+            return;
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            match Config::load(&path) {
+                Ok(config) => self.user.push(Preset {
+                    name: config.name.clone(),
+                    description: path.display().to_string(),
+                    config,
+                    path: Some(path),
+                }),
+                Err(e) => self
+                    .errors
+                    .push(format!("Skipping preset {}: {}", path.display(), e)),
+            }
+        }
+        self.user.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Save `current` under the entered name. Returns `Ok(None)` when an existing
+    /// preset would be replaced and the save needs to be confirmed first.
+    fn save(&mut self, current: &Config) -> Result<Option<String>, String> {
+        let name = self.new_name.trim().to_string();
+        if name.is_empty() {
+            return Err("Enter a name for the preset".to_string());
+        }
+        let dir = Self::dir().ok_or("No app directory available")?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{}.json", crate::output::path_component(&name)));
+        if path.exists() && self.confirm_overwrite.as_deref() != Some(name.as_str()) {
+            self.confirm_overwrite = Some(name);
+            return Ok(None);
+        }
+        self.confirm_overwrite = None;
+
+        let mut config = current.clone();
+        config.name = name.clone();
+        config.save(&path).map_err(|e| e.to_string())?;
+        self.new_name.clear();
+        self.reload();
+        Ok(Some(format!(
+            "Preset '{}' saved to {}",
+            name,
+            path.display()
+        )))
+    }
+
+    pub fn update_section(&mut self, ui: &mut egui::Ui, current: &Config) -> Option<PresetAction> {
+        if !self.loaded {
+            self.reload();
+        }
+        let mut action = None;
+
+        ui.label("Applying a preset replaces all settings but keeps the selected files and output location.");
+        ui.heading("Built-in");
+        for preset in &self.builtin {
+            preset_row(ui, preset, current, &mut action, false);
+        }
+        for error in &self.errors {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        ui.heading("User Presets");
+        let mut delete = None;
+        if self.user.is_empty() {
+            ui.weak("No user presets saved yet.");
+        }
+        for (i, preset) in self.user.iter().enumerate() {
+            if preset_row(ui, preset, current, &mut action, true) {
+                delete = Some(i);
+            }
+        }
+        if let Some(i) = delete {
+            let preset = self.user.remove(i);
+            let result = match &preset.path {
+                Some(path) => fs::remove_file(path).map_err(|e| e.to_string()),
+                None => Err("Built-in presets cannot be deleted".to_string()),
+            };
+            action = Some(PresetAction::Message(match result {
+                Ok(_) => format!("Preset '{}' deleted", preset.name),
+                Err(e) => format!("Error: {}", e),
+            }));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Save current settings as:");
+            if ui
+                .add(egui::TextEdit::singleline(&mut self.new_name).hint_text("preset name"))
+                .changed()
+            {
+                self.confirm_overwrite = None;
+            }
+            let label = match self.confirm_overwrite {
+                Some(_) => "Overwrite Preset",
+                None => "Save Preset",
+            };
+            if ui.button(label).clicked() {
+                match self.save(current) {
+                    Ok(Some(message)) => action = Some(PresetAction::Message(message)),
+                    Ok(None) => {}
+                    Err(e) => action = Some(PresetAction::Message(format!("Error: {}", e))),
+                }
+            }
+        });
+        if let Some(name) = &self.confirm_overwrite {
+            ui.colored_label(
+                egui::Color32::from_rgb(230, 160, 0),
+                format!(
+                    "A preset named '{}' already exists, click again to replace it.",
+                    name
+                ),
+            );
+        }
+        action
+    }
+}
+
+/// One preset with its apply (and delete) button. Returns whether delete was clicked.
+fn preset_row(
+    ui: &mut egui::Ui,
+    preset: &Preset,
+    current: &Config,
+    action: &mut Option<PresetAction>,
+    deletable: bool,
+) -> bool {
+    let mut delete = false;
+    ui.horizontal(|ui| {
+        if ui.button("Apply").clicked() {
+            *action = Some(PresetAction::Apply(Box::new(preset.apply(current))));
+        }
+        if deletable && ui.small_button("Delete").clicked() {
+            delete = true;
+        }
+        ui.label(egui::RichText::new(&preset.name).strong());
+        ui.weak(&preset.description);
+    });
+    delete
+}
@@ -0,0 +1,508 @@
+/// Normalization and missing value imputation of LFQ or TMT matrices.
+///
+/// Everything happens on log2 values: normalize, then impute, then (unless the log
+/// transform is kept) back to the linear scale. The result is written next to the
+/// other results as `<source>.processed.tsv`. kNN imputation of a large matrix takes
+/// a while, so processing runs on a worker thread, like the searches.
+use crate::plot;
+use crate::quant::{self, QuantMatrix};
+use crate::stats::{self, Rng};
+use eframe::egui;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Above this many complete rows, kNN only looks at an evenly spaced subset of them.
+const MAX_DONORS: usize = 2000;
+const SEED: u64 = 42;
+
+/// A matrix offered for processing. Sample names are the column names to write.
+pub struct Source {
+    /// File name stem, e.g. `lfq.peptides`.
+    pub name: String,
+    /// Header of the identifier column.
+    pub id: &'static str,
+    pub matrix: QuantMatrix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    None,
+    Median,
+    Quantile,
+    /// Ratios to the reference channel(s) of each row, TMT only.
+    Reference,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Imputation {
+    None,
+    /// Perseus style: a normal distribution shifted down by `shift` and narrowed to
+    /// `width`, both in standard deviations of the sample.
+    DownshiftedNormal {
+        shift: f64,
+        width: f64,
+    },
+    Minimum,
+    Knn {
+        k: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub normalization: Normalization,
+    pub log: bool,
+    pub imputation: Imputation,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            normalization: Normalization::Median,
+            log: true,
+            imputation: Imputation::None,
+        }
+    }
+}
+
+type Columns = Vec<Vec<Option<f64>>>;
+
+fn log2_columns(matrix: &QuantMatrix) -> Columns {
+    (0..matrix.samples.len())
+        .map(|s| {
+            matrix
+                .rows
+                .iter()
+                .map(|row| row.values[s].map(f64::log2))
+                .collect()
+        })
+        .collect()
+}
+
+fn present(column: &[Option<f64>]) -> Vec<f64> {
+    column.iter().flatten().copied().collect()
+}
+
+/// Shift every sample to the same median.
+fn median_normalize(columns: &mut Columns) {
+    let medians: Vec<Option<f64>> = columns.iter().map(|c| stats::median(&present(c))).collect();
+    let target = stats::mean(&medians.iter().flatten().copied().collect::<Vec<_>>()).unwrap_or(0.0);
+    for (column, median) in columns.iter_mut().zip(medians) {
+        let shift = target - median.unwrap_or(target);
+        column.iter_mut().flatten().for_each(|v| *v += shift);
+    }
+}
+
+/// Give every sample the same distribution: the mean of all sample quantiles. Samples
+/// with missing values are matched by relative rank.
+///
+/// The reference distribution is built once, on a grid as fine as the largest sample,
+/// and values are mapped onto it by rank.
+fn quantile_normalize(columns: &mut Columns) {
+    let sorted: Vec<Vec<f64>> = columns
+        .iter()
+        .map(|c| {
+            let mut values = present(c);
+            values.sort_by(|a, b| a.total_cmp(b));
+            values
+        })
+        .collect();
+    let points = sorted.iter().map(Vec::len).max().unwrap_or(0);
+    if points < 2 {
+        return;
+    }
+    let reference: Vec<f64> = (0..points)
+        .map(|i| {
+            let q = i as f64 / (points - 1) as f64;
+            let quantiles: Vec<f64> = sorted
+                .iter()
+                .filter_map(|values| stats::quantile_sorted(values, q))
+                .collect();
+            stats::mean(&quantiles).unwrap_or(0.0)
+        })
+        .collect();
+
+    for (column, values) in columns.iter_mut().zip(&sorted) {
+        let n = values.len();
+        if n < 2 {
+            continue;
+        }
+        for value in column.iter_mut().flatten() {
+            let rank = values.partition_point(|v| v < value);
+            let q = rank as f64 / (n - 1) as f64;
+            if let Some(target) = stats::quantile_sorted(&reference, q) {
+                *value = target;
+            }
+        }
+    }
+}
+
+/// Log ratio of each row to the mean of its reference channels.
+fn reference_normalize(columns: &mut Columns, reference: &[usize]) {
+    let rows = columns.first().map_or(0, Vec::len);
+    for r in 0..rows {
+        let references: Vec<f64> = reference.iter().filter_map(|&s| columns[s][r]).collect();
+        let level = stats::mean(&references);
+        for column in columns.iter_mut() {
+            column[r] = match level {
+                Some(level) => column[r].map(|v| v - level),
+                None => None,
+            };
+        }
+    }
+}
+
+fn impute_downshifted(columns: &mut Columns, shift: f64, width: f64) {
+    let mut rng = Rng::new(SEED);
+    for column in columns.iter_mut() {
+        let values = present(column);
+        let (Some(mean), Some(variance)) = (stats::mean(&values), stats::variance(&values)) else {
+            continue;
+        };
+        let sd = variance.sqrt();
+        for value in column.iter_mut().filter(|v| v.is_none()) {
+            *value = Some(mean - shift * sd + width * sd * rng.normal());
+        }
+    }
+}
+
+fn impute_minimum(columns: &mut Columns) {
+    for column in columns.iter_mut() {
+        let minimum = present(column).into_iter().reduce(f64::min);
+        for value in column.iter_mut().filter(|v| v.is_none()) {
+            *value = minimum;
+        }
+    }
+}
+
+/// Mean of the `k` complete rows closest to the row (over the samples it has).
+fn impute_knn(columns: &mut Columns, k: usize) {
+    let samples = columns.len();
+    let rows = columns.first().map_or(0, Vec::len);
+    let row = |r: usize| -> Vec<Option<f64>> { columns.iter().map(|c| c[r]).collect() };
+    let complete: Vec<Vec<f64>> = (0..rows)
+        .filter_map(|r| row(r).into_iter().collect::<Option<Vec<f64>>>())
+        .collect();
+    let step = complete.len().div_ceil(MAX_DONORS).max(1);
+    let donors: Vec<&Vec<f64>> = complete.iter().step_by(step).collect();
+    if donors.is_empty() {
+        return;
+    }
+
+    let mut imputed = Vec::new();
+    for r in 0..rows {
+        let values = row(r);
+        let observed: Vec<usize> = (0..samples).filter(|&s| values[s].is_some()).collect();
+        if observed.len() == samples || observed.is_empty() {
+            continue;
+        }
+        let mut distances: Vec<(f64, &Vec<f64>)> = donors
+            .iter()
+            .map(|donor| {
+                let distance: f64 = observed
+                    .iter()
+                    .map(|&s| (values[s].unwrap_or(0.0) - donor[s]).powi(2))
+                    .sum();
+                (distance / observed.len() as f64, *donor)
+            })
+            .collect();
+        distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let nearest = &distances[..k.min(distances.len())];
+        for s in (0..samples).filter(|s| values[*s].is_none()) {
+            let mean =
+                nearest.iter().map(|(_, donor)| donor[s]).sum::<f64>() / nearest.len() as f64;
+            imputed.push((s, r, mean));
+        }
+    }
+    for (s, r, value) in imputed {
+        columns[s][r] = Some(value);
+    }
+}
+
+/// The processed copy of `matrix`, log2 if `settings.log`.
+pub fn process(matrix: &QuantMatrix, settings: &Settings, reference: &[usize]) -> QuantMatrix {
+    let mut columns = log2_columns(matrix);
+    match settings.normalization {
+        Normalization::None => {}
+        Normalization::Median => median_normalize(&mut columns),
+        Normalization::Quantile => quantile_normalize(&mut columns),
+        Normalization::Reference => reference_normalize(&mut columns, reference),
+    }
+    match settings.imputation {
+        Imputation::None => {}
+        Imputation::DownshiftedNormal { shift, width } => {
+            impute_downshifted(&mut columns, shift, width)
+        }
+        Imputation::Minimum => impute_minimum(&mut columns),
+        Imputation::Knn { k } => impute_knn(&mut columns, k),
+    }
+
+    let mut processed = matrix.clone();
+    for (r, row) in processed.rows.iter_mut().enumerate() {
+        for (s, value) in row.values.iter_mut().enumerate() {
+            *value = match settings.log {
+                true => columns[s][r],
+                false => columns[s][r].map(f64::exp2),
+            };
+        }
+    }
+    processed
+}
+
+/// Per sample values for the preview, on the log2 scale.
+fn distributions(matrix: &QuantMatrix, log: bool) -> Vec<(String, Vec<f64>)> {
+    (0..matrix.samples.len())
+        .map(|s| {
+            let values = match log {
+                true => matrix.rows.iter().filter_map(|row| row.values[s]).collect(),
+                false => matrix.log2_column(s),
+            };
+            (matrix.samples[s].clone(), values)
+        })
+        .collect()
+}
+
+/// Sample distributions before and after processing, for the preview.
+struct Preview {
+    before: Vec<(String, Vec<f64>)>,
+    after: Vec<(String, Vec<f64>)>,
+}
+
+/// Process `source`, and write the result to `path` unless this is a preview.
+/// Returns the preview and the status message.
+fn run(
+    source: Source,
+    settings: Settings,
+    reference: Vec<usize>,
+    path: PathBuf,
+    write: bool,
+) -> (Preview, String) {
+    let matrix = process(&source.matrix, &settings, &reference);
+    let message = match write {
+        true => {
+            let rows: Vec<(String, Vec<Option<f64>>)> = matrix
+                .rows
+                .iter()
+                .map(|row| (row.label(), row.values.clone()))
+                .collect();
+            match quant::write_wide(&path, source.id, &matrix.samples, &rows) {
+                Ok(_) => format!("Written to {}", path.display()),
+                Err(e) => format!("Error: {}", e),
+            }
+        }
+        false => format!("Preview only, {} is not written yet", path.display()),
+    };
+    let preview = Preview {
+        before: distributions(&source.matrix, false),
+        after: distributions(&matrix, settings.log),
+    };
+    (preview, message)
+}
+
+#[derive(Default)]
+pub struct Processing {
+    settings: Settings,
+    level: usize,
+    preview: Option<Preview>,
+    message: String,
+    receiver: Option<Receiver<(Preview, String)>>,
+    started: Option<Instant>,
+}
+
+impl Processing {
+    /// Also forgets a running preview, it belongs to the previous results.
+    pub fn clear(&mut self) {
+        self.preview = None;
+        self.message.clear();
+        self.receiver = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+
+    /// Pick up the result of the worker thread.
+    fn poll(&mut self, ctx: &egui::Context) {
+        let Some(receiver) = &self.receiver else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok((preview, message)) => {
+                self.preview = Some(preview);
+                self.message = message;
+                self.receiver = None;
+            }
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.message = "Error: processing stopped unexpectedly".to_string();
+                self.receiver = None;
+            }
+            Err(mpsc::TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(250)),
+        }
+    }
+
+    /// `levels` name the matrices `source` can build; `reference` are the indices of
+    /// reference channels, empty when there are none.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        levels: &[&str],
+        reference: &[usize],
+        dir: &Path,
+        source: impl FnOnce(usize) -> Result<Source, String>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Matrix:");
+            for (i, level) in levels.iter().enumerate() {
+                ui.radio_value(&mut self.level, i, *level);
+            }
+        });
+
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("Normalization:");
+            ui.radio_value(&mut settings.normalization, Normalization::None, "None");
+            ui.radio_value(&mut settings.normalization, Normalization::Median, "Median");
+            ui.radio_value(
+                &mut settings.normalization,
+                Normalization::Quantile,
+                "Quantile",
+            );
+            ui.add_enabled_ui(!reference.is_empty(), |ui| {
+                ui.radio_value(
+                    &mut settings.normalization,
+                    Normalization::Reference,
+                    "Reference channel",
+                )
+                .on_disabled_hover_text("Mark a reference channel in the TMT channel annotation");
+            });
+        });
+        ui.checkbox(&mut settings.log, "Write log2 values");
+
+        ui.horizontal(|ui| {
+            ui.label("Imputation:");
+            let downshifted = Imputation::DownshiftedNormal {
+                shift: 1.8,
+                width: 0.3,
+            };
+            let choices = [
+                (Imputation::None, "None"),
+                (downshifted, "Down-shifted normal"),
+                (Imputation::Minimum, "Minimum"),
+                (Imputation::Knn { k: 10 }, "kNN"),
+            ];
+            for (choice, label) in choices {
+                let selected =
+                    std::mem::discriminant(&settings.imputation) == std::mem::discriminant(&choice);
+                if ui.radio(selected, label).clicked() && !selected {
+                    settings.imputation = choice;
+                }
+            }
+        });
+        match &mut settings.imputation {
+            Imputation::DownshiftedNormal { shift, width } => {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(shift)
+                            .speed(0.05)
+                            .range(0.0..=5.0)
+                            .prefix("shift: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(width)
+                            .speed(0.05)
+                            .range(0.05..=2.0)
+                            .prefix("width: "),
+                    );
+                    ui.weak("in standard deviations of each sample");
+                });
+            }
+            Imputation::Knn { k } => {
+                ui.add(egui::DragValue::new(k).range(1..=50).prefix("k: "));
+            }
+            Imputation::None | Imputation::Minimum => {}
+        }
+
+        self.poll(ui.ctx());
+        let running = self.is_running();
+        let (preview, write) = ui
+            .horizontal(|ui| {
+                ui.add_enabled_ui(!running, |ui| {
+                    let preview = ui
+                        .button("Preview")
+                        .on_hover_text("Process with these settings without writing anything")
+                        .clicked();
+                    (preview, ui.button("Process and Write").clicked())
+                })
+                .inner
+            })
+            .inner;
+        if (preview || write) && !running {
+            match source(self.level) {
+                Ok(source) => {
+                    let path = dir.join(format!("{}.processed.tsv", source.name));
+                    let (settings, reference) = (self.settings, reference.to_vec());
+                    let (sender, receiver) = mpsc::channel();
+                    thread::spawn(move || {
+                        let _ = sender.send(run(source, settings, reference, path, write));
+                    });
+                    self.receiver = Some(receiver);
+                    self.started = Some(Instant::now());
+                }
+                Err(e) => self.message = format!("Error: {}", e),
+            }
+        }
+        if self.is_running() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                let elapsed = self.started.map(|s| s.elapsed()).unwrap_or_default();
+                ui.label(format!("Processing ({})", crate::format_duration(elapsed)));
+            });
+        }
+        ui.label(&self.message);
+
+        if let Some(preview) = &self.preview {
+            ui.label("Before:");
+            plot::boxplots(ui, &preview.before, "log2 value");
+            ui.label("After:");
+            plot::boxplots(ui, &preview.after, "log2 value");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile_normalization_gives_equal_distributions() {
+        let mut columns: Columns = vec![
+            vec![Some(5.0), Some(2.0), Some(3.0), Some(4.0)],
+            vec![Some(5.0), Some(1.0), Some(6.0), Some(2.0)],
+            vec![Some(3.0), Some(4.0), Some(6.0), Some(7.0)],
+        ];
+        quantile_normalize(&mut columns);
+        // Mean of the sorted columns: 2, 3, 5, 6
+        let expected = [
+            vec![Some(6.0), Some(2.0), Some(3.0), Some(5.0)],
+            vec![Some(5.0), Some(2.0), Some(6.0), Some(3.0)],
+            vec![Some(2.0), Some(3.0), Some(5.0), Some(6.0)],
+        ];
+        assert_eq!(columns, expected);
+    }
+
+    #[test]
+    fn quantile_normalization_matches_missing_values_by_rank() {
+        let mut columns: Columns = vec![
+            vec![Some(1.0), Some(2.0), Some(3.0)],
+            vec![Some(10.0), None, Some(30.0)],
+        ];
+        quantile_normalize(&mut columns);
+        let expected = [
+            vec![Some(5.5), Some(11.0), Some(16.5)],
+            vec![Some(5.5), None, Some(16.5)],
+        ];
+        assert_eq!(columns, expected);
+    }
+}
@@ -0,0 +1,564 @@
+/// Provenance records for searches and the local run history built from them.
+///
+/// Every launch writes `sagegui_provenance.json` next to sage's own outputs, once when
+/// the search starts and again when it ends. Finished runs are also appended to a
+/// history file in the app data directory, which the GUI reads to list past runs with
+/// their stage timings and counts.
+use chrono::{DateTime, Local};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const PROVENANCE_FILE_NAME: &str = "sagegui_provenance.json";
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+const CONFIDENT_Q: f64 = 0.01;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputFile {
+    pub path: String,
+    pub size: Option<u64>,
+    /// SHA-256 of the file, or of all files (in path order) inside a `.d` folder.
+    /// Remote inputs are not hashed.
+    pub sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Provenance {
+    pub sagegui_version: String,
+    pub sage_version: Option<String>,
+    pub hostname: String,
+    pub threads: u16,
+    pub started: DateTime<Local>,
+    pub finished: Option<DateTime<Local>>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    pub output_directory: String,
+    pub files: Vec<InputFile>,
+    /// The GUI config the search was launched from.
+    pub config: serde_json::Value,
+    /// The config sage's `Input` was built from: `config` with the output template
+    /// expanded.
+    pub input: Option<serde_json::Value>,
+    #[serde(default)]
+    pub stats: RunStats,
+}
+
+/// Stage timings and counts of a search, kept in the history so runs can be compared
+/// across sage versions. `None` where a stage did not run or a count is not available.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RunStats {
+    pub hashing_secs: Option<f64>,
+    /// Digesting the FASTA and indexing fragments (`Runner::new`).
+    pub database_secs: Option<f64>,
+    /// Reading spectra, scoring, FDR, quantification and writing results (`Runner::run`).
+    pub search_secs: Option<f64>,
+    /// Entries in the FASTA, without the decoys sage generates.
+    pub fasta_entries: Option<usize>,
+    /// From the `spectrumList` count of the inputs, only when all are local mzML.
+    pub spectra: Option<usize>,
+    /// Every PSM sage reported.
+    pub psms: Option<usize>,
+    /// Rank 1 target PSMs at 1% spectrum-level FDR.
+    pub confident_psms: Option<usize>,
+}
+
+impl RunStats {
+    /// Read the counts from the FASTA, the spectra and the results of a finished
+    /// search. Remote files are not read.
+    pub fn count(&mut self, fasta: &str, spectra: &[String], output_directory: &str) {
+        if !crate::cloud::is_s3_uri(fasta) {
+            self.fasta_entries = count_fasta_entries(Path::new(fasta)).ok();
+        }
+        self.spectra = spectra
+            .iter()
+            .map(|path| spectrum_count(Path::new(path)))
+            .sum();
+        if !crate::cloud::is_s3_uri(output_directory) {
+            if let Ok(psms) = crate::results::read_psms(Path::new(output_directory)) {
+                self.psms = Some(psms.len());
+                self.confident_psms = Some(
+                    psms.iter()
+                        .filter(|psm| {
+                            psm.is_target() && psm.rank == 1 && psm.spectrum_q <= CONFIDENT_Q
+                        })
+                        .count(),
+                );
+            }
+        }
+    }
+}
+
+impl RunStats {
+    /// Fixed table of the stage timings and counts.
+    pub fn update_section(&self, ui: &mut egui::Ui) {
+        egui::Grid::new("run_stats_grid")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                let rows = [
+                    ("Hashing Inputs:", seconds(self.hashing_secs)),
+                    ("Building Database:", seconds(self.database_secs)),
+                    ("Searching:", seconds(self.search_secs)),
+                    ("FASTA Entries:", count(self.fasta_entries)),
+                    ("Spectra:", count(self.spectra)),
+                    ("PSMs Reported:", count(self.psms)),
+                    ("PSMs at 1% FDR:", count(self.confident_psms)),
+                ];
+                for (label, value) in rows {
+                    ui.label(label);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+fn seconds(secs: Option<f64>) -> String {
+    match secs {
+        Some(secs) => crate::format_duration(std::time::Duration::from_secs_f64(secs)),
+        None => "-".to_string(),
+    }
+}
+
+fn count(n: Option<usize>) -> String {
+    match n {
+        Some(n) => thousands(n as u64),
+        None => "-".to_string(),
+    }
+}
+
+fn count_fasta_entries(path: &Path) -> std::io::Result<usize> {
+    let mut entries = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        if line?.starts_with('>') {
+            entries += 1;
+        }
+    }
+    Ok(entries)
+}
+
+/// The `count` of the `spectrumList` of an uncompressed local mzML. Only the header
+/// is read, up to the first spectrum.
+fn spectrum_count(path: &Path) -> Option<usize> {
+    let is_mzml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mzml"));
+    if !is_mzml || crate::cloud::is_s3_uri(&path.to_string_lossy()) {
+        return None;
+    }
+    let reader = BufReader::new(File::open(path).ok()?);
+    for line in reader.lines() {
+        let line = line.ok()?;
+        if let Some(start) = line.find("<spectrumList") {
+            let rest = &line[start..];
+            let value = rest.split("count=\"").nth(1)?.split('"').next()?;
+            return value.parse().ok();
+        }
+        if line.contains("<spectrum ") {
+            break;
+        }
+    }
+    None
+}
+
+impl Provenance {
+    pub fn new(
+        config: serde_json::Value,
+        input: serde_json::Value,
+        output_directory: String,
+        files: Vec<String>,
+        threads: u16,
+    ) -> Self {
+        Self {
+            sagegui_version: env!("CARGO_PKG_VERSION").to_string(),
+            sage_version: None,
+            hostname: gethostname::gethostname().to_string_lossy().to_string(),
+            threads,
+            started: Local::now(),
+            finished: None,
+            status: RunStatus::Running,
+            error: None,
+            output_directory,
+            files: files
+                .into_iter()
+                .map(|path| InputFile {
+                    path,
+                    size: None,
+                    sha256: None,
+                })
+                .collect(),
+            config,
+            input: Some(input),
+            stats: RunStats::default(),
+        }
+    }
+
+    /// Fill in sizes and hashes of local inputs. This reads every input once,
+    /// so it is meant to run on the analysis thread.
+    pub fn hash_inputs(&mut self) {
+        let started = std::time::Instant::now();
+        for file in self.files.iter_mut() {
+            if crate::cloud::is_s3_uri(&file.path) {
+                continue;
+            }
+            let path = Path::new(&file.path);
+            match hash_path(path) {
+                Ok((size, digest)) => {
+                    file.size = Some(size);
+                    file.sha256 = Some(digest);
+                }
+                Err(e) => println!("Could not hash {}: {}", file.path, e),
+            }
+        }
+        self.stats.hashing_secs = Some(started.elapsed().as_secs_f64());
+    }
+
+    /// Take the sage version from the `results.json` sage wrote. Remote outputs are
+    /// not read back, their version stays unknown.
+    pub fn read_sage_version(&mut self) {
+        if crate::cloud::is_s3_uri(&self.output_directory) {
+            return;
+        }
+        let path = Path::new(&self.output_directory).join("results.json");
+        self.sage_version = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+            .and_then(|results| Some(results.get("version")?.as_str()?.to_string()));
+    }
+
+    pub fn finish(&mut self, result: &Result<String, String>) {
+        self.finished = Some(Local::now());
+        match result {
+            Ok(_) => self.status = RunStatus::Succeeded,
+            Err(e) => {
+                self.status = RunStatus::Failed;
+                self.error = Some(e.clone());
+            }
+        }
+    }
+
+    /// Write the record into the output directory. Remote outputs are skipped,
+    /// the run still shows up in the local history.
+    pub fn write(&self) -> Result<(), String> {
+        if crate::cloud::is_s3_uri(&self.output_directory) {
+            return Ok(());
+        }
+        let dir = Path::new(&self.output_directory);
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(dir.join(PROVENANCE_FILE_NAME), json).map_err(|e| e.to_string())
+    }
+
+    fn duration(&self) -> Option<chrono::Duration> {
+        self.finished.map(|f| f - self.started)
+    }
+
+    /// The "Last run" card.
+    pub fn summary_section(&self, ui: &mut egui::Ui) {
+        ui.heading("Last Run");
+        egui::Grid::new("last_run_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Status:");
+                ui.label(format!("{:?}", self.status));
+                ui.end_row();
+                ui.label("Started:");
+                ui.label(self.started.format("%Y-%m-%d %H:%M:%S").to_string());
+                ui.end_row();
+                if let Some(duration) = self.duration() {
+                    ui.label("Wall Time:");
+                    ui.label(crate::format_duration(
+                        duration.to_std().unwrap_or_default(),
+                    ));
+                    ui.end_row();
+                }
+                ui.label("sage Version:");
+                ui.label(self.sage_version.as_deref().unwrap_or("unknown"));
+                ui.end_row();
+                ui.label("Threads:");
+                ui.label(self.threads.to_string());
+                ui.end_row();
+                ui.label("Output:");
+                ui.label(&self.output_directory);
+                ui.end_row();
+            });
+
+        ui.add_space(5.0);
+        self.stats.update_section(ui);
+    }
+}
+
+fn thousands(n: u64) -> String {
+    let digits: Vec<char> = n.to_string().chars().collect();
+    let groups: Vec<String> = digits
+        .rchunks(3)
+        .rev()
+        .map(|group| group.iter().collect())
+        .collect();
+    groups.join(",")
+}
+
+fn hash_path(path: &Path) -> std::io::Result<(u64, String)> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buf = vec![0u8; 1 << 20];
+    for file in files {
+        let mut reader = File::open(&file)?;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            size += n as u64;
+        }
+    }
+    let digest = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((size, digest))
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(&entry?.path(), files)?;
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Open a folder in the platform's file manager.
+pub fn open_in_file_manager(path: &str) -> Result<(), String> {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(program)
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Could not open {}: {}", path, e))
+}
+
+pub enum HistoryAction {
+    ReopenConfig(serde_json::Value),
+    OpenFolder(String),
+}
+
+#[derive(Default)]
+pub struct RunHistory {
+    entries: Vec<Provenance>,
+    loaded: bool,
+    filter: String,
+}
+
+impl RunHistory {
+    fn path() -> Option<PathBuf> {
+        crate::app_dir().map(|d| d.join(HISTORY_FILE_NAME))
+    }
+
+    /// Read the index lazily, the first time anybody looks at it.
+    pub fn ensure_loaded(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        let Some(file) = Self::path().and_then(|p| File::open(p).ok()) else {
+            return;
+        };
+        // Skip lines that don't parse (e.g. written by a future version)
+        self.entries = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str(&line).ok())
+            .collect();
+    }
+
+    /// Add a finished run to the history file. Does file I/O, so it is meant to run
+    /// on the analysis thread; `push` then updates the list shown.
+    pub fn append(record: &Provenance) -> Result<(), String> {
+        let path = Self::path().ok_or("Could not find the app data directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| writeln!(f, "{}", line))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Show a run `append` already wrote. Before the first load there is nothing to
+    /// update, the file is read then.
+    pub fn push(&mut self, record: Provenance) {
+        if self.loaded {
+            self.entries.push(record);
+        }
+    }
+
+    pub fn update_section(&mut self, ui: &mut egui::Ui) -> Option<HistoryAction> {
+        self.ensure_loaded();
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            ui.text_edit_singleline(&mut self.filter)
+                .on_hover_text("Matches the output location or the sage version");
+            if ui.button("Reload").clicked() {
+                self.loaded = false;
+            }
+        });
+        if self.entries.is_empty() {
+            ui.label("No runs recorded yet.");
+            return None;
+        }
+
+        let filter = self.filter.to_lowercase();
+        egui::ScrollArea::vertical()
+            .id_salt("run_history")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("run_history_grid")
+                    .striped(true)
+                    .num_columns(10)
+                    .show(ui, |ui| {
+                        for header in [
+                            "Started", "Status", "sage", "Duration", "Database", "Search",
+                            "Spectra", "PSMs 1%", "Output",
+                        ] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+
+                        // Newest first
+                        for entry in self.entries.iter().rev() {
+                            let version = entry.sage_version.as_deref().unwrap_or("?");
+                            if !filter.is_empty()
+                                && !entry.output_directory.to_lowercase().contains(&filter)
+                                && !version.to_lowercase().contains(&filter)
+                            {
+                                continue;
+                            }
+                            ui.label(entry.started.format("%Y-%m-%d %H:%M").to_string());
+                            let (color, text) = match entry.status {
+                                RunStatus::Succeeded => (egui::Color32::GREEN, "OK"),
+                                RunStatus::Failed => (egui::Color32::RED, "Failed"),
+                                RunStatus::Running => (egui::Color32::YELLOW, "Unfinished"),
+                            };
+                            let status = ui.colored_label(color, text);
+                            if let Some(error) = &entry.error {
+                                status.on_hover_text(error);
+                            }
+                            ui.label(version);
+                            ui.label(
+                                entry
+                                    .duration()
+                                    .map(|d| crate::format_duration(d.to_std().unwrap_or_default()))
+                                    .unwrap_or_default(),
+                            );
+                            ui.label(seconds(entry.stats.database_secs));
+                            ui.label(seconds(entry.stats.search_secs));
+                            ui.label(count(entry.stats.spectra));
+                            ui.label(count(entry.stats.confident_psms));
+                            ui.label(&entry.output_directory).on_hover_text(format!(
+                                "{} input files, {} threads on {}",
+                                entry.files.len(),
+                                entry.threads,
+                                entry.hostname
+                            ));
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Reopen Config").clicked() {
+                                    action =
+                                        Some(HistoryAction::ReopenConfig(entry.config.clone()));
+                                }
+                                if ui.small_button("Open Results").clicked() {
+                                    action = Some(HistoryAction::OpenFolder(
+                                        entry.output_directory.clone(),
+                                    ));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thousands_separators() {
+        assert_eq!(thousands(0), "0");
+        assert_eq!(thousands(999), "999");
+        assert_eq!(thousands(1000), "1,000");
+        assert_eq!(thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn counts_fasta_entries_and_spectra() {
+        let dir = std::env::temp_dir().join(format!("sagegui_stats_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fasta = dir.join("db.fasta");
+        fs::write(&fasta, ">sp|P1|A\nPEPTIDEK\nPEPTIDER\n>sp|P2|B\nMKR\n").unwrap();
+        let mzml = dir.join("run.mzML");
+        fs::write(
+            &mzml,
+            "<mzML>\n  <run id=\"run\">\n    <spectrumList count=\"1234\" defaultDataProcessingRef=\"dp\">\n      <spectrum index=\"0\">\n",
+        )
+        .unwrap();
+
+        let mut stats = RunStats::default();
+        let spectra = [mzml.display().to_string()];
+        stats.count(
+            &fasta.display().to_string(),
+            &spectra,
+            &dir.display().to_string(),
+        );
+        assert_eq!(stats.fasta_entries, Some(2));
+        assert_eq!(stats.spectra, Some(1234));
+        // No results.sage.tsv in there
+        assert_eq!(stats.psms, None);
+
+        // One run that can't be counted makes the total unknown
+        let spectra = [
+            mzml.display().to_string(),
+            "s3://bucket/run.mzML".to_string(),
+        ];
+        stats.count(
+            &fasta.display().to_string(),
+            &spectra,
+            &dir.display().to_string(),
+        );
+        assert_eq!(stats.spectra, None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+/// Sage's peptide, modification and spectrum conventions mapped onto the PSI
+/// vocabularies, shared by the mzTab, mzIdentML and pepXML exports.
+use serde_json::Value;
+
+pub const PROTON: f64 = 1.007276;
+/// Terminal groups, pepXML gives terminal modification masses including them.
+pub const HYDROGEN: f64 = 1.007825;
+pub const HYDROXYL: f64 = 17.00274;
+
+/// Monoisotopic residue masses.
+pub fn residue_mass(residue: char) -> Option<f64> {
+    let mass = match residue {
+        'G' => 57.021464,
+        'A' => 71.037114,
+        'S' => 87.032028,
+        'P' => 97.052764,
+        'V' => 99.068414,
+        'T' => 101.047679,
+        'C' => 103.009185,
+        'L' | 'I' => 113.084064,
+        'N' => 114.042927,
+        'D' => 115.026943,
+        'Q' => 128.058578,
+        'K' => 128.094963,
+        'E' => 129.042593,
+        'M' => 131.040485,
+        'H' => 137.058912,
+        'F' => 147.068414,
+        'U' => 150.95363,
+        'R' => 156.101111,
+        'Y' => 163.06332,
+        'W' => 186.079313,
+        'O' => 237.147727,
+        _ => return None,
+    };
+    Some(mass)
+}
+
+/// Unimod accession, name and monoisotopic mass of common modifications. The one
+/// excerpt of Unimod in sagegui, for the exports and for open search annotation.
+pub const UNIMOD: &[(u32, &str, f64)] = &[
+    (1, "Acetyl", 42.010565),
+    (2, "Amidated", -0.984016),
+    (3, "Biotin", 226.077598),
+    (4, "Carbamidomethyl", 57.021464),
+    (5, "Carbamyl", 43.005814),
+    (7, "Deamidated", 0.984016),
+    (21, "Phospho", 79.966331),
+    (24, "Propionamide", 71.037114),
+    (27, "Glu->pyro-Glu", -18.010565),
+    (28, "Gln->pyro-Glu", -17.026549),
+    (30, "Cation:Na", 21.981943),
+    (34, "Methyl", 14.01565),
+    (35, "Oxidation", 15.994915),
+    (36, "Dimethyl", 28.0313),
+    (37, "Trimethyl", 42.04695),
+    (39, "Methylthio", 45.987721),
+    (40, "Sulfo", 79.956815),
+    (41, "Hex", 162.052824),
+    (43, "HexNAc", 203.079373),
+    (44, "Farnesyl", 204.187801),
+    (45, "Myristoyl", 210.198366),
+    (47, "Palmitoyl", 238.229666),
+    (55, "Glutathione", 305.068156),
+    (58, "Propionyl", 56.026215),
+    (64, "Succinyl", 100.016044),
+    (108, "Nethylmaleimide", 125.047679),
+    (121, "GG", 114.042927),
+    (122, "Formyl", 27.994915),
+    (129, "Iodo", 125.896648),
+    (188, "Label:13C(6)", 6.020129),
+    (214, "iTRAQ4plex", 144.102063),
+    (254, "Delta:H(2)C(2)", 26.01565),
+    (259, "Label:13C(6)15N(2)", 8.014199),
+    (267, "Label:13C(6)15N(4)", 10.008269),
+    (299, "Carboxy", 43.989829),
+    (312, "Cysteinyl", 119.004099),
+    (345, "Trioxidation", 47.984744),
+    (351, "Trp->Kynurenin", 3.994915),
+    (354, "Nitro", 44.985078),
+    (359, "Pro->pyro-Glu", 13.979265),
+    (368, "Cys->Dha", -33.987721),
+    (425, "Dioxidation", 31.989829),
+    (526, "Dethiomethyl", -48.003371),
+    (530, "Cation:K", 37.955882),
+    (730, "iTRAQ8plex", 304.20536),
+    (737, "TMT6plex", 229.162932),
+    (747, "Malonyl", 86.000394),
+    (765, "Met-loss", -131.040485),
+    (766, "Met-loss+Acetyl", -89.02992),
+    (951, "Cation:Ca[II]", 37.946941),
+    (1289, "Butyryl", 70.041865),
+    (1363, "Crotonyl", 68.026215),
+    (1849, "Hydroxyisobutyryl", 86.036779),
+    (2016, "TMTpro", 304.207146),
+];
+/// Tight enough to tell TMTpro from iTRAQ8plex.
+const UNIMOD_TOLERANCE: f64 = 0.001;
+
+pub fn unimod(mass: f64) -> Option<(u32, &'static str)> {
+    UNIMOD
+        .iter()
+        .find(|(_, _, delta)| (delta - mass).abs() <= UNIMOD_TOLERANCE)
+        .map(|&(accession, name, _)| (accession, name))
+}
+
+/// Site and position of a sage modification key: a residue, optionally after one of
+/// `^` (peptide N-terminus), `$` (peptide C-terminus), `[` or `]` (protein termini).
+pub fn mod_site(key: &str) -> (String, &'static str) {
+    let mut chars = key.chars();
+    let (terminus, position) = match chars.next() {
+        Some('^') => ("N-term", "Any N-term"),
+        Some('$') => ("C-term", "Any C-term"),
+        Some('[') => ("N-term", "Protein N-term"),
+        Some(']') => ("C-term", "Protein C-term"),
+        _ => return (key.to_string(), "Anywhere"),
+    };
+    match chars.as_str() {
+        "" => (terminus.to_string(), position),
+        residue => (residue.to_string(), position),
+    }
+}
+
+/// Plain sequence and (position, mass) of the modifications of a sage peptide such as
+/// `[+42.0106]-M[+15.9949]PEPTIDE`. Position 0 is the N-terminus, length + 1 the
+/// C-terminus.
+pub fn parse_peptide(peptide: &str) -> (String, Vec<(usize, f64)>) {
+    let mut sequence = String::new();
+    let mut mods = Vec::new();
+    let mut c_term = false;
+    let mut chars = peptide.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let mass: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let Ok(mass) = mass.parse::<f64>() else {
+                    continue;
+                };
+                let position = match (sequence.is_empty(), c_term) {
+                    (true, _) => 0,
+                    (false, true) => sequence.len() + 1,
+                    (false, false) => sequence.len(),
+                };
+                mods.push((position, mass));
+            }
+            '-' => c_term = !sequence.is_empty(),
+            c if c.is_ascii_alphabetic() => sequence.push(c),
+            _ => {}
+        }
+    }
+    (sequence, mods)
+}
+
+/// Accession and name of the native ID format of a run, from sage's `scannr` values.
+pub fn id_format(example: &str) -> (&'static str, &'static str) {
+    if example.contains("controllerType") {
+        ("MS:1000768", "Thermo nativeID format")
+    } else if example.contains('=') {
+        ("MS:1001530", "mzML unique identifier")
+    } else {
+        ("MS:1000776", "scan number only nativeID format")
+    }
+}
+
+/// Location URI of an input file.
+pub fn location(path: &str) -> String {
+    match path.contains("://") {
+        true => path.to_string(),
+        false if path.starts_with('/') => format!("file://{}", path),
+        false => format!("file:///{}", path.replace('\\', "/")),
+    }
+}
+
+/// (site key, mass) of the fixed or variable modifications in the settings.
+pub fn searched_mods(settings: &Value, key: &str) -> Vec<(String, f64)> {
+    let Some(mods) = settings
+        .pointer(&format!("/database/{}", key))
+        .and_then(Value::as_object)
+    else {
+        return Vec::new();
+    };
+    let mut searched = Vec::new();
+    for (site, masses) in mods {
+        match masses {
+            Value::Array(masses) => {
+                for mass in masses.iter().filter_map(Value::as_f64) {
+                    searched.push((site.clone(), mass));
+                }
+            }
+            mass => searched.extend(mass.as_f64().map(|mass| (site.clone(), mass))),
+        }
+    }
+    searched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmodified_peptide() {
+        assert_eq!(parse_peptide("PEPTIDE"), ("PEPTIDE".to_string(), vec![]));
+    }
+
+    #[test]
+    fn internal_modifications() {
+        let (sequence, mods) = parse_peptide("PEPC[+57.0215]TM[+15.9949]IDE");
+        assert_eq!(sequence, "PEPCTMIDE");
+        assert_eq!(mods, vec![(4, 57.0215), (6, 15.9949)]);
+    }
+
+    #[test]
+    fn n_terminal_modification() {
+        let (sequence, mods) = parse_peptide("[+42.0106]-M[+15.9949]PEPTIDE");
+        assert_eq!(sequence, "MPEPTIDE");
+        assert_eq!(mods, vec![(0, 42.0106), (1, 15.9949)]);
+    }
+
+    #[test]
+    fn c_terminal_modification() {
+        let (sequence, mods) = parse_peptide("PEPTIDEK[+8.0142]-[-0.984]");
+        assert_eq!(sequence, "PEPTIDEK");
+        assert_eq!(mods, vec![(8, 8.0142), (9, -0.984)]);
+    }
+
+    #[test]
+    fn unimod_tells_close_masses_apart() {
+        assert_eq!(unimod(304.2071), Some((2016, "TMTpro")));
+        assert_eq!(unimod(304.2054), Some((730, "iTRAQ8plex")));
+        assert_eq!(unimod(79.9663), Some((21, "Phospho")));
+        assert_eq!(unimod(79.9568), Some((40, "Sulfo")));
+        assert_eq!(unimod(12.3456), None);
+    }
+
+    #[test]
+    fn mod_sites() {
+        assert_eq!(mod_site("C"), ("C".to_string(), "Anywhere"));
+        assert_eq!(mod_site("^"), ("N-term".to_string(), "Any N-term"));
+        assert_eq!(mod_site("^Q"), ("Q".to_string(), "Any N-term"));
+        assert_eq!(mod_site("]"), ("C-term".to_string(), "Protein C-term"));
+    }
+}
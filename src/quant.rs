@@ -0,0 +1,246 @@
+/// Quantification tables, as a row (peptide or PSM) by sample matrix.
+use crate::results::{self, field, Psm, Tsv};
+use crate::stats;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const LFQ_FILE_NAME: &str = "lfq.tsv";
+
+/// Columns of `lfq.tsv` that describe the peptide, everything else is a file.
+const LFQ_ANNOTATIONS: &[&str] = &[
+    "peptide",
+    "charge",
+    "proteins",
+    "q_value",
+    "score",
+    "spectral_angle",
+];
+
+#[derive(Debug, Clone)]
+pub struct QuantRow {
+    pub peptide: String,
+    pub proteins: String,
+    pub charge: Option<u8>,
+    // Missing (or zero) intensities are `None`
+    pub values: Vec<Option<f64>>,
+}
+
+impl QuantRow {
+    pub fn label(&self) -> String {
+        match self.charge {
+            Some(charge) => format!("{}/{}", self.peptide, charge),
+            None => self.peptide.clone(),
+        }
+    }
+
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        query.is_empty() || self.peptide.contains(query) || self.proteins.contains(query)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QuantMatrix {
+    pub samples: Vec<String>,
+    pub rows: Vec<QuantRow>,
+}
+
+impl QuantMatrix {
+    /// A matrix of aggregated rows (e.g. proteins), the key stands in for the peptide.
+    pub fn from_rows(samples: Vec<String>, rows: Vec<(String, Vec<Option<f64>>)>) -> Self {
+        let rows = rows
+            .into_iter()
+            .map(|(key, values)| QuantRow {
+                peptide: key.clone(),
+                proteins: key,
+                charge: None,
+                values,
+            })
+            .collect();
+        Self { samples, rows }
+    }
+
+    /// log2 of the present values of one sample.
+    pub fn log2_column(&self, sample: usize) -> Vec<f64> {
+        self.rows
+            .iter()
+            .filter_map(|row| row.values[sample])
+            .map(f64::log2)
+            .collect()
+    }
+
+    pub fn missing_fraction(&self, sample: usize) -> f64 {
+        if self.rows.is_empty() {
+            return 0.0;
+        }
+        let missing = self
+            .rows
+            .iter()
+            .filter(|row| row.values[sample].is_none())
+            .count();
+        missing as f64 / self.rows.len() as f64
+    }
+
+    /// Pearson correlation of log2 values over the rows quantified in both samples.
+    pub fn correlation(&self, a: usize, b: usize) -> Option<f64> {
+        let pairs: Vec<(f64, f64)> = self
+            .rows
+            .iter()
+            .filter_map(|row| Some((row.values[a]?.log2(), row.values[b]?.log2())))
+            .collect();
+        stats::pearson(&pairs)
+    }
+}
+
+fn intensity(row: &[String], column: usize) -> Option<f64> {
+    field::<f64>(row, column).filter(|v| *v > 0.0 && v.is_finite())
+}
+
+pub fn read_lfq(dir: &Path) -> Result<QuantMatrix, String> {
+    let tsv = Tsv::open(&dir.join(LFQ_FILE_NAME))?;
+    let peptide = tsv.column("peptide")?;
+    let proteins = tsv.column("proteins")?;
+    let charge = tsv.optional_column("charge");
+    let (samples, columns): (Vec<String>, Vec<usize>) = tsv
+        .header()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, name)| !LFQ_ANNOTATIONS.contains(&name.as_str()))
+        .map(|(i, name)| (name, i))
+        .unzip();
+    if samples.is_empty() {
+        return Err(format!("{} has no intensity columns", LFQ_FILE_NAME));
+    }
+
+    let rows = tsv
+        .filter_map(|row| {
+            Some(QuantRow {
+                peptide: row.get(peptide)?.clone(),
+                proteins: row.get(proteins)?.clone(),
+                charge: charge.and_then(|i| field(&row, i)),
+                values: columns.iter().map(|&i| intensity(&row, i)).collect(),
+            })
+        })
+        .collect();
+    Ok(QuantMatrix { samples, rows })
+}
+
+pub const TMT_FILE_NAME: &str = "tmt.tsv";
+/// Reporter ion columns of `tmt.tsv` are named `tmt_<channel>`.
+const TMT_PREFIX: &str = "tmt_";
+
+/// Reporter intensities (or S/N) of the confident PSMs, one row per PSM.
+///
+/// `tmt.tsv` (or its impurity corrected copy, `file_name`) only has the spectrum, so
+/// peptides and proteins come from the PSM with the same (filename, scannr) in
+/// `results.sage.tsv`.
+pub fn read_tmt(dir: &Path, file_name: &str, q_value: f64) -> Result<QuantMatrix, String> {
+    let psms: HashMap<(String, String), Psm> = results::read_psms(dir)?
+        .into_iter()
+        .filter(|psm| psm.is_target() && psm.rank == 1 && psm.spectrum_q <= q_value)
+        .map(|psm| ((psm.filename.clone(), psm.scannr.clone()), psm))
+        .collect();
+
+    let tsv = Tsv::open(&dir.join(file_name))?;
+    let filename = tsv.column("filename")?;
+    let scannr = tsv.column("scannr")?;
+    let (samples, columns): (Vec<String>, Vec<usize>) = tsv
+        .header()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((name.strip_prefix(TMT_PREFIX)?.to_string(), i)))
+        .unzip();
+    if samples.is_empty() {
+        return Err(format!("{} has no reporter ion columns", file_name));
+    }
+
+    let rows: Vec<QuantRow> = tsv
+        .filter_map(|row| {
+            let key = (row.get(filename)?.clone(), row.get(scannr)?.clone());
+            let psm = psms.get(&key)?;
+            Some(QuantRow {
+                peptide: psm.peptide.clone(),
+                proteins: psm.proteins.clone(),
+                charge: None,
+                values: columns.iter().map(|&i| intensity(&row, i)).collect(),
+            })
+        })
+        .collect();
+    if rows.is_empty() {
+        return Err(format!(
+            "No PSMs in {} match a confident identification",
+            file_name
+        ));
+    }
+    Ok(QuantMatrix { samples, rows })
+}
+
+/// Rows summed per key, e.g. PSMs to peptides, sorted by key.
+pub fn rollup(
+    matrix: &QuantMatrix,
+    key: impl Fn(&QuantRow) -> &str,
+) -> Vec<(String, Vec<Option<f64>>)> {
+    let mut totals: BTreeMap<&str, Vec<Option<f64>>> = BTreeMap::new();
+    for row in &matrix.rows {
+        let total = totals
+            .entry(key(row))
+            .or_insert_with(|| vec![None; matrix.samples.len()]);
+        for (total, value) in total.iter_mut().zip(&row.values) {
+            if let Some(value) = value {
+                *total = Some(total.unwrap_or(0.0) + value);
+            }
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(key, values)| (key.to_string(), values))
+        .collect()
+}
+
+/// Tab separated matrix with one column per sample, missing values left empty.
+pub fn write_wide(
+    path: &Path,
+    id: &str,
+    samples: &[String],
+    rows: &[(String, Vec<Option<f64>>)],
+) -> Result<(), String> {
+    let mut out = id.to_string();
+    for sample in samples {
+        out.push('\t');
+        out.push_str(sample);
+    }
+    out.push('\n');
+    for (key, values) in rows {
+        out.push_str(key);
+        for value in values {
+            out.push('\t');
+            if let Some(value) = value {
+                out.push_str(&value.to_string());
+            }
+        }
+        out.push('\n');
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+/// A matrix written by `write_wide`: an identifier column followed by one column per
+/// sample. Values may be on the log scale, so only empty or non-numeric cells are missing.
+pub fn read_wide(path: &Path) -> Result<QuantMatrix, String> {
+    let tsv = Tsv::open(path)?;
+    let header = tsv.header();
+    if header.len() < 2 {
+        return Err(format!("{} has no sample columns", path.display()));
+    }
+    let samples = header[1..].to_vec();
+    let rows = tsv
+        .filter_map(|row| {
+            let key = row.first()?.clone();
+            let values = (1..=samples.len())
+                .map(|i| field::<f64>(&row, i).filter(|v| v.is_finite()))
+                .collect();
+            Some((key, values))
+        })
+        .collect();
+    Ok(QuantMatrix::from_rows(samples, rows))
+}
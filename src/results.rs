@@ -0,0 +1,223 @@
+/// Reading sage's tab separated outputs back in.
+///
+/// Columns are looked up by name so files from older or newer sage versions work as
+/// long as the columns we need are there.
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const RESULTS_FILE_NAME: &str = "results.sage.tsv";
+pub const FRAGMENTS_FILE_NAME: &str = "matched_fragments.sage.tsv";
+
+/// Row by row reader of a TSV file with a header line.
+pub struct Tsv {
+    path: PathBuf,
+    columns: HashMap<String, usize>,
+    lines: Lines<BufReader<File>>,
+}
+
+impl Tsv {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next() {
+            Some(Ok(header)) => header,
+            Some(Err(e)) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+            None => return Err(format!("{} is empty", path.display())),
+        };
+        let columns = header
+            .split('\t')
+            .enumerate()
+            .map(|(i, name)| (name.trim().to_string(), i))
+            .collect();
+        Ok(Self {
+            path: path.to_path_buf(),
+            columns,
+            lines,
+        })
+    }
+
+    pub fn column(&self, name: &str) -> Result<usize, String> {
+        self.columns
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("{} has no '{}' column", self.path.display(), name))
+    }
+
+    pub fn optional_column(&self, name: &str) -> Option<usize> {
+        self.columns.get(name).copied()
+    }
+
+    /// All column names in file order.
+    pub fn header(&self) -> Vec<String> {
+        let mut names: Vec<(&String, &usize)> = self.columns.iter().collect();
+        names.sort_by_key(|(_, i)| **i);
+        names.into_iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+impl Iterator for Tsv {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?.ok()?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(line.split('\t').map(|s| s.to_string()).collect());
+        }
+    }
+}
+
+/// Parse one field of a row, `None` if it is missing or malformed.
+pub fn field<T: FromStr>(row: &[String], column: usize) -> Option<T> {
+    row.get(column).and_then(|value| value.trim().parse().ok())
+}
+
+/// The columns of `results.sage.tsv` sagegui works with.
+#[derive(Debug, Clone)]
+pub struct Psm {
+    pub psm_id: u64,
+    pub peptide: String,
+    pub proteins: String,
+    pub filename: String,
+    pub scannr: String,
+    pub charge: Option<u8>,
+    /// Retention time in minutes.
+    pub rt: Option<f64>,
+    /// On sage's aligned retention time scale, 0 to 1 over the gradient.
+    pub predicted_rt: Option<f64>,
+    /// Zero for data without ion mobility.
+    pub ion_mobility: Option<f64>,
+    pub rank: u32,
+    // 1 for targets, -1 for decoys
+    pub label: i32,
+    pub expmass: f64,
+    pub calcmass: f64,
+    pub hyperscore: f64,
+    pub discriminant: f64,
+    pub spectrum_q: f64,
+    pub peptide_q: f64,
+    pub protein_q: f64,
+}
+
+impl Psm {
+    pub fn is_target(&self) -> bool {
+        self.label > 0
+    }
+}
+
+pub fn read_psms(dir: &Path) -> Result<Vec<Psm>, String> {
+    let tsv = Tsv::open(&dir.join(RESULTS_FILE_NAME))?;
+    let psm_id = tsv.column("psm_id")?;
+    let peptide = tsv.column("peptide")?;
+    let proteins = tsv.column("proteins")?;
+    let filename = tsv.column("filename")?;
+    let scannr = tsv.column("scannr")?;
+    let charge = tsv.optional_column("charge");
+    let rt = tsv.optional_column("rt");
+    let predicted_rt = tsv.optional_column("predicted_rt");
+    let ion_mobility = tsv.optional_column("ion_mobility");
+    let rank = tsv.optional_column("rank");
+    let label = tsv.column("label")?;
+    let expmass = tsv.column("expmass")?;
+    let calcmass = tsv.column("calcmass")?;
+    let hyperscore = tsv.column("hyperscore")?;
+    let discriminant = tsv.optional_column("sage_discriminant_score");
+    let spectrum_q = tsv.column("spectrum_q")?;
+    let peptide_q = tsv.column("peptide_q")?;
+    let protein_q = tsv.column("protein_q")?;
+
+    Ok(tsv
+        .filter_map(|row| {
+            Some(Psm {
+                psm_id: field(&row, psm_id)?,
+                peptide: row.get(peptide)?.clone(),
+                proteins: row.get(proteins)?.clone(),
+                filename: row.get(filename)?.clone(),
+                scannr: row.get(scannr)?.clone(),
+                charge: charge.and_then(|i| field(&row, i)),
+                rt: rt.and_then(|i| field(&row, i)),
+                predicted_rt: predicted_rt.and_then(|i| field(&row, i)),
+                ion_mobility: ion_mobility.and_then(|i| field(&row, i)),
+                rank: rank.and_then(|i| field(&row, i)).unwrap_or(1),
+                label: field(&row, label)?,
+                expmass: field(&row, expmass)?,
+                calcmass: field(&row, calcmass)?,
+                hyperscore: field(&row, hyperscore).unwrap_or(0.0),
+                discriminant: discriminant.and_then(|i| field(&row, i)).unwrap_or(0.0),
+                spectrum_q: field(&row, spectrum_q).unwrap_or(1.0),
+                peptide_q: field(&row, peptide_q).unwrap_or(1.0),
+                protein_q: field(&row, protein_q).unwrap_or(1.0),
+            })
+        })
+        .collect())
+}
+
+/// Target identifications passing a q-value threshold.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary {
+    pub psms: usize,
+    pub peptides: usize,
+    pub proteins: usize,
+}
+
+impl Summary {
+    pub fn new(psms: &[Psm], q_value: f64) -> Self {
+        let targets = || psms.iter().filter(|psm| psm.is_target() && psm.rank == 1);
+        Self {
+            psms: targets().filter(|psm| psm.spectrum_q <= q_value).count(),
+            peptides: targets()
+                .filter(|psm| psm.peptide_q <= q_value)
+                .map(|psm| psm.peptide.as_str())
+                .collect::<HashSet<_>>()
+                .len(),
+            proteins: targets()
+                .filter(|psm| psm.protein_q <= q_value)
+                .map(|psm| psm.proteins.as_str())
+                .collect::<HashSet<_>>()
+                .len(),
+        }
+    }
+}
+
+/// One annotated peak from `matched_fragments.sage.tsv`.
+#[derive(Debug, Clone)]
+pub struct Fragment {
+    pub psm_id: u64,
+    /// Ion series, `b` or `y`.
+    pub kind: String,
+    pub ordinal: u32,
+    pub charge: u8,
+    pub mz_experimental: f64,
+    pub mz_calculated: f64,
+    pub intensity: f64,
+}
+
+pub fn read_fragments(dir: &Path) -> Result<Vec<Fragment>, String> {
+    let tsv = Tsv::open(&dir.join(FRAGMENTS_FILE_NAME))?;
+    let psm_id = tsv.column("psm_id")?;
+    let kind = tsv.optional_column("fragment_type");
+    let ordinal = tsv.optional_column("fragment_ordinals");
+    let charge = tsv.optional_column("fragment_charge");
+    let experimental = tsv.column("fragment_mz_experimental")?;
+    let calculated = tsv.column("fragment_mz_calculated")?;
+    let intensity = tsv.optional_column("fragment_intensity");
+    Ok(tsv
+        .filter_map(|row| {
+            Some(Fragment {
+                psm_id: field(&row, psm_id)?,
+                kind: kind.and_then(|i| row.get(i)).cloned().unwrap_or_default(),
+                ordinal: ordinal.and_then(|i| field(&row, i)).unwrap_or(0),
+                charge: charge.and_then(|i| field(&row, i)).unwrap_or(1),
+                mz_experimental: field(&row, experimental)?,
+                mz_calculated: field(&row, calculated)?,
+                intensity: intensity.and_then(|i| field(&row, i)).unwrap_or(0.0),
+            })
+        })
+        .collect())
+}
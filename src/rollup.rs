@@ -0,0 +1,575 @@
+/// Protein quantities from sage's peptide level LFQ intensities.
+///
+/// Every row of `lfq.tsv` (a peptide, or a peptide at one charge) is a feature of the
+/// proteins it maps to; how peptides shared between proteins count is up to the user.
+use crate::design::Design;
+use crate::plot;
+use crate::quant::QuantMatrix;
+use crate::stats;
+use eframe::egui;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const PROTEIN_FILE_NAME: &str = "lfq.proteins.tsv";
+/// Proteins listed for the search box.
+const MAX_MATCHES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    TopN,
+    MedianPolish,
+    MaxLfq,
+}
+
+impl Method {
+    fn name(&self) -> &'static str {
+        match self {
+            Method::TopN => "Top N",
+            Method::MedianPolish => "Median polish",
+            Method::MaxLfq => "MaxLFQ",
+        }
+    }
+}
+
+/// What to do with peptides that map to more than one protein.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedPeptides {
+    /// Only peptides unique to a single protein are used.
+    Exclude,
+    /// Proteins connected through shared peptides are merged into one group, which
+    /// is quantified from all of their peptides.
+    Group,
+    /// The peptide counts for each of its proteins.
+    EveryProtein,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub method: Method,
+    pub top_n: usize,
+    pub shared: SharedPeptides,
+    pub min_peptides: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            method: Method::MaxLfq,
+            top_n: 3,
+            shared: SharedPeptides::Group,
+            min_peptides: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProteinRow {
+    pub protein: String,
+    pub peptides: usize,
+    pub values: Vec<Option<f64>>,
+}
+
+type Features<'a> = Vec<&'a [Option<f64>]>;
+
+/// Sum of the N features with the highest median intensity.
+fn top_n(features: &[&[Option<f64>]], samples: usize, n: usize) -> Vec<Option<f64>> {
+    let mut ranked: Vec<(f64, &[Option<f64>])> = features
+        .iter()
+        .map(|values| {
+            let present: Vec<f64> = values.iter().flatten().copied().collect();
+            (stats::median(&present).unwrap_or(0.0), *values)
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    (0..samples)
+        .map(|s| {
+            let present: Vec<f64> = ranked.iter().take(n).filter_map(|(_, v)| v[s]).collect();
+            (!present.is_empty()).then(|| present.iter().sum())
+        })
+        .collect()
+}
+
+/// Tukey's median polish on log2 values; the protein is the overall effect plus the
+/// sample effect.
+fn median_polish(features: &[&[Option<f64>]], samples: usize) -> Vec<Option<f64>> {
+    let mut residuals: Vec<Vec<Option<f64>>> = features
+        .iter()
+        .map(|values| values.iter().map(|v| v.map(f64::log2)).collect())
+        .collect();
+    let mut overall = 0.0;
+    let mut rows = vec![0.0; residuals.len()];
+    let mut columns = vec![0.0; samples];
+    for _ in 0..10 {
+        for (row, effect) in residuals.iter_mut().zip(rows.iter_mut()) {
+            let present: Vec<f64> = row.iter().flatten().copied().collect();
+            let median = stats::median(&present).unwrap_or(0.0);
+            row.iter_mut().flatten().for_each(|v| *v -= median);
+            *effect += median;
+        }
+        for (s, effect) in columns.iter_mut().enumerate() {
+            let present: Vec<f64> = residuals.iter().filter_map(|row| row[s]).collect();
+            let median = stats::median(&present).unwrap_or(0.0);
+            residuals
+                .iter_mut()
+                .filter_map(|row| row[s].as_mut())
+                .for_each(|v| *v -= median);
+            *effect += median;
+        }
+        // Keep the effects centered, their medians go into the overall level
+        for effects in [&mut rows, &mut columns] {
+            let median = stats::median(effects).unwrap_or(0.0);
+            effects.iter_mut().for_each(|e| *e -= median);
+            overall += median;
+        }
+    }
+    (0..samples)
+        .map(|s| {
+            let present = features.iter().any(|values| values[s].is_some());
+            present.then(|| (overall + columns[s]).exp2())
+        })
+        .collect()
+}
+
+/// MaxLFQ: the median log ratio of every pair of samples over their shared features,
+/// solved for per-sample levels by least squares. Each connected set of samples is
+/// then scaled so its summed intensity matches the summed feature intensities.
+fn max_lfq(features: &[&[Option<f64>]], samples: usize) -> Vec<Option<f64>> {
+    let mut ratios = vec![vec![None; samples]; samples];
+    for a in 0..samples {
+        for b in (a + 1)..samples {
+            let logs: Vec<f64> = features
+                .iter()
+                .filter_map(|values| Some(values[a]?.log2() - values[b]?.log2()))
+                .collect();
+            if let Some(ratio) = stats::median(&logs) {
+                ratios[a][b] = Some(ratio);
+                ratios[b][a] = Some(-ratio);
+            }
+        }
+    }
+
+    // Gauss-Seidel on the graph Laplacian, fine for a few dozen samples
+    let mut levels = vec![0.0; samples];
+    for _ in 0..200 {
+        let mut change = 0.0f64;
+        for a in 0..samples {
+            let neighbours: Vec<f64> = (0..samples)
+                .filter_map(|b| Some(levels[b] + ratios[a][b]?))
+                .collect();
+            if let Some(level) = stats::mean(&neighbours) {
+                change = change.max((level - levels[a]).abs());
+                levels[a] = level;
+            }
+        }
+        if change < 1e-9 {
+            break;
+        }
+    }
+
+    let totals: Vec<Option<f64>> = (0..samples)
+        .map(|s| {
+            let present: Vec<f64> = features.iter().filter_map(|values| values[s]).collect();
+            (!present.is_empty()).then(|| present.iter().sum())
+        })
+        .collect();
+    let mut component = vec![usize::MAX; samples];
+    let mut result = vec![None; samples];
+    for start in 0..samples {
+        if component[start] != usize::MAX || totals[start].is_none() {
+            continue;
+        }
+        let mut members = vec![start];
+        component[start] = start;
+        let mut i = 0;
+        while i < members.len() {
+            let a = members[i];
+            for b in 0..samples {
+                if ratios[a][b].is_some() && component[b] == usize::MAX {
+                    component[b] = start;
+                    members.push(b);
+                }
+            }
+            i += 1;
+        }
+        let observed: f64 = members.iter().filter_map(|&s| totals[s]).sum();
+        let modelled: f64 = members.iter().map(|&s| levels[s].exp2()).sum();
+        for &s in &members {
+            result[s] = Some(levels[s].exp2() * observed / modelled);
+        }
+    }
+    result
+}
+
+fn accessions(proteins: &str) -> impl Iterator<Item = &str> {
+    proteins.split(';').filter(|p| !p.is_empty())
+}
+
+/// Protein group of every entry of `proteins` (the accessions of a peptide): proteins
+/// are merged, by union-find, whenever a peptide maps to both of them. Groups are
+/// named by their sorted accessions joined with ';'.
+fn protein_groups(proteins: &[&str]) -> Vec<String> {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut parent: Vec<usize> = Vec::new();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for entry in proteins {
+        let mut first = None;
+        for accession in accessions(entry) {
+            let i = *index.entry(accession).or_insert_with(|| {
+                parent.push(parent.len());
+                parent.len() - 1
+            });
+            match first {
+                None => first = Some(i),
+                Some(first) => {
+                    let (a, b) = (root(&mut parent, first), root(&mut parent, i));
+                    parent[b] = a;
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (&accession, &i) in &index {
+        members
+            .entry(root(&mut parent, i))
+            .or_default()
+            .push(accession);
+    }
+    let names: HashMap<usize, String> = members
+        .into_iter()
+        .map(|(group, mut accessions)| {
+            accessions.sort_unstable();
+            (group, accessions.join(";"))
+        })
+        .collect();
+    proteins
+        .iter()
+        .map(|entry| match accessions(entry).next() {
+            Some(accession) => names[&root(&mut parent, index[accession])].clone(),
+            None => entry.to_string(),
+        })
+        .collect()
+}
+
+/// Protein by sample matrix, sorted by protein.
+pub fn rollup(matrix: &QuantMatrix, settings: &Settings) -> Vec<ProteinRow> {
+    let groups = match settings.shared {
+        SharedPeptides::Group => {
+            let entries: Vec<&str> = matrix
+                .rows
+                .iter()
+                .map(|row| row.proteins.as_str())
+                .collect();
+            protein_groups(&entries)
+        }
+        _ => Vec::new(),
+    };
+    let mut proteins: BTreeMap<&str, Features> = BTreeMap::new();
+    for (i, row) in matrix.rows.iter().enumerate() {
+        let accessions: Vec<&str> = accessions(&row.proteins).collect();
+        match settings.shared {
+            SharedPeptides::Exclude if accessions.len() > 1 => {}
+            SharedPeptides::Exclude => {
+                proteins.entry(&row.proteins).or_default().push(&row.values);
+            }
+            SharedPeptides::Group => {
+                proteins.entry(&groups[i]).or_default().push(&row.values);
+            }
+            SharedPeptides::EveryProtein => {
+                for accession in accessions {
+                    proteins.entry(accession).or_default().push(&row.values);
+                }
+            }
+        }
+    }
+
+    let samples = matrix.samples.len();
+    proteins
+        .into_iter()
+        .filter(|(_, features)| features.len() >= settings.min_peptides)
+        .map(|(protein, features)| ProteinRow {
+            protein: protein.to_string(),
+            peptides: features.len(),
+            values: match settings.method {
+                Method::TopN => top_n(&features, samples, settings.top_n),
+                Method::MedianPolish => median_polish(&features, samples),
+                Method::MaxLfq => max_lfq(&features, samples),
+            },
+        })
+        .collect()
+}
+
+/// Columns are named by sample and grouped by condition, like the peptide export.
+pub fn write(
+    path: &Path,
+    samples: &[String],
+    rows: &[ProteinRow],
+    design: &Design,
+) -> Result<(), String> {
+    let order = design.order(samples);
+    let mut out = "protein\tpeptides".to_string();
+    for &i in &order {
+        out.push('\t');
+        out.push_str(&design.column_name(&samples[i]));
+    }
+    out.push('\n');
+    for row in rows {
+        out.push_str(&format!("{}\t{}", row.protein, row.peptides));
+        for &i in &order {
+            out.push('\t');
+            if let Some(value) = row.values[i] {
+                out.push_str(&value.to_string());
+            }
+        }
+        out.push('\n');
+    }
+    fs::write(path, out).map_err(|e| e.to_string())
+}
+
+#[derive(Default)]
+pub struct Rollup {
+    settings: Settings,
+    proteins: Option<Vec<ProteinRow>>,
+    query: String,
+    selected: Option<usize>,
+    message: String,
+}
+
+impl Rollup {
+    /// Results from a previous `lfq.tsv` are dropped when a new one is loaded.
+    pub fn clear(&mut self) {
+        self.proteins = None;
+        self.selected = None;
+        self.message.clear();
+    }
+
+    pub fn proteins(&self) -> Option<&[ProteinRow]> {
+        self.proteins.as_deref()
+    }
+
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        matrix: &QuantMatrix,
+        dir: &Path,
+        design: &Design,
+        names: &[String],
+    ) {
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("Method:");
+            for method in [Method::TopN, Method::MedianPolish, Method::MaxLfq] {
+                ui.radio_value(&mut settings.method, method, method.name());
+            }
+            if settings.method == Method::TopN {
+                ui.add(
+                    egui::DragValue::new(&mut settings.top_n)
+                        .range(1..=10)
+                        .prefix("N: "),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Shared peptides:");
+            ui.radio_value(&mut settings.shared, SharedPeptides::Exclude, "Exclude");
+            ui.radio_value(&mut settings.shared, SharedPeptides::Group, "Protein group")
+                .on_hover_text(
+                    "Proteins connected through shared peptides are merged and quantified together",
+                );
+            ui.radio_value(
+                &mut settings.shared,
+                SharedPeptides::EveryProtein,
+                "Every protein",
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Min. peptides:");
+            ui.add(egui::DragValue::new(&mut settings.min_peptides).range(1..=10));
+        });
+
+        if ui.button("Roll Up and Write lfq.proteins.tsv").clicked() {
+            let proteins = rollup(matrix, &self.settings);
+            let path = dir.join(PROTEIN_FILE_NAME);
+            self.message = match write(&path, &matrix.samples, &proteins, design) {
+                Ok(_) => format!("{} proteins written to {}", proteins.len(), path.display()),
+                Err(e) => format!("Error: {}", e),
+            };
+            self.proteins = Some(proteins);
+            self.selected = None;
+        }
+        ui.label(&self.message);
+
+        let Some(proteins) = &self.proteins else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.add(egui::TextEdit::singleline(&mut self.query).hint_text("protein accession"));
+        });
+        let query = self.query.trim();
+        let matches: Vec<usize> = (0..proteins.len())
+            .filter(|&i| proteins[i].protein.contains(query))
+            .take(MAX_MATCHES)
+            .collect();
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        egui::ScrollArea::vertical()
+            .id_salt("lfq_proteins")
+            .max_height(200.0)
+            .show_rows(ui, row_height, matches.len(), |ui, range| {
+                for &i in &matches[range] {
+                    let row = &proteins[i];
+                    let text = format!("{}  ({} peptides)", row.protein, row.peptides);
+                    if ui
+                        .selectable_label(self.selected == Some(i), text)
+                        .clicked()
+                    {
+                        self.selected = Some(i);
+                    }
+                }
+            });
+        if let Some(row) = self.selected.and_then(|i| proteins.get(i)) {
+            ui.strong(&row.protein);
+            plot::bars(ui, names, &row.values, "intensity");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            match (a, e) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 1e-6, "{:?}", actual),
+                _ => assert_eq!(a, e, "{:?}", actual),
+            }
+        }
+    }
+
+    /// Twice as much in the second sample and four times in the third, one feature is
+    /// missing from the second sample.
+    const DOUBLING: [[Option<f64>; 3]; 3] = [
+        [Some(100.0), Some(200.0), Some(400.0)],
+        [Some(1000.0), Some(2000.0), Some(4000.0)],
+        [Some(30.0), None, Some(120.0)],
+    ];
+
+    fn doubling() -> Vec<&'static [Option<f64>]> {
+        DOUBLING.iter().map(|row| &row[..]).collect()
+    }
+
+    fn ratios(values: &[Option<f64>]) -> Vec<f64> {
+        values
+            .iter()
+            .map(|v| v.unwrap() / values[0].unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn top_n_sums_the_most_intense_features() {
+        assert_close(
+            &top_n(&doubling(), 3, 2),
+            &[Some(1100.0), Some(2200.0), Some(4400.0)],
+        );
+        assert_close(
+            &top_n(&doubling(), 3, 3),
+            &[Some(1130.0), Some(2200.0), Some(4520.0)],
+        );
+    }
+
+    #[test]
+    fn median_polish_keeps_a_constant_ratio() {
+        let values = median_polish(&doubling(), 3);
+        for (ratio, expected) in ratios(&values).iter().zip([1.0, 2.0, 4.0]) {
+            assert!((ratio - expected).abs() < 1e-9, "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn max_lfq_keeps_a_constant_ratio() {
+        let values = max_lfq(&doubling(), 3);
+        for (ratio, expected) in ratios(&values).iter().zip([1.0, 2.0, 4.0]) {
+            assert!((ratio - expected).abs() < 1e-6, "{:?}", values);
+        }
+        // Scaled to the summed intensity of the features
+        let total: f64 = values.iter().flatten().sum();
+        assert!((total - (1130.0 + 2200.0 + 4520.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn max_lfq_scales_disconnected_samples_separately() {
+        let features: [[Option<f64>; 5]; 4] = [
+            [Some(100.0), Some(200.0), None, None, None],
+            [Some(10.0), Some(20.0), None, None, None],
+            [None, None, Some(50.0), Some(150.0), None],
+            [None, None, Some(5.0), Some(15.0), None],
+        ];
+        let features: Vec<&[Option<f64>]> = features.iter().map(|row| &row[..]).collect();
+        assert_close(
+            &max_lfq(&features, 5),
+            &[Some(110.0), Some(220.0), Some(55.0), Some(165.0), None],
+        );
+    }
+
+    fn keys(shared: SharedPeptides) -> Vec<(String, usize)> {
+        let matrix = QuantMatrix::from_rows(
+            vec!["a".to_string()],
+            ["P1", "P1;P2", "P2", "P2"]
+                .iter()
+                .map(|proteins| (proteins.to_string(), vec![Some(1.0)]))
+                .collect(),
+        );
+        let settings = Settings {
+            shared,
+            min_peptides: 1,
+            ..Settings::default()
+        };
+        rollup(&matrix, &settings)
+            .into_iter()
+            .map(|row| (row.protein, row.peptides))
+            .collect()
+    }
+
+    #[test]
+    fn shared_peptides() {
+        let key = |protein: &str, peptides| (protein.to_string(), peptides);
+        assert_eq!(
+            keys(SharedPeptides::Exclude),
+            vec![key("P1", 1), key("P2", 2)]
+        );
+        assert_eq!(keys(SharedPeptides::Group), vec![key("P1;P2", 4)]);
+        assert_eq!(
+            keys(SharedPeptides::EveryProtein),
+            vec![key("P1", 2), key("P2", 3)]
+        );
+    }
+
+    #[test]
+    fn groups_overlapping_proteins() {
+        let entries = [
+            "P3;P2", "P1;P2", "P4", "P5;P6", "P4", "P3", "P7;P1", "", "P6",
+        ];
+        let groups = protein_groups(&entries);
+        let expected = [
+            "P1;P2;P3;P7",
+            "P1;P2;P3;P7",
+            "P4",
+            "P5;P6",
+            "P4",
+            "P1;P2;P3;P7",
+            "P1;P2;P3;P7",
+            "",
+            "P5;P6",
+        ];
+        assert_eq!(groups, expected);
+    }
+}
@@ -0,0 +1,302 @@
+/// Arithmetic mean, `None` for no values.
+pub fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Sample variance (n - 1 in the denominator).
+pub fn variance(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values)?;
+    Some(values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64)
+}
+
+/// Linear interpolation between closest ranks, `q` in 0..=1.
+pub fn quantile(values: &[f64], q: f64) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    quantile_sorted(&sorted, q)
+}
+
+/// `quantile` of values that are already sorted, without copying them.
+pub fn quantile_sorted(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let pos = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    Some(sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64))
+}
+
+pub fn median(values: &[f64]) -> Option<f64> {
+    quantile(values, 0.5)
+}
+
+/// Pearson correlation over the pairs where both values are present.
+pub fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 3 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        xy += (x - mean_x) * (y - mean_y);
+        xx += (x - mean_x).powi(2);
+        yy += (y - mean_y).powi(2);
+    }
+    if xx == 0.0 || yy == 0.0 {
+        return None;
+    }
+    Some(xy / (xx * yy).sqrt())
+}
+
+/// Coefficient of variation (on the linear scale) of the present values.
+pub fn cv(values: &[f64]) -> Option<f64> {
+    let mean = mean(values)?;
+    if mean <= 0.0 {
+        return None;
+    }
+    Some(variance(values)?.sqrt() / mean)
+}
+
+/// Small seeded generator (SplitMix64), so imputed values are reproducible.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Uniform in (0, 1).
+    pub fn uniform(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        ((z >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    /// Standard normal by Box-Muller.
+    pub fn normal(&mut self) -> f64 {
+        let (u, v) = (self.uniform(), self.uniform());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+/// ln Γ(x) for x > 0 (Lanczos approximation).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for (i, c) in COEFFICIENTS.iter().enumerate() {
+        series += c / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Continued fraction of the incomplete beta function (modified Lentz).
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        for aa in [
+            m * (b - m) * x / ((qam + m2) * (a + m2)),
+            -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2)),
+        ] {
+            d = 1.0 + aa * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + aa / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    h
+}
+
+/// Regularized incomplete beta function I_x(a, b).
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// Two-sided p-value of a t statistic with `df` degrees of freedom.
+pub fn t_test_p(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// Benjamini-Hochberg adjusted p-values, in the order given.
+pub fn benjamini_hochberg(p_values: &[f64]) -> Vec<f64> {
+    let n = p_values.len();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| p_values[b].total_cmp(&p_values[a]));
+    let mut adjusted = vec![1.0; n];
+    let mut running = 1.0f64;
+    for (rank, &i) in order.iter().enumerate() {
+        // Ranks counted from the largest p-value down
+        let k = (n - rank) as f64;
+        running = running.min(p_values[i] * n as f64 / k);
+        adjusted[i] = running.min(1.0);
+    }
+    adjusted
+}
+
+pub fn digamma(mut x: f64) -> f64 {
+    let mut result = 0.0;
+    while x < 6.0 {
+        result -= 1.0 / x;
+        x += 1.0;
+    }
+    let f = 1.0 / (x * x);
+    result + x.ln() - 0.5 / x - f * (1.0 / 12.0 - f * (1.0 / 120.0 - f / 252.0))
+}
+
+pub fn trigamma(mut x: f64) -> f64 {
+    let mut result = 0.0;
+    while x < 6.0 {
+        result += 1.0 / (x * x);
+        x += 1.0;
+    }
+    let f = 1.0 / (x * x);
+    result + 1.0 / x + f / 2.0 + f / x * (1.0 / 6.0 - f * (1.0 / 30.0 - f / 42.0))
+}
+
+/// Second derivative of the digamma function.
+fn tetragamma(mut x: f64) -> f64 {
+    let mut result = 0.0;
+    while x < 6.0 {
+        result -= 2.0 / (x * x * x);
+        x += 1.0;
+    }
+    let f = 1.0 / (x * x);
+    result - f - f / x - f * f * (0.5 - f * (1.0 / 6.0 - f / 6.0))
+}
+
+/// Solves trigamma(x) = y by Newton's method, as in limma.
+pub fn inverse_trigamma(y: f64) -> f64 {
+    if y > 1e7 {
+        return 1.0 / y.sqrt();
+    }
+    if y < 1e-6 {
+        return 1.0 / y;
+    }
+    let mut x = 0.5 + 1.0 / y;
+    for _ in 0..50 {
+        let tri = trigamma(x);
+        let step = tri * (1.0 - tri / y) / tetragamma(x);
+        x += step;
+        if -step / x < 1e-8 {
+            break;
+        }
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn quantiles() {
+        let values = [4.0, 1.0, 3.0, 2.0];
+        assert_eq!(quantile(&values, 0.0), Some(1.0));
+        assert_eq!(quantile(&values, 0.5), Some(2.5));
+        assert_eq!(quantile(&values, 1.0), Some(4.0));
+        assert_eq!(quantile_sorted(&[1.0, 2.0, 3.0, 4.0], 0.25), Some(1.75));
+        assert_eq!(median(&[]), None);
+    }
+
+    #[test]
+    fn regularized_incomplete_beta() {
+        // I_0.4(2, 3) is a binomial tail: P(X >= 2), X ~ Bin(4, 0.4)
+        assert_close(incomplete_beta(2.0, 3.0, 0.4), 0.5248, 1e-10);
+        assert_close(incomplete_beta(0.5, 0.5, 0.5), 0.5, 1e-10);
+        assert_eq!(incomplete_beta(2.0, 3.0, 0.0), 0.0);
+        assert_eq!(incomplete_beta(2.0, 3.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn t_test_p_values() {
+        assert_close(t_test_p(2.0, 4.0), 0.116_116_523_5, 1e-9);
+        assert_close(t_test_p(-2.0, 4.0), 0.116_116_523_5, 1e-9);
+        assert_close(t_test_p(0.0, 10.0), 1.0, 1e-12);
+        // Approaches the normal distribution
+        assert_close(t_test_p(1.959_964, 1e6), 0.05, 1e-5);
+    }
+
+    #[test]
+    fn benjamini_hochberg_matches_p_adjust() {
+        // p.adjust(c(0.01, 0.04, 0.03, 0.005, 0.2), "BH") in R
+        let adjusted = benjamini_hochberg(&[0.01, 0.04, 0.03, 0.005, 0.2]);
+        for (a, e) in adjusted.iter().zip([0.025, 0.05, 0.05, 0.025, 0.2]) {
+            assert_close(*a, e, 1e-12);
+        }
+        assert_eq!(benjamini_hochberg(&[0.9, 0.8]), vec![0.9, 0.9]);
+    }
+
+    #[test]
+    fn polygamma_values() {
+        // The asymptotic series is good to a few 1e-9
+        assert_close(digamma(1.0), -0.577_215_664_901_532_9, 1e-8);
+        assert_close(digamma(0.5), -1.963_510_026_021_423_5, 1e-8);
+        assert_close(trigamma(1.0), std::f64::consts::PI.powi(2) / 6.0, 1e-8);
+        assert_close(trigamma(0.5), std::f64::consts::PI.powi(2) / 2.0, 1e-8);
+    }
+
+    #[test]
+    fn inverse_trigamma_round_trips() {
+        for x in [0.05, 0.5, 1.0, 2.7, 10.0, 250.0] {
+            assert_close(inverse_trigamma(trigamma(x)), x, 1e-6 * x);
+        }
+    }
+}
@@ -0,0 +1,527 @@
+/// Parameter sweeps: the Cartesian product of value lists for a few `Config` fields,
+/// each combination searched into its own output folder.
+///
+/// Fields are addressed by their dotted path in the saved config JSON (for example
+/// `database.enzyme.missed_cleavages`), so anything that can be saved can be swept.
+/// A plain number for `precursor_tol` or `fragment_tol` means `±value` in the unit
+/// already selected.
+use crate::results::{self, Summary};
+use crate::Config;
+use chrono::{DateTime, Local};
+use eframe::egui;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+const Q_VALUE: f64 = 0.01;
+/// Above this many combinations the sweep needs a second click.
+const LARGE_SWEEP: usize = 50;
+
+/// Fields that are commonly swept, offered in the field picker.
+const COMMON_FIELDS: &[(&str, &str)] = &[
+    ("precursor_tol", "Precursor tolerance (±)"),
+    ("fragment_tol", "Fragment tolerance (±)"),
+    ("database.enzyme.missed_cleavages", "Missed cleavages"),
+    ("database.enzyme.semi_enzymatic", "Semi-enzymatic"),
+    ("database.enzyme.min_len", "Min peptide length"),
+    ("database.enzyme.max_len", "Max peptide length"),
+    ("database.max_variable_mods", "Max variable mods"),
+    ("min_matched_peaks", "Min matched peaks"),
+    ("min_peaks", "Min peaks"),
+    ("max_peaks", "Max peaks"),
+    ("max_fragment_charge", "Max fragment charge"),
+    ("deisotope", "Deisotope"),
+    ("chimera", "Chimera"),
+    ("score_type", "Score type"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Axis {
+    pub field: String,
+    // Comma separated, each value is JSON (bare words are taken as strings)
+    pub values: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    Queued,
+    Done(Summary),
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct Combination {
+    pub label: String,
+    pub output_directory: String,
+    pub status: Status,
+    pub runtime: Option<Duration>,
+}
+
+/// Actions requested from the sweep panel, handled by the launcher.
+pub enum SweepAction {
+    /// Combination index and its config, with the output directory already set.
+    Queue(Vec<(usize, Config)>),
+    CancelRemaining,
+}
+
+#[derive(Default)]
+pub struct Sweep {
+    pub axes: Vec<Axis>,
+    combinations: Vec<Combination>,
+    confirm_large: bool,
+    /// Combination count for the axes and config generation it was computed from.
+    planned: Option<(Vec<Axis>, u64, Result<usize, String>)>,
+    /// Why the last sweep could not be queued.
+    error: Option<String>,
+}
+
+/// Split on commas that are not inside brackets, braces or quotes.
+fn split_values(text: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let (mut depth, mut quoted) = (0i32, false);
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                values.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    values.push(current);
+    values
+        .into_iter()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+fn parse_values(text: &str) -> Vec<Value> {
+    split_values(text)
+        .into_iter()
+        .map(|v| serde_json::from_str(&v).unwrap_or(Value::String(v)))
+        .collect()
+}
+
+/// A copy of `config` with the field at dotted `path` set to `value`.
+pub fn set_field(config: &Config, path: &str, value: &Value) -> Result<Config, String> {
+    let mut json = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    let pointer = format!("/{}", path.trim().replace('.', "/"));
+    let target = json
+        .pointer_mut(&pointer)
+        .ok_or_else(|| format!("Unknown config field '{}'", path))?;
+
+    let tolerance = path.ends_with("_tol");
+    match (target, value.as_f64()) {
+        // {"ppm": [lo, hi]} or {"da": [lo, hi]}
+        (Value::Object(unit), Some(width)) if tolerance => {
+            for bounds in unit.values_mut() {
+                *bounds = serde_json::json!([-width, width]);
+            }
+        }
+        (target, _) => *target = value.clone(),
+    }
+
+    let mut updated: Config = serde_json::from_value(json)
+        .map_err(|e| format!("Invalid value {} for '{}': {}", value, path, e))?;
+    // Not part of the saved config
+    updated.bruker_config = config.bruker_config.clone();
+    Ok(updated)
+}
+
+/// All combinations of the axes as (label, config) pairs.
+pub fn expand(base: &Config, axes: &[Axis]) -> Result<Vec<(String, Config)>, String> {
+    let mut combinations = vec![(String::new(), base.clone())];
+    for axis in axes {
+        let field = axis.field.trim();
+        if field.is_empty() {
+            continue;
+        }
+        let values = parse_values(&axis.values);
+        if values.is_empty() {
+            return Err(format!("No values given for '{}'", field));
+        }
+        let mut next = Vec::with_capacity(combinations.len() * values.len());
+        for (label, config) in &combinations {
+            for value in &values {
+                let name = field.rsplit('.').next().unwrap_or(field);
+                let value_text = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                let label = match label.is_empty() {
+                    true => format!("{}={}", name, value_text),
+                    false => format!("{}, {}={}", label, name, value_text),
+                };
+                next.push((label, set_field(config, field, value)?));
+            }
+        }
+        combinations = next;
+    }
+    if combinations.len() == 1 && combinations[0].0.is_empty() {
+        return Err("Add at least one field to sweep".to_string());
+    }
+    Ok(combinations)
+}
+
+fn format_runtime(runtime: Option<Duration>) -> String {
+    runtime
+        .map(crate::format_duration)
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Sweep {
+    /// Number of combinations, or why there are none. Expanding builds every config,
+    /// so it only happens again when the axes change or `generation` says the base
+    /// config may have.
+    fn planned(&mut self, base: &Config, generation: u64) -> Result<usize, String> {
+        match &self.planned {
+            Some((axes, planned, count)) if *axes == self.axes && *planned == generation => {
+                count.clone()
+            }
+            _ => {
+                let count = expand(base, &self.axes).map(|c| c.len());
+                self.planned = Some((self.axes.clone(), generation, count.clone()));
+                count
+            }
+        }
+    }
+
+    /// Prepare the jobs of a sweep below `base.output_directory`.
+    fn plan(&mut self, base: &Config, now: DateTime<Local>) -> Result<SweepAction, String> {
+        let combinations = expand(base, &self.axes)?;
+        let root = Path::new(&base.output_directory)
+            .join(format!("sweep-{}", now.format("%Y%m%d-%H%M%S")));
+
+        self.combinations.clear();
+        let mut jobs = Vec::new();
+        for (i, (label, mut config)) in combinations.into_iter().enumerate() {
+            let folder = crate::output::path_component(&format!(
+                "{:02}_{}",
+                i + 1,
+                label.replace(", ", "_").replace('=', "-")
+            ));
+            config.output_directory = root.join(folder).display().to_string();
+            config.output_template.clear();
+            config.name = label.clone();
+            self.combinations.push(Combination {
+                label,
+                output_directory: config.output_directory.clone(),
+                status: Status::Queued,
+                runtime: None,
+            });
+            jobs.push((i, config));
+        }
+        Ok(SweepAction::Queue(jobs))
+    }
+
+    /// Called by the launcher when the search of a combination ended. The search may
+    /// have written to a timestamped subfolder of the planned `output_directory`.
+    pub fn record(
+        &mut self,
+        index: usize,
+        result: &Result<String, String>,
+        output_directory: Option<&str>,
+        runtime: Duration,
+    ) {
+        let Some(combination) = self.combinations.get_mut(index) else {
+            return;
+        };
+        if let Some(dir) = output_directory {
+            combination.output_directory = dir.to_string();
+        }
+        combination.runtime = Some(runtime);
+        combination.status = match result {
+            Ok(_) => match results::read_psms(Path::new(&combination.output_directory)) {
+                Ok(psms) => Status::Done(Summary::new(&psms, Q_VALUE)),
+                Err(e) => Status::Failed(e),
+            },
+            Err(e) => Status::Failed(e.clone()),
+        };
+    }
+
+    pub fn cancel_remaining(&mut self) {
+        for combination in &mut self.combinations {
+            if matches!(combination.status, Status::Queued) {
+                combination.status = Status::Cancelled;
+            }
+        }
+    }
+
+    /// `generation` changes whenever `base` may have changed.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        base: &Config,
+        generation: u64,
+    ) -> Option<SweepAction> {
+        let mut action = None;
+        ui.label("Runs the current settings once for every combination of the values below, each into its own folder under the output location.");
+
+        let mut remove = None;
+        for (i, axis) in self.axes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(("sweep_field", i))
+                    .selected_text(if axis.field.is_empty() {
+                        "Pick a field"
+                    } else {
+                        axis.field.as_str()
+                    })
+                    .show_ui(ui, |ui| {
+                        for (field, description) in COMMON_FIELDS {
+                            ui.selectable_value(&mut axis.field, field.to_string(), *description);
+                        }
+                    });
+                ui.add(
+                    egui::TextEdit::singleline(&mut axis.field)
+                        .hint_text("database.enzyme.min_len")
+                        .desired_width(200.0),
+                );
+                ui.label("Values:");
+                ui.add(egui::TextEdit::singleline(&mut axis.values).hint_text("10, 20, 30"));
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.axes.remove(i);
+        }
+        if ui.button("Add Field").clicked() {
+            self.axes.push(Axis::default());
+        }
+
+        let planned = self.planned(base, generation);
+        let queued = self
+            .combinations
+            .iter()
+            .filter(|c| matches!(c.status, Status::Queued))
+            .count();
+        ui.horizontal(|ui| {
+            match &planned {
+                Ok(n) => {
+                    ui.label(format!("{} combinations", n));
+                }
+                Err(e) => {
+                    ui.weak(e);
+                }
+            }
+            let n = *planned.as_ref().unwrap_or(&0);
+            let label = if n > LARGE_SWEEP && !self.confirm_large {
+                "Queue Sweep..."
+            } else {
+                "Queue Sweep"
+            };
+            if ui
+                .add_enabled(n > 0 && queued == 0, egui::Button::new(label))
+                .clicked()
+            {
+                if n > LARGE_SWEEP && !self.confirm_large {
+                    self.confirm_large = true;
+                } else {
+                    self.confirm_large = false;
+                    match self.plan(base, Local::now()) {
+                        Ok(planned) => {
+                            self.error = None;
+                            action = Some(planned);
+                        }
+                        Err(e) => {
+                            self.combinations.clear();
+                            self.error = Some(format!("Could not plan the sweep: {}", e));
+                        }
+                    }
+                }
+            }
+            if queued > 0 && ui.button("Cancel Remaining").clicked() {
+                action = Some(SweepAction::CancelRemaining);
+            }
+        });
+        if self.confirm_large {
+            ui.colored_label(
+                egui::Color32::from_rgb(230, 160, 0),
+                "This queues a lot of searches, click again to confirm.",
+            );
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        if self.combinations.is_empty() {
+            return action;
+        }
+        egui::ScrollArea::vertical()
+            .id_salt("sweep_results")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("sweep_table").striped(true).show(ui, |ui| {
+                    for header in [
+                        "Combination",
+                        "Status",
+                        "Runtime",
+                        "PSMs",
+                        "Peptides",
+                        "Proteins",
+                    ] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for combination in &self.combinations {
+                        ui.label(&combination.label)
+                            .on_hover_text(&combination.output_directory);
+                        match &combination.status {
+                            Status::Done(summary) => {
+                                ui.label("Done");
+                                ui.label(format_runtime(combination.runtime));
+                                ui.label(summary.psms.to_string());
+                                ui.label(summary.peptides.to_string());
+                                ui.label(summary.proteins.to_string());
+                            }
+                            Status::Failed(e) => {
+                                ui.colored_label(egui::Color32::RED, "Failed")
+                                    .on_hover_text(e);
+                                ui.label(format_runtime(combination.runtime));
+                            }
+                            Status::Queued => {
+                                ui.label("Queued");
+                            }
+                            Status::Cancelled => {
+                                ui.weak("Cancelled");
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        ui.weak("Counts are target PSMs, peptides and protein groups at 1% FDR.");
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ToleranceConfig;
+
+    fn axis(field: &str, values: &str) -> Axis {
+        Axis {
+            field: field.to_string(),
+            values: values.to_string(),
+        }
+    }
+
+    #[test]
+    fn splits_values_outside_brackets_and_quotes() {
+        assert_eq!(split_values(" 1, 2 ,,3 "), ["1", "2", "3"]);
+        assert_eq!(
+            split_values(r#"[1, 2], {"a": 1, "b": 2}, "x, y", plain"#),
+            [r#"[1, 2]"#, r#"{"a": 1, "b": 2}"#, r#""x, y""#, "plain"]
+        );
+        assert!(split_values(" , ").is_empty());
+        assert_eq!(
+            parse_values(r#"7, true, "SageHyperScore", word"#),
+            [
+                serde_json::json!(7),
+                serde_json::json!(true),
+                serde_json::json!("SageHyperScore"),
+                serde_json::json!("word")
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_fields_by_path() {
+        let base = Config {
+            fragment_tol: ToleranceConfig::Da(-0.5, 0.5),
+            ..Default::default()
+        };
+
+        let config = set_field(&base, "database.enzyme.min_len", &serde_json::json!(9)).unwrap();
+        assert_eq!(config.database.enzyme.min_len, 9);
+        assert_eq!(config.database.enzyme.max_len, base.database.enzyme.max_len);
+
+        // A bare number keeps the unit of the tolerance
+        let config = set_field(&base, "precursor_tol", &serde_json::json!(20)).unwrap();
+        assert!(
+            matches!(config.precursor_tol, ToleranceConfig::Ppm(lo, hi) if lo == -20.0 && hi == 20.0)
+        );
+        let config = set_field(&base, "fragment_tol", &serde_json::json!(0.02)).unwrap();
+        assert!(
+            matches!(config.fragment_tol, ToleranceConfig::Da(lo, hi) if lo == -0.02 && hi == 0.02)
+        );
+        let config = set_field(
+            &base,
+            "fragment_tol",
+            &serde_json::json!({"ppm": [-10.0, 15.0]}),
+        )
+        .unwrap();
+        assert!(
+            matches!(config.fragment_tol, ToleranceConfig::Ppm(lo, hi) if lo == -10.0 && hi == 15.0)
+        );
+
+        assert!(
+            set_field(&base, "database.enzyme.nope", &serde_json::json!(1))
+                .unwrap_err()
+                .contains("Unknown config field")
+        );
+        assert!(set_field(&base, "min_peaks", &serde_json::json!("many"))
+            .unwrap_err()
+            .contains("Invalid value"));
+    }
+
+    #[test]
+    fn expands_the_cartesian_product() {
+        let base = Config::default();
+        let axes = [
+            axis("database.enzyme.missed_cleavages", "1, 2"),
+            axis("", "ignored"),
+            axis("deisotope", "true, false, true"),
+        ];
+        let combinations = expand(&base, &axes).unwrap();
+        let labels: Vec<&str> = combinations.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "missed_cleavages=1, deisotope=true",
+                "missed_cleavages=1, deisotope=false",
+                "missed_cleavages=1, deisotope=true",
+                "missed_cleavages=2, deisotope=true",
+                "missed_cleavages=2, deisotope=false",
+                "missed_cleavages=2, deisotope=true",
+            ]
+        );
+        let (_, last) = &combinations[5];
+        assert_eq!(last.database.enzyme.missed_cleavages, 2);
+        assert!(last.deisotope);
+
+        assert!(expand(&base, &[]).is_err());
+        assert!(expand(&base, &[axis("min_peaks", " ")])
+            .unwrap_err()
+            .contains("No values"));
+        assert!(expand(&base, &[axis("min_peaks", "10, x")]).is_err());
+    }
+
+    #[test]
+    fn records_results_where_the_search_wrote_them() {
+        let mut sweep = Sweep {
+            axes: vec![axis("min_peaks", "10, 20")],
+            ..Default::default()
+        };
+        let Ok(SweepAction::Queue(jobs)) = sweep.plan(&Config::default(), Local::now()) else {
+            panic!("sweep was not planned");
+        };
+        assert_eq!(jobs.len(), 2);
+        assert!(jobs[1].1.output_directory.ends_with("02_min_peaks-20"));
+
+        let moved = "/nonexistent/sweep/02_min_peaks-20/20260314-092653";
+        sweep.record(1, &Ok(String::new()), Some(moved), Duration::from_secs(3));
+        assert_eq!(sweep.combinations[1].output_directory, moved);
+        assert!(matches!(sweep.combinations[1].status, Status::Failed(_)));
+        assert!(matches!(sweep.combinations[0].status, Status::Queued));
+    }
+}
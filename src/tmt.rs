@@ -0,0 +1,386 @@
+/// The TMT tab: reporter ion intensities of the confident PSMs in `tmt.tsv`.
+use crate::compare;
+use crate::impurity::{self, ImpurityCorrection};
+use crate::plot::{self, Histogram, Marker};
+use crate::processing::{Processing, Source};
+use crate::quant::{self, QuantMatrix};
+use crate::stats;
+use crate::ChannelAnnotation;
+use eframe::egui;
+use rfd::FileDialog;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+const Q_VALUE: f64 = 0.01;
+/// Entries listed for the search box.
+const MAX_MATCHES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Peptide,
+    Protein,
+}
+
+/// Everything derived from the matrix that does not depend on UI state.
+struct Overview {
+    log2: Vec<(String, Vec<f64>)>,
+    missing: Vec<f64>,
+    // PSMs with at least one channel missing
+    any_missing: f64,
+    correlation: Vec<Vec<Option<f64>>>,
+    // log10 of the reporter sum of each PSM
+    summed: Vec<f64>,
+    peptides: Vec<String>,
+    proteins: Vec<String>,
+}
+
+impl Overview {
+    fn new(matrix: &QuantMatrix) -> Self {
+        let samples = 0..matrix.samples.len();
+        let any_missing = matrix
+            .rows
+            .iter()
+            .filter(|row| row.values.iter().any(Option::is_none))
+            .count();
+        let summed = matrix
+            .rows
+            .iter()
+            .map(|row| row.values.iter().flatten().sum::<f64>())
+            .filter(|sum| *sum > 0.0)
+            .map(f64::log10)
+            .collect();
+        let peptides: BTreeSet<&String> = matrix.rows.iter().map(|row| &row.peptide).collect();
+        let proteins: BTreeSet<&str> = matrix
+            .rows
+            .iter()
+            .flat_map(|row| row.proteins.split(';'))
+            .collect();
+
+        Self {
+            log2: samples
+                .clone()
+                .map(|s| (matrix.samples[s].clone(), matrix.log2_column(s)))
+                .collect(),
+            missing: samples
+                .clone()
+                .map(|s| matrix.missing_fraction(s))
+                .collect(),
+            any_missing: any_missing as f64 / matrix.rows.len().max(1) as f64,
+            correlation: samples
+                .clone()
+                .map(|a| samples.clone().map(|b| matrix.correlation(a, b)).collect())
+                .collect(),
+            summed,
+            peptides: peptides.into_iter().cloned().collect(),
+            proteins: proteins.into_iter().map(str::to_string).collect(),
+        }
+    }
+}
+
+/// Summed reporter values of every PSM of a peptide or protein, per channel.
+fn profile(matrix: &QuantMatrix, level: Level, key: &str) -> (usize, Vec<Option<f64>>) {
+    let mut psms = 0;
+    let mut profile = vec![None; matrix.samples.len()];
+    let rows = matrix.rows.iter().filter(|row| match level {
+        Level::Peptide => row.peptide == key,
+        Level::Protein => row.proteins.split(';').any(|protein| protein == key),
+    });
+    for row in rows {
+        psms += 1;
+        for (total, value) in profile.iter_mut().zip(&row.values) {
+            if let Some(value) = value {
+                *total = Some(total.unwrap_or(0.0) + value);
+            }
+        }
+    }
+    (psms, profile)
+}
+
+/// Whether the run reported signal to noise instead of intensities.
+fn uses_sn(dir: &Path) -> bool {
+    compare::settings(dir)
+        .and_then(|settings| settings.pointer("/quant/tmt_settings/sn")?.as_bool())
+        .unwrap_or(false)
+}
+
+pub struct TmtExplorer {
+    pub results_dir: String,
+    matrix: Option<Result<(QuantMatrix, Overview), String>>,
+    sn: bool,
+    level: Level,
+    query: String,
+    selected: Option<String>,
+    message: String,
+    corrected: bool,
+    impurity: ImpurityCorrection,
+    processing: Processing,
+}
+
+impl Default for TmtExplorer {
+    fn default() -> Self {
+        Self {
+            results_dir: String::new(),
+            matrix: None,
+            sn: false,
+            level: Level::Peptide,
+            query: String::new(),
+            selected: None,
+            message: String::new(),
+            corrected: false,
+            impurity: ImpurityCorrection::default(),
+            processing: Processing::default(),
+        }
+    }
+}
+
+fn load(dir: &Path, corrected: bool) -> Result<(QuantMatrix, Overview), String> {
+    let file_name = match corrected {
+        true => impurity::CORRECTED_FILE_NAME,
+        false => quant::TMT_FILE_NAME,
+    };
+    let matrix = quant::read_tmt(dir, file_name, Q_VALUE)?;
+    let overview = Overview::new(&matrix);
+    Ok((matrix, overview))
+}
+
+impl TmtExplorer {
+    /// `channels` is the annotation of the plex in the current config, if any.
+    pub fn update_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        default_dir: &str,
+        channels: &[ChannelAnnotation],
+    ) {
+        if self.results_dir.is_empty() {
+            self.results_dir = default_dir.to_string();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Results Folder:");
+            ui.text_edit_singleline(&mut self.results_dir);
+            if ui.button("Browse").clicked() {
+                if let Some(path) = FileDialog::new().pick_folder() {
+                    self.results_dir = path.display().to_string();
+                }
+            }
+            ui.checkbox(&mut self.corrected, "Impurity corrected");
+            if ui.button("Load tmt.tsv").clicked() {
+                let dir = Path::new(&self.results_dir);
+                self.matrix = Some(load(dir, self.corrected));
+                self.sn = uses_sn(dir);
+                self.selected = None;
+                self.message.clear();
+                self.processing.clear();
+            }
+        });
+
+        let (matrix, overview) = match &self.matrix {
+            Some(Ok(loaded)) => (&loaded.0, &loaded.1),
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+                return;
+            }
+            None => {
+                ui.label("Load the tmt.tsv sage writes when TMT quantification is enabled.");
+                return;
+            }
+        };
+        ui.label(format!(
+            "{} PSMs at 1% FDR with reporter ions across {} channels",
+            matrix.rows.len(),
+            matrix.samples.len()
+        ));
+        ui.checkbox(&mut self.sn, "Values are signal to noise")
+            .on_hover_text("Read from the run settings, sage reports S/N when 'sn' is on.");
+        let unit = if self.sn { "S/N" } else { "intensity" };
+        let names = &matrix.samples;
+
+        ui.collapsing("Channel Distributions", |ui| {
+            plot::boxplots(ui, &overview.log2, &format!("log2 {}", unit));
+        });
+
+        ui.collapsing("Missing Reporters", |ui| {
+            ui.label(format!(
+                "{:.1}% of PSMs miss at least one channel",
+                100.0 * overview.any_missing
+            ));
+            let missing: Vec<Option<f64>> =
+                overview.missing.iter().map(|m| Some(100.0 * m)).collect();
+            plot::bars(ui, names, &missing, "% missing");
+        });
+
+        ui.collapsing("Correlation", |ui| {
+            let correlation = &overview.correlation;
+            plot::heatmap(
+                ui,
+                names.len(),
+                names.len(),
+                Some(names),
+                names,
+                |r, c| match correlation[r][c] {
+                    Some(rho) => (
+                        plot::diverging(0.5 + rho / 2.0),
+                        format!("{} vs {}: r = {:.3}", names[r], names[c], rho),
+                    ),
+                    None => (egui::Color32::GRAY, "Not enough shared PSMs".to_string()),
+                },
+            );
+            ui.weak(format!(
+                "Pearson correlation of log2 {} over PSMs quantified in both channels.",
+                unit
+            ));
+        });
+
+        if self.sn {
+            ui.collapsing("Signal to Noise", |ui| {
+                egui::Grid::new("tmt_sn").striped(true).show(ui, |ui| {
+                    ui.strong("Channel");
+                    ui.strong("Median S/N");
+                    ui.end_row();
+                    for (name, log2) in &overview.log2 {
+                        let median = stats::median(log2).map(f64::exp2);
+                        ui.label(name);
+                        ui.label(median.map_or("-".to_string(), |m| format!("{:.1}", m)));
+                        ui.end_row();
+                    }
+                });
+                if let Some(median) = stats::median(&overview.summed) {
+                    Histogram::new(&overview.summed, 0.0, 5.0, 100).show(
+                        ui,
+                        "log10 summed S/N per PSM",
+                        &[Marker {
+                            x: median,
+                            color: egui::Color32::from_rgb(230, 160, 0),
+                            label: format!("median {:.0}", 10f64.powf(median)),
+                        }],
+                    );
+                }
+            });
+        }
+
+        ui.collapsing("Impurity Correction", |ui| {
+            let dir = Path::new(&self.results_dir);
+            self.impurity.update_section(ui, dir, names);
+            ui.weak(
+                "Load again with 'Impurity corrected' ticked to explore the corrected reporters.",
+            );
+        });
+
+        // Sample names from the channel annotation, matched to the tmt.tsv columns by
+        // channel label; columns without one keep the label
+        let annotation: Vec<Option<&ChannelAnnotation>> = names
+            .iter()
+            .map(|name| channels.iter().find(|c| c.channel == *name))
+            .collect();
+        let annotated = annotation.iter().all(Option::is_some);
+        let columns: Vec<String> = names
+            .iter()
+            .zip(&annotation)
+            .map(|(name, channel)| channel.map_or(name.as_str(), |c| c.name()).to_string())
+            .collect();
+
+        ui.collapsing("Wide Matrix Export", |ui| {
+            if !annotated {
+                ui.weak("Not every reporter column has a channel annotation, those are named by channel.");
+            }
+            ui.horizontal(|ui| {
+                for (level, id) in [(Level::Peptide, "peptide"), (Level::Protein, "protein")] {
+                    if ui.button(format!("Export {} Matrix", id)).clicked() {
+                        if let Some(path) = FileDialog::new()
+                            .add_filter("TSV", &["tsv"])
+                            .set_file_name(format!("tmt_{}s.tsv", id))
+                            .save_file()
+                        {
+                            let rows = match level {
+                                Level::Peptide => quant::rollup(matrix, |row| &row.peptide),
+                                Level::Protein => quant::rollup(matrix, |row| &row.proteins),
+                            };
+                            self.message = match quant::write_wide(&path, id, &columns, &rows) {
+                                Ok(_) => format!("Exported {} rows to {}", rows.len(), path.display()),
+                                Err(e) => format!("Error: {}", e),
+                            };
+                        }
+                    }
+                }
+            });
+            ui.label(&self.message);
+            ui.weak("Reporter values are summed over the PSMs of each peptide or protein group.");
+        });
+
+        ui.collapsing("Normalization and Imputation", |ui| {
+            let dir = Path::new(&self.results_dir);
+            let reference: Vec<usize> = (0..annotation.len())
+                .filter(|&i| annotation[i].is_some_and(|c| c.reference))
+                .collect();
+            self.processing.update_section(
+                ui,
+                &["Peptides", "Proteins"],
+                &reference,
+                dir,
+                |level| {
+                    let (name, id, rows) = match level {
+                        0 => (
+                            "tmt.peptides",
+                            "peptide",
+                            quant::rollup(matrix, |row| &row.peptide),
+                        ),
+                        _ => (
+                            "tmt.proteins",
+                            "protein",
+                            quant::rollup(matrix, |row| &row.proteins),
+                        ),
+                    };
+                    Ok(Source {
+                        name: name.to_string(),
+                        id,
+                        matrix: QuantMatrix::from_rows(columns.clone(), rows),
+                    })
+                },
+            );
+        });
+
+        ui.collapsing("Reporter Profiles", |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.level, Level::Peptide, "Peptide");
+                ui.radio_value(&mut self.level, Level::Protein, "Protein");
+                ui.label("Search:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("peptide sequence or protein accession"),
+                );
+            });
+            let keys = match self.level {
+                Level::Peptide => &overview.peptides,
+                Level::Protein => &overview.proteins,
+            };
+            let query = self.query.trim();
+            let matches: Vec<&String> = keys
+                .iter()
+                .filter(|key| key.contains(query))
+                .take(MAX_MATCHES)
+                .collect();
+            if matches.len() == MAX_MATCHES {
+                ui.weak(format!("Showing the first {} matches", MAX_MATCHES));
+            }
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            egui::ScrollArea::vertical()
+                .id_salt("tmt_profiles")
+                .max_height(200.0)
+                .show_rows(ui, row_height, matches.len(), |ui, range| {
+                    for key in &matches[range] {
+                        let selected = self.selected.as_ref() == Some(*key);
+                        if ui.selectable_label(selected, key.as_str()).clicked() {
+                            self.selected = Some(key.to_string());
+                        }
+                    }
+                });
+
+            if let Some(key) = &self.selected {
+                let (psms, values) = profile(matrix, self.level, key);
+                if psms > 0 {
+                    ui.strong(format!("{} ({} PSMs)", key, psms));
+                    plot::bars(ui, names, &values, &format!("summed {}", unit));
+                }
+            }
+        });
+    }
+}
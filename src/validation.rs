@@ -0,0 +1,615 @@
+/// Checks over a `Config` before it is handed to sage.
+///
+/// Errors block the launch, warnings are settings that are valid but likely
+/// not what was intended. Every issue is tied to a `Field`, so the sections can
+/// show it right next to the widget that needs fixing.
+use crate::{Config, QuantType, ToleranceConfig};
+use eframe::egui;
+use sage_core::modification::ModificationSpecificity;
+use sage_core::tmt::Isobaric;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Output,
+    Fasta,
+    Spectra,
+    PeptideLength,
+    CleaveAt,
+    RestrictChar,
+    PeptideMass,
+    IonKinds,
+    Decoys,
+    VariableMods,
+    PrecursorTol,
+    FragmentTol,
+    PrecursorCharge,
+    IsotopeErrors,
+    Peaks,
+    WideWindow,
+    Quant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub field: Field,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    issues: Vec<Issue>,
+}
+
+/// Reporter tag masses, to check the label is actually searched for.
+const TMT_MASS: f32 = 229.162_93;
+const TMTPRO_MASS: f32 = 304.207_15;
+
+/// How long a path check is trusted before the file system is asked again.
+const PATH_CHECK_AGE: Duration = Duration::from_secs(2);
+
+/// Residues sage accepts in `cleave_at` and `restrict`.
+const AMINO_ACIDS: &str = "ACDEFGHIKLMNPQRSTVWYUOBJXZ";
+
+impl Report {
+    fn error(&mut self, field: Field, message: impl Into<String>) {
+        self.issues.push(Issue {
+            field,
+            severity: Severity::Error,
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, field: Field, message: impl Into<String>) {
+        self.issues.push(Issue {
+            field,
+            severity: Severity::Warning,
+            message: message.into(),
+        });
+    }
+
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    /// Draw the issues of one field inline.
+    pub fn show(&self, ui: &mut egui::Ui, field: Field) {
+        for issue in self.issues.iter().filter(|i| i.field == field) {
+            show_issue(ui, issue);
+        }
+    }
+}
+
+pub fn show_issue(ui: &mut egui::Ui, issue: &Issue) {
+    match issue.severity {
+        Severity::Error => ui.colored_label(egui::Color32::RED, format!("⛔ {}", issue.message)),
+        Severity::Warning => ui.colored_label(
+            egui::Color32::from_rgb(230, 160, 0),
+            format!("⚠ {}", issue.message),
+        ),
+    };
+}
+
+/// Remembers which input paths exist, so checking the settings on every frame does
+/// not stat every input file each time. A fresh cache always asks the file system.
+#[derive(Debug, Default)]
+pub struct PathChecks {
+    /// (path, must be a file) -> (exists, when it was checked)
+    checked: HashMap<(PathBuf, bool), (bool, Instant)>,
+}
+
+impl PathChecks {
+    fn exists(&mut self, path: &Path, file: bool, now: Instant) -> bool {
+        let key = (path.to_path_buf(), file);
+        match self.checked.get(&key) {
+            Some((exists, at)) if now.duration_since(*at) < PATH_CHECK_AGE => *exists,
+            _ => {
+                let exists = match file {
+                    true => path.is_file(),
+                    false => path.exists(),
+                };
+                self.checked.insert(key, (exists, now));
+                exists
+            }
+        }
+    }
+
+    /// Forget paths that were not looked at recently, e.g. removed inputs.
+    fn prune(&mut self, now: Instant) {
+        self.checked
+            .retain(|_, (_, at)| now.duration_since(*at) < PATH_CHECK_AGE);
+    }
+}
+
+fn bounds(tol: &ToleranceConfig) -> (f32, f32, &'static str) {
+    match *tol {
+        ToleranceConfig::Ppm(lo, hi) => (lo, hi, "ppm"),
+        ToleranceConfig::Da(lo, hi) => (lo, hi, "Da"),
+    }
+}
+
+pub fn validate(config: &Config, paths: &mut PathChecks) -> Report {
+    let mut report = Report::default();
+    let now = Instant::now();
+    paths.prune(now);
+    check_files(config, &mut report, &mut |path, file| {
+        paths.exists(path, file, now)
+    });
+    check_database(config, &mut report);
+    check_search(config, &mut report);
+    check_quant(config, &mut report);
+    report
+}
+
+fn check_files(config: &Config, report: &mut Report, exists: &mut dyn FnMut(&Path, bool) -> bool) {
+    if config.output_directory.trim().is_empty() {
+        report.error(Field::Output, "No output location selected");
+    } else if crate::cloud::is_s3_uri(&config.output_directory)
+        && !config.output_template.contains("{time}")
+    {
+        report.warning(
+            Field::Output,
+            "Existing results in an S3 location are not detected and get overwritten; add {time} to the subfolder template to keep runs apart",
+        );
+    }
+
+    let fasta = &config.database.fasta;
+    if fasta.is_empty() {
+        report.error(Field::Fasta, "FASTA file is not selected");
+    } else if !crate::cloud::is_s3_uri(fasta) && !exists(Path::new(fasta), true) {
+        report.error(
+            Field::Fasta,
+            format!("FASTA file '{}' does not exist", fasta),
+        );
+    }
+
+    if config.mzml_paths.is_empty() && config.dotd_paths.is_empty() && config.cloud_paths.is_empty()
+    {
+        report.error(Field::Spectra, "mzML file is not selected");
+    }
+    for path in config.mzml_paths.iter().chain(config.dotd_paths.iter()) {
+        if !exists(path, false) {
+            report.error(
+                Field::Spectra,
+                format!("'{}' does not exist", path.display()),
+            );
+        }
+    }
+
+    let files: Vec<String> = config
+        .mzml_paths
+        .iter()
+        .chain(config.dotd_paths.iter())
+        .map(|path| path.display().to_string())
+        .chain(config.cloud_paths.iter().cloned())
+        .collect();
+    for (name, paths) in crate::design::name_collisions(&files) {
+        report.warning(
+            Field::Spectra,
+            format!(
+                "{} inputs are named '{}'; sage's outputs and the experimental design cannot tell them apart",
+                paths.len(),
+                name
+            ),
+        );
+    }
+}
+
+fn check_database(config: &Config, report: &mut Report) {
+    let db = &config.database;
+    let enzyme = &db.enzyme;
+
+    if enzyme.min_len > enzyme.max_len {
+        report.error(
+            Field::PeptideLength,
+            format!(
+                "Min length ({}) is larger than max length ({})",
+                enzyme.min_len, enzyme.max_len
+            ),
+        );
+    }
+
+    // '$' disables cleavage entirely, an empty string means non-specific digestion
+    if enzyme.cleave_at.is_empty() {
+        report.warning(
+            Field::CleaveAt,
+            "Empty 'Cleave At' means non-specific digestion, the database will be very large",
+        );
+    } else if enzyme.cleave_at != "$" {
+        let invalid: String = enzyme
+            .cleave_at
+            .chars()
+            .filter(|c| !AMINO_ACIDS.contains(*c))
+            .collect();
+        if !invalid.is_empty() {
+            report.error(
+                Field::CleaveAt,
+                format!(
+                    "'Cleave At' must be upper case residues (or '$' for no cleavage), found '{}'",
+                    invalid
+                ),
+            );
+        }
+    }
+
+    if enzyme.enable_restrict {
+        let mut chars = enzyme.restrict_char.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if AMINO_ACIDS.contains(c) => {}
+            (None, _) => report.error(
+                Field::RestrictChar,
+                "Restriction is enabled but no residue is set",
+            ),
+            _ => report.error(
+                Field::RestrictChar,
+                format!(
+                    "Restrict must be a single upper case residue, got '{}'",
+                    enzyme.restrict_char
+                ),
+            ),
+        }
+    }
+
+    if db.peptide_min_mass > db.peptide_max_mass {
+        report.error(
+            Field::PeptideMass,
+            format!(
+                "Min peptide mass ({}) is larger than max peptide mass ({})",
+                db.peptide_min_mass, db.peptide_max_mass
+            ),
+        );
+    }
+
+    if db.ion_kinds.ion_kinds.values().all(|enabled| !enabled) {
+        report.error(Field::IonKinds, "At least one ion kind must be selected");
+    }
+
+    if !db.generate_decoys {
+        let tag = db.decoy_tag.as_deref().unwrap_or("");
+        report.warning(
+            Field::Decoys,
+            format!(
+                "Decoys are not generated; FDR relies on decoys with the '{}' prefix already being in the FASTA",
+                tag
+            ),
+        );
+    }
+
+    if db.max_variable_mods == 0 && !db.variable_mods.variable_mods.static_mods.is_empty() {
+        report.warning(
+            Field::VariableMods,
+            "Variable modifications are set, but at most 0 are allowed per peptide",
+        );
+    }
+}
+
+fn check_search(config: &Config, report: &mut Report) {
+    for (field, name, tol) in [
+        (Field::PrecursorTol, "Precursor", &config.precursor_tol),
+        (Field::FragmentTol, "Fragment", &config.fragment_tol),
+    ] {
+        let (lo, hi, unit) = bounds(tol);
+        if lo > hi {
+            report.error(
+                field,
+                format!(
+                    "{} tolerance is inverted: lower bound {} {} is above upper bound {} {}",
+                    name, lo, unit, hi, unit
+                ),
+            );
+        } else if lo == hi {
+            report.error(field, format!("{} tolerance window is empty", name));
+        }
+    }
+
+    let (lo, hi) = config.precursor_charge;
+    if lo > hi {
+        report.error(
+            Field::PrecursorCharge,
+            format!("Min precursor charge ({}) is larger than max ({})", lo, hi),
+        );
+    } else if lo == 0 {
+        report.error(
+            Field::PrecursorCharge,
+            "Precursor charge must be at least 1",
+        );
+    }
+
+    let (lo, hi) = config.isotope_errors;
+    if lo > hi {
+        report.error(
+            Field::IsotopeErrors,
+            format!("Isotope errors are reversed: {} is above {}", lo, hi),
+        );
+    } else if crate::open_search::is_open(&config.precursor_tol) && (lo, hi) != (0, 0) {
+        report.warning(
+            Field::IsotopeErrors,
+            "Isotope errors are redundant in an open search (they appear as delta mass peaks) and slow it down",
+        );
+    }
+
+    if config.min_peaks > config.max_peaks {
+        report.error(
+            Field::Peaks,
+            format!(
+                "Min peaks ({}) is larger than max peaks ({})",
+                config.min_peaks, config.max_peaks
+            ),
+        );
+    }
+    if config.min_matched_peaks as u32 > config.min_peaks {
+        report.warning(
+            Field::Peaks,
+            format!(
+                "Min matched peaks ({}) is above min peaks ({}); spectra with few peaks can never match",
+                config.min_matched_peaks, config.min_peaks
+            ),
+        );
+    }
+
+    if config.wide_window {
+        let narrow = match config.precursor_tol {
+            ToleranceConfig::Ppm(lo, hi) => hi - lo <= 100.0,
+            ToleranceConfig::Da(lo, hi) => hi - lo <= 1.0,
+        };
+        if narrow {
+            report.warning(
+                Field::WideWindow,
+                "Wide window is on with a narrow precursor tolerance; this is meant for DIA/wide isolation data, where the isolation window replaces the precursor tolerance",
+            );
+        }
+    }
+}
+
+fn check_quant(config: &Config, report: &mut Report) {
+    if !config.quant_enabled {
+        return;
+    }
+    let QuantType::Tmt(isobar, tmt) = &config.quant else {
+        return;
+    };
+
+    if !(2..=3).contains(&tmt.level) {
+        report.error(
+            Field::Quant,
+            format!(
+                "TMT reporter ions can only be read from MS2 or MS3 scans, not MS{}",
+                tmt.level
+            ),
+        );
+    }
+
+    let expected = match isobar.selected {
+        Isobaric::Tmt16 | Isobaric::Tmt18 => TMTPRO_MASS,
+        _ => TMT_MASS,
+    };
+    let static_mods = &config.database.static_mods.static_mods;
+    let labeled = |is_site: &dyn Fn(&ModificationSpecificity) -> bool| {
+        static_mods
+            .iter()
+            .any(|(site, mass)| is_site(site) && (mass - expected).abs() < 0.01)
+    };
+    let lysine = labeled(&|site| *site == ModificationSpecificity::Residue(b'K'));
+    let n_term = labeled(&|site| site.to_string() == "^");
+    if !lysine || !n_term {
+        report.warning(
+            Field::Quant,
+            format!(
+                "TMT quantification without a {:.4} static modification on K and peptide N-termini (^)",
+                expected
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IsobarSelection, TmtConfig};
+
+    /// The (field, severity) of every issue, ignoring the input files.
+    fn issues(config: &Config) -> Vec<(Field, Severity)> {
+        let mut report = Report::default();
+        check_database(config, &mut report);
+        check_search(config, &mut report);
+        check_quant(config, &mut report);
+        report
+            .issues()
+            .iter()
+            .filter(|i| i.field != Field::Decoys)
+            .map(|i| (i.field, i.severity))
+            .collect()
+    }
+
+    fn has(config: &Config, field: Field, severity: Severity) -> bool {
+        issues(config).contains(&(field, severity))
+    }
+
+    #[test]
+    fn defaults_are_clean() {
+        assert!(issues(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn tolerance_bounds() {
+        let mut config = Config {
+            precursor_tol: ToleranceConfig::Ppm(10.0, -10.0),
+            fragment_tol: ToleranceConfig::Da(0.02, 0.02),
+            ..Default::default()
+        };
+        assert!(has(&config, Field::PrecursorTol, Severity::Error));
+        assert!(has(&config, Field::FragmentTol, Severity::Error));
+
+        config.precursor_tol = ToleranceConfig::Da(-500.0, 100.0);
+        config.fragment_tol = ToleranceConfig::Ppm(-20.0, 20.0);
+        assert!(!has(&config, Field::PrecursorTol, Severity::Error));
+        assert!(!has(&config, Field::FragmentTol, Severity::Error));
+    }
+
+    #[test]
+    fn charge_and_isotope_ranges() {
+        let mut config = Config {
+            precursor_charge: (4, 2),
+            isotope_errors: (3, -1),
+            ..Default::default()
+        };
+        assert!(has(&config, Field::PrecursorCharge, Severity::Error));
+        assert!(has(&config, Field::IsotopeErrors, Severity::Error));
+
+        config.precursor_charge = (0, 3);
+        config.isotope_errors = (-1, 3);
+        assert!(has(&config, Field::PrecursorCharge, Severity::Error));
+        assert!(!has(&config, Field::IsotopeErrors, Severity::Error));
+
+        // Open search: isotope errors are allowed, but pointless
+        config.precursor_charge = (2, 4);
+        config.precursor_tol = ToleranceConfig::Da(-150.0, 500.0);
+        assert_eq!(
+            issues(&config),
+            vec![(Field::IsotopeErrors, Severity::Warning)]
+        );
+        config.isotope_errors = (0, 0);
+        assert!(issues(&config).is_empty());
+    }
+
+    #[test]
+    fn peptide_length() {
+        let mut config = Config::default();
+        config.database.enzyme.min_len = 30;
+        config.database.enzyme.max_len = 7;
+        assert_eq!(
+            issues(&config),
+            vec![(Field::PeptideLength, Severity::Error)]
+        );
+        config.database.enzyme.max_len = 30;
+        assert!(issues(&config).is_empty());
+    }
+
+    #[test]
+    fn cleave_at_and_restrict() {
+        let mut config = Config::default();
+        let enzyme = &mut config.database.enzyme;
+        enzyme.cleave_at = "kR".to_string();
+        enzyme.enable_restrict = true;
+        enzyme.restrict_char = String::new();
+        assert!(has(&config, Field::CleaveAt, Severity::Error));
+        assert!(has(&config, Field::RestrictChar, Severity::Error));
+
+        let enzyme = &mut config.database.enzyme;
+        enzyme.cleave_at = String::new();
+        enzyme.restrict_char = "PP".to_string();
+        assert!(has(&config, Field::CleaveAt, Severity::Warning));
+        assert!(has(&config, Field::RestrictChar, Severity::Error));
+
+        let enzyme = &mut config.database.enzyme;
+        enzyme.cleave_at = "$".to_string();
+        enzyme.restrict_char = "P".to_string();
+        assert!(issues(&config).is_empty());
+    }
+
+    #[test]
+    fn tmt_needs_the_static_label() {
+        let mut config = Config {
+            quant_enabled: true,
+            ..Default::default()
+        };
+        let tmt = |selected, level| {
+            QuantType::Tmt(
+                IsobarSelection {
+                    selected,
+                    channels: Vec::new(),
+                },
+                TmtConfig {
+                    level,
+                    ..Default::default()
+                },
+            )
+        };
+        config.quant = tmt(Isobaric::Tmt16, 4);
+        assert_eq!(
+            issues(&config),
+            vec![
+                (Field::Quant, Severity::Error),
+                (Field::Quant, Severity::Warning)
+            ]
+        );
+
+        // TMT (not TMTpro) masses do not label a TMTpro plex
+        let static_mods = &mut config.database.static_mods.static_mods;
+        static_mods.insert(ModificationSpecificity::Residue(b'K'), TMT_MASS);
+        static_mods.insert("^".parse().unwrap(), TMT_MASS);
+        config.quant = tmt(Isobaric::Tmt16, 2);
+        assert_eq!(issues(&config), vec![(Field::Quant, Severity::Warning)]);
+
+        config.quant = tmt(Isobaric::Tmt10, 3);
+        assert!(issues(&config).is_empty());
+
+        config.quant_enabled = false;
+        config.quant = tmt(Isobaric::Tmt16, 4);
+        assert!(issues(&config).is_empty());
+    }
+
+    #[test]
+    fn wide_window_with_narrow_tolerance() {
+        let mut config = Config {
+            wide_window: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            issues(&config),
+            vec![(Field::WideWindow, Severity::Warning)]
+        );
+        config.precursor_tol = ToleranceConfig::Da(-2.0, 2.0);
+        assert!(issues(&config).is_empty());
+    }
+
+    #[test]
+    fn path_checks_are_cached() {
+        let dir = std::env::temp_dir().join(format!("sagegui_validation_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let fasta = dir.join("db.fasta");
+        let _ = std::fs::remove_file(&fasta);
+
+        let mut paths = PathChecks::default();
+        let start = Instant::now();
+        assert!(!paths.exists(&fasta, true, start));
+        std::fs::write(&fasta, ">sp|P1|A\nPEPTIDE\n").unwrap();
+        assert!(!paths.exists(&fasta, true, start + Duration::from_millis(500)));
+        assert!(paths.exists(&fasta, true, start + PATH_CHECK_AGE));
+        assert!(!paths.exists(&dir, true, start));
+        assert!(paths.exists(&dir, false, start));
+
+        let mut config = Config::default();
+        config.database.fasta = fasta.display().to_string();
+        config.mzml_paths = vec![dir.join("missing.mzML")];
+        let report = validate(&config, &mut PathChecks::default());
+        let spectra: Vec<_> = report
+            .issues()
+            .iter()
+            .filter(|i| i.field != Field::Decoys)
+            .map(|i| (i.field, i.severity))
+            .collect();
+        assert_eq!(spectra, vec![(Field::Spectra, Severity::Error)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,406 @@
+/// Watch-folder mode: pick up new acquisitions as the instrument writes them.
+///
+/// This polls instead of using filesystem notifications, since the folders we care
+/// about are usually network shares where those are unreliable. A file (or `.d`
+/// folder) is considered done once its size and modification time stop changing
+/// for `stable_secs`. Every search is appended to a log in the output root, which is
+/// read back on start so restarts don't search the same runs again. Failed runs are
+/// logged too, and only searched again once the run changes on disk (e.g. it was
+/// copied again), or after a restart.
+use eframe::egui;
+use rfd::FileDialog;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const LOG_FILE_NAME: &str = "sagegui_watch_log.tsv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    snapshot: Snapshot,
+    stable_since: Instant,
+}
+
+pub struct WatchFolder {
+    pub folder: String,
+    pub config_path: String,
+    pub output_root: String,
+    pub stable_secs: u64,
+    pub poll_secs: u64,
+    active: bool,
+    last_poll: Option<Instant>,
+    candidates: HashMap<PathBuf, Candidate>,
+    processed: HashSet<PathBuf>,
+    // Queued runs, with the snapshot they were queued at
+    pending: HashMap<PathBuf, Snapshot>,
+    // Runs whose search failed, until their snapshot changes
+    failed: HashMap<PathBuf, Snapshot>,
+    events: Vec<String>,
+}
+
+impl Default for WatchFolder {
+    fn default() -> Self {
+        Self {
+            folder: String::new(),
+            config_path: String::new(),
+            output_root: String::new(),
+            stable_secs: 60,
+            poll_secs: 10,
+            active: false,
+            last_poll: None,
+            candidates: HashMap::new(),
+            processed: HashSet::new(),
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+/// Actions requested from the watch panel, handled by the launcher.
+pub enum WatchAction {
+    Start,
+    Stop,
+}
+
+impl WatchFolder {
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn log_path(&self) -> PathBuf {
+        Path::new(&self.output_root).join(LOG_FILE_NAME)
+    }
+
+    pub fn start(&mut self) -> Result<(), String> {
+        if !Path::new(&self.folder).is_dir() {
+            return Err(format!("Watch folder '{}' does not exist", self.folder));
+        }
+        if self.output_root.is_empty() {
+            return Err("Output root for watched runs is not set".to_string());
+        }
+        fs::create_dir_all(&self.output_root).map_err(|e| e.to_string())?;
+
+        // Only successful searches are done, failed ones are retried
+        self.processed = match fs::read_to_string(self.log_path()) {
+            Ok(contents) => contents
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.split('\t').skip(1);
+                    let run = fields.next()?;
+                    (fields.next() == Some("ok")).then(|| PathBuf::from(run))
+                })
+                .collect(),
+            Err(_) => HashSet::new(),
+        };
+        self.candidates.clear();
+        self.pending.clear();
+        self.failed.clear();
+        self.last_poll = None;
+        self.active = true;
+        self.push_event(format!(
+            "Watching {} ({} runs already processed)",
+            self.folder,
+            self.processed.len()
+        ));
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.push_event("Stopped watching".to_string());
+    }
+
+    /// Scan the folder if the poll interval elapsed and return runs that just became
+    /// stable. Those are considered pending until `record` is called for them.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if !self.active {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_poll {
+            if now.duration_since(last) < Duration::from_secs(self.poll_secs) {
+                return Vec::new();
+            }
+        }
+        self.last_poll = Some(now);
+        self.scan(now)
+    }
+
+    fn scan(&mut self, now: Instant) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(&self.folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.push_event(format!("Cannot read {}: {}", self.folder, e));
+                return Vec::new();
+            }
+        };
+
+        let mut ready = Vec::new();
+        let mut seen = HashSet::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_spectrum_file(&path)
+                || self.processed.contains(&path)
+                || self.pending.contains_key(&path)
+            {
+                continue;
+            }
+            let snapshot = snapshot(&path);
+            if self.failed.get(&path) == Some(&snapshot) {
+                continue;
+            }
+            self.failed.remove(&path);
+            seen.insert(path.clone());
+
+            let candidate = self.candidates.entry(path.clone()).or_insert(Candidate {
+                snapshot,
+                stable_since: now,
+            });
+            if candidate.snapshot != snapshot {
+                // Still being written
+                candidate.snapshot = snapshot;
+                candidate.stable_since = now;
+            } else if snapshot.size > 0
+                && now.duration_since(candidate.stable_since)
+                    >= Duration::from_secs(self.stable_secs)
+            {
+                ready.push(path);
+            }
+        }
+        self.candidates.retain(|path, _| seen.contains(path));
+
+        for path in &ready {
+            if let Some(candidate) = self.candidates.remove(path) {
+                self.pending.insert(path.clone(), candidate.snapshot);
+            }
+            self.push_event(format!("Queued {}", path.display()));
+        }
+        ready
+    }
+
+    /// Per-run output folder, named after the run.
+    pub fn output_for(&self, run: &Path) -> PathBuf {
+        Path::new(&self.output_root).join(crate::output::stem(&run.to_string_lossy()))
+    }
+
+    /// Append a finished run to the log. Only successful runs count as processed, a
+    /// failed one is searched again once it changes on disk.
+    pub fn record(&mut self, run: &Path, result: &Result<String, String>) {
+        let queued = self.pending.remove(run);
+        match (result, queued) {
+            (Ok(_), _) => {
+                self.processed.insert(run.to_path_buf());
+            }
+            (Err(_), Some(snapshot)) => {
+                self.failed.insert(run.to_path_buf(), snapshot);
+            }
+            (Err(_), None) => {}
+        }
+
+        let status = match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => format!("error: {}", e.replace(['\t', '\n'], " ")),
+        };
+        self.push_event(format!("{}: {}", run.display(), status));
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let line = format!("{}\t{}\t{}\n", timestamp, run.display(), status);
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            self.push_event(format!("Could not write watch log: {}", e));
+        }
+    }
+
+    fn push_event(&mut self, event: String) {
+        self.events.push(event);
+        // Only keep the recent ones around for display
+        if self.events.len() > 200 {
+            self.events.drain(..100);
+        }
+    }
+
+    pub fn update_section(&mut self, ui: &mut egui::Ui) -> Option<WatchAction> {
+        let mut action = None;
+        ui.label(
+            "Searches new mzML/.d runs dropped into a folder, each into its own output subfolder.",
+        );
+
+        ui.add_enabled_ui(!self.active, |ui| {
+            path_row(ui, "Watch Folder:", &mut self.folder, || {
+                FileDialog::new().pick_folder()
+            });
+            path_row(ui, "Saved Config:", &mut self.config_path, || {
+                FileDialog::new().add_filter("JSON", &["json"]).pick_file()
+            });
+            path_row(ui, "Output Root:", &mut self.output_root, || {
+                FileDialog::new().pick_folder()
+            });
+            ui.add(
+                egui::Slider::new(&mut self.stable_secs, 5..=600)
+                    .text("Seconds without changes before searching"),
+            );
+            ui.add(egui::Slider::new(&mut self.poll_secs, 1..=120).text("Poll Interval (s)"));
+        });
+
+        ui.horizontal(|ui| {
+            if self.active {
+                ui.spinner();
+                ui.label(format!(
+                    "Watching ({} waiting to stabilize, {} queued, {} failed)",
+                    self.candidates.len(),
+                    self.pending.len(),
+                    self.failed.len()
+                ));
+                if ui.button("Stop Watching").clicked() {
+                    action = Some(WatchAction::Stop);
+                }
+            } else if ui.button("Start Watching").clicked() {
+                action = Some(WatchAction::Start);
+            }
+        });
+
+        if !self.events.is_empty() {
+            egui::ScrollArea::vertical()
+                .id_salt("watch_events")
+                .max_height(150.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for event in &self.events {
+                        ui.label(event);
+                    }
+                });
+        }
+        action
+    }
+}
+
+fn path_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut String,
+    pick: impl FnOnce() -> Option<PathBuf>,
+) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.text_edit_singleline(value);
+        if ui.button("Browse").clicked() {
+            if let Some(path) = pick() {
+                *value = path.display().to_string();
+            }
+        }
+    });
+}
+
+pub fn is_spectrum_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if path.is_dir() {
+        name.ends_with(".d")
+    } else {
+        name.ends_with(".mzml") || name.ends_with(".mzml.gz")
+    }
+}
+
+/// Total size and latest modification of a file, or of everything inside a `.d` folder.
+fn snapshot(path: &Path) -> Snapshot {
+    let mut snap = Snapshot {
+        size: 0,
+        modified: None,
+    };
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(current) = stack.pop() {
+        let Ok(meta) = fs::metadata(&current) else {
+            continue;
+        };
+        if meta.is_dir() {
+            if let Ok(entries) = fs::read_dir(&current) {
+                stack.extend(entries.flatten().map(|e| e.path()));
+            }
+        } else {
+            snap.size += meta.len();
+            let modified = meta.modified().ok();
+            if modified > snap.modified {
+                snap.modified = modified;
+            }
+        }
+    }
+    snap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn append(path: &Path, bytes: &[u8]) {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(bytes))
+            .unwrap();
+    }
+
+    #[test]
+    fn poll_waits_for_stable_runs_and_skips_done_ones() {
+        let root = std::env::temp_dir().join(format!("sagegui_watch_{}", std::process::id()));
+        let folder = root.join("incoming");
+        fs::create_dir_all(&folder).unwrap();
+        let a = folder.join("a.mzML");
+        let b = folder.join("b.mzML.gz");
+        append(&a, b"a");
+        append(&b, b"b");
+        append(&folder.join("notes.txt"), b"not a run");
+
+        let mut watch = WatchFolder {
+            folder: folder.display().to_string(),
+            output_root: root.join("out").display().to_string(),
+            stable_secs: 60,
+            ..Default::default()
+        };
+        watch.start().unwrap();
+        let t0 = Instant::now();
+        let at = |secs| t0 + Duration::from_secs(secs);
+
+        // New runs have to stay unchanged for `stable_secs` first
+        assert!(watch.scan(t0).is_empty());
+        append(&b, b"still writing");
+        assert_eq!(watch.scan(at(61)), vec![a.clone()]);
+        assert_eq!(watch.output_for(&a), root.join("out").join("a"));
+
+        watch.record(&a, &Ok("done".to_string()));
+        assert_eq!(watch.scan(at(200)), vec![b.clone()]);
+
+        // A failed run stays put until it changes
+        watch.record(&b, &Err("corrupt".to_string()));
+        assert!(watch.scan(at(300)).is_empty());
+        assert!(watch.scan(at(400)).is_empty());
+        append(&b, b"copied again");
+        assert!(watch.scan(at(401)).is_empty());
+        assert_eq!(watch.scan(at(462)), vec![b.clone()]);
+
+        // Only the successful run survives a restart
+        watch.record(&b, &Err("corrupt".to_string()));
+        watch.start().unwrap();
+        assert_eq!(watch.processed, HashSet::from([a]));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}